@@ -0,0 +1,82 @@
+// Precompute the RGB to spectrum coefficient table (see `src/spectral.rs`).
+// The table is written in OUT_DIR as little-endian f32: the brightness scale
+// (`RGB2SPEC_RES` values) followed by the coefficients (`3 * RGB2SPEC_RES³`
+// triplets).
+#[macro_use]
+extern crate lazy_static;
+
+use std::io::Write;
+
+#[allow(dead_code)]
+mod opt {
+    include!("src/rgb2spec_opt.rs");
+
+    fn smoothstep(x: f64) -> f64 {
+        x * x * (3.0 - 2.0 * x)
+    }
+
+    // Brightness values of the table (denser near black and near white)
+    fn table_scale(res: usize) -> Vec<f32> {
+        (0..res)
+            .map(|k| smoothstep(smoothstep(k as f64 / (res - 1) as f64)) as f32)
+            .collect()
+    }
+
+    // Optimize the coefficients of every cell of the table (see
+    // `RGBToSpectrumTable` for the layout). The cost grows as `res³`.
+    fn optimize_table(res: usize) -> Vec<[f32; 3]> {
+        let scale = table_scale(res);
+        let mut data = vec![[0.0f32; 3]; 3 * res * res * res];
+
+        let start = res / 5;
+        for l in 0..3 {
+            for j in 0..res {
+                let y = j as f64 / (res - 1) as f64;
+                for i in 0..res {
+                    let x = i as f64 / (res - 1) as f64;
+                    // Continuation over the brightness (from the start
+                    // position going up, then going down)
+                    let mut solve = |k: usize, coeffs: &mut [f64; 3]| {
+                        let b = scale[k] as f64;
+                        let mut rgb = [0.0; 3];
+                        rgb[l] = b;
+                        rgb[(l + 1) % 3] = x * b;
+                        rgb[(l + 2) % 3] = y * b;
+                        optimize(rgb, coeffs);
+                        data[((l * res + k) * res + j) * res + i] = denormalize(*coeffs);
+                    };
+                    let mut coeffs = [0.0; 3];
+                    for k in start..res {
+                        solve(k, &mut coeffs);
+                    }
+                    let mut coeffs = [0.0; 3];
+                    for k in (0..start).rev() {
+                        solve(k, &mut coeffs);
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    pub fn table(res: usize) -> (Vec<f32>, Vec<[f32; 3]>) {
+        (table_scale(res), optimize_table(res))
+    }
+}
+
+// Must match `RGB2SPEC_RES` inside `src/spectral.rs`
+const RGB2SPEC_RES: usize = 32;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/rgb2spec_opt.rs");
+
+    let (scale, data) = opt::table(RGB2SPEC_RES);
+    let mut bytes = Vec::with_capacity(4 * (scale.len() + 3 * data.len()));
+    for v in scale.iter().chain(data.iter().flatten()) {
+        bytes.write_all(&v.to_le_bytes()).unwrap();
+    }
+
+    let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("rgb2spec.bin");
+    std::fs::write(out, bytes).unwrap();
+}
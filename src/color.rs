@@ -0,0 +1,482 @@
+//! Color spaces and white point handling.
+//!
+//! Mitsuba treats all RGB values as linear sRGB. Renderers working in a
+//! different space (Rec.2020, ACEScg, XYZ) can convert the values with these
+//! utilities. Chromatic adaptation between white points uses the Bradford
+//! transform.
+use crate::scattering::ScatteringParameters;
+use crate::weave::WeaveParameter;
+use crate::Result;
+use crate::{
+    BSDFColor, Emitter, Scene, Shape, ShapeOption, Spectrum, Subsurface, Texture, BSDF, RGB,
+};
+
+type Matrix3 = [[f32; 3]; 3];
+
+/// Linear color spaces supported for conversion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+    /// Linear sRGB / Rec.709 primaries, D65 white point (Mitsuba default)
+    LinearSRGB,
+    /// Rec.2020 primaries, D65 white point
+    Rec2020,
+    /// ACES AP1 primaries, ACES white point (~D60)
+    ACEScg,
+    /// CIE 1931 XYZ (no chromatic adaptation)
+    XYZ,
+}
+
+// White points (XYZ with Y = 1)
+const WHITE_D65: [f32; 3] = [0.950_47, 1.0, 1.088_83];
+const WHITE_ACES: [f32; 3] = [0.952_646, 1.0, 1.008_825];
+
+#[rustfmt::skip]
+const SRGB_TO_XYZ: Matrix3 = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175],
+    [0.019_333_9, 0.119_192, 0.950_304_1],
+];
+#[rustfmt::skip]
+const REC2020_TO_XYZ: Matrix3 = [
+    [0.636_958, 0.144_616_9, 0.168_881],
+    [0.262_700_2, 0.677_998_1, 0.059_301_7],
+    [0.0, 0.028_072_7, 1.060_985_1],
+];
+#[rustfmt::skip]
+const ACESCG_TO_XYZ: Matrix3 = [
+    [ 0.662_454_2, 0.134_004_2, 0.156_187_7],
+    [ 0.272_228_7, 0.674_081_8, 0.053_689_5],
+    [-0.005_574_6, 0.004_060_7, 1.010_339_1],
+];
+#[rustfmt::skip]
+const BRADFORD: Matrix3 = [
+    [ 0.8951,  0.2664, -0.1614],
+    [-0.7502,  1.7135,  0.0367],
+    [ 0.0389, -0.0685,  1.0296],
+];
+const IDENTITY: Matrix3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+fn mul_vec(m: &Matrix3, v: [f32; 3]) -> [f32; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+fn mul(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut r = [[0.0; 3]; 3];
+    for (i, row) in r.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    r
+}
+
+fn inverse(m: &Matrix3) -> Matrix3 {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    let inv_det = 1.0 / det;
+    #[rustfmt::skip]
+    let r = [
+        [
+            (m[1][1] * m[2][2] - m[1][2] * m[2][1]) * inv_det,
+            (m[0][2] * m[2][1] - m[0][1] * m[2][2]) * inv_det,
+            (m[0][1] * m[1][2] - m[0][2] * m[1][1]) * inv_det,
+        ],
+        [
+            (m[1][2] * m[2][0] - m[1][0] * m[2][2]) * inv_det,
+            (m[0][0] * m[2][2] - m[0][2] * m[2][0]) * inv_det,
+            (m[0][2] * m[1][0] - m[0][0] * m[1][2]) * inv_det,
+        ],
+        [
+            (m[1][0] * m[2][1] - m[1][1] * m[2][0]) * inv_det,
+            (m[0][1] * m[2][0] - m[0][0] * m[2][1]) * inv_det,
+            (m[0][0] * m[1][1] - m[0][1] * m[1][0]) * inv_det,
+        ],
+    ];
+    r
+}
+
+/// Bradford chromatic adaptation matrix between two white points (XYZ)
+pub fn bradford_adaptation(src_white: [f32; 3], dst_white: [f32; 3]) -> [[f32; 3]; 3] {
+    let src = mul_vec(&BRADFORD, src_white);
+    let dst = mul_vec(&BRADFORD, dst_white);
+    let scale = [
+        [dst[0] / src[0], 0.0, 0.0],
+        [0.0, dst[1] / src[1], 0.0],
+        [0.0, 0.0, dst[2] / src[2]],
+    ];
+    mul(&inverse(&BRADFORD), &mul(&scale, &BRADFORD))
+}
+
+impl ColorSpace {
+    /// White point (XYZ with Y = 1). XYZ does not have a white point.
+    pub fn white_point(&self) -> Option<[f32; 3]> {
+        match self {
+            ColorSpace::LinearSRGB | ColorSpace::Rec2020 => Some(WHITE_D65),
+            ColorSpace::ACEScg => Some(WHITE_ACES),
+            ColorSpace::XYZ => None,
+        }
+    }
+
+    /// Matrix converting from this color space to XYZ
+    pub fn to_xyz_matrix(&self) -> [[f32; 3]; 3] {
+        match self {
+            ColorSpace::LinearSRGB => SRGB_TO_XYZ,
+            ColorSpace::Rec2020 => REC2020_TO_XYZ,
+            ColorSpace::ACEScg => ACESCG_TO_XYZ,
+            ColorSpace::XYZ => IDENTITY,
+        }
+    }
+
+    /// Matrix converting from this color space to `target`, including
+    /// chromatic adaptation if the white points differ
+    pub fn conversion_matrix(&self, target: ColorSpace) -> [[f32; 3]; 3] {
+        let adaptation = match (self.white_point(), target.white_point()) {
+            (Some(src), Some(dst)) if src != dst => bradford_adaptation(src, dst),
+            _ => IDENTITY,
+        };
+        let m = mul(&adaptation, &self.to_xyz_matrix());
+        mul(&inverse(&target.to_xyz_matrix()), &m)
+    }
+}
+
+impl RGB {
+    /// Convert the color from the color space `from` to `to`
+    pub fn convert(&self, from: ColorSpace, to: ColorSpace) -> RGB {
+        if from == to {
+            return *self;
+        }
+        let v = mul_vec(&from.conversion_matrix(to), [self.r, self.g, self.b]);
+        RGB {
+            r: v[0],
+            g: v[1],
+            b: v[2],
+        }
+    }
+}
+
+impl Spectrum {
+    /// Convert the RGB value from the color space `from` to `to`.
+    ///
+    /// True spectral values (`"λ:v, ..."`) are not tied to a color space
+    /// and are left untouched.
+    pub fn convert_color_space(&mut self, from: ColorSpace, to: ColorSpace) -> Result<()> {
+        if from == to || self.value.contains(':') {
+            return Ok(());
+        }
+        let rgb = self.clone().as_rgb()?.convert(from, to);
+        self.value = format!("{}, {}, {}", rgb.r, rgb.g, rgb.b);
+        Ok(())
+    }
+}
+
+// Visit every color stored inside the scene description (reflectance,
+// transmittance, albedo and emission, see `ParseOptions::color_space`)
+type SpectrumFn<'a> = dyn FnMut(&mut Spectrum) -> Result<()> + 'a;
+
+impl BSDFColor<Spectrum> {
+    fn for_each_spectrum(&mut self, f: &mut SpectrumFn) -> Result<()> {
+        match self {
+            BSDFColor::Texture(t) => t.for_each_spectrum(f),
+            BSDFColor::Constant(s) => f(s),
+        }
+    }
+}
+
+impl Texture {
+    pub(crate) fn for_each_spectrum(&mut self, f: &mut SpectrumFn) -> Result<()> {
+        match self {
+            Texture::Bitmap { .. } | Texture::VertexColors | Texture::Curvature { .. } => Ok(()),
+            Texture::Checkerboard { color0, color1, .. } => {
+                color0.for_each_spectrum(f)?;
+                color1.for_each_spectrum(f)
+            }
+            Texture::GridTexture { color0, color1, .. } => {
+                f(color0)?;
+                f(color1)
+            }
+            Texture::WireFrame {
+                interior_color,
+                edge_color,
+                ..
+            } => {
+                f(interior_color)?;
+                f(edge_color)
+            }
+            // The scale factor is a multiplier, not a color
+            Texture::Scale { texture, .. } => texture.for_each_spectrum(f),
+        }
+    }
+}
+
+impl BSDF {
+    pub(crate) fn for_each_spectrum(&mut self, f: &mut SpectrumFn) -> Result<()> {
+        match self {
+            // Float parameters (exponent, roughness) are not colors,
+            // even when given as a texture
+            BSDF::Phong {
+                specular_reflectance,
+                diffuse_reflectance,
+                ..
+            } => {
+                specular_reflectance.for_each_spectrum(f)?;
+                diffuse_reflectance.for_each_spectrum(f)
+            }
+            BSDF::Diffuse { reflectance } => reflectance.for_each_spectrum(f),
+            BSDF::Roughtdiffuse { reflectance, .. } => reflectance.for_each_spectrum(f),
+            // Complex index of refraction is not a color
+            BSDF::Conductor {
+                specular_reflectance,
+                ..
+            } => specular_reflectance.for_each_spectrum(f),
+            BSDF::Dielectric {
+                specular_reflectance,
+                specular_transmittance,
+                ..
+            } => {
+                specular_reflectance.for_each_spectrum(f)?;
+                specular_transmittance.for_each_spectrum(f)
+            }
+            BSDF::Plastic {
+                specular_reflectance,
+                diffuse_reflectance,
+                ..
+            }
+            | BSDF::Ward {
+                specular_reflectance,
+                diffuse_reflectance,
+                ..
+            } => {
+                specular_reflectance.for_each_spectrum(f)?;
+                diffuse_reflectance.for_each_spectrum(f)
+            }
+            BSDF::TwoSided { bsdf } => bsdf.for_each_spectrum(f),
+            BSDF::MixtureBSDF { bsdfs, .. } => {
+                for b in bsdfs {
+                    b.for_each_spectrum(f)?;
+                }
+                Ok(())
+            }
+            BSDF::Mask { opacity, bsdf } => {
+                opacity.for_each_spectrum(f)?;
+                bsdf.for_each_spectrum(f)
+            }
+            BSDF::DiffuseTransmitter { transmittance } | BSDF::Polarizer { transmittance, .. } => {
+                transmittance.for_each_spectrum(f)
+            }
+            BSDF::Null | BSDF::Measured { .. } => Ok(()),
+            BSDF::Blend { bsdfs, .. } => {
+                for b in bsdfs {
+                    b.for_each_spectrum(f)?;
+                }
+                Ok(())
+            }
+            // The absorption coefficient is not a color
+            BSDF::Coating {
+                specular_reflectance,
+                bsdf,
+                ..
+            } => {
+                specular_reflectance.for_each_spectrum(f)?;
+                bsdf.for_each_spectrum(f)
+            }
+            // Height and normal maps are not colors
+            BSDF::BumpMap { bsdf, .. } | BSDF::NormalMap { bsdf, .. } => bsdf.for_each_spectrum(f),
+            BSDF::HK { coefficients, .. } => coefficients.for_each_spectrum(f),
+            BSDF::Principled { base_color, .. } | BSDF::PrincipledThin { base_color, .. } => {
+                base_color.for_each_spectrum(f)
+            }
+            BSDF::Irawan {
+                pattern,
+                parameters,
+                ..
+            } => {
+                for p in parameters.values_mut() {
+                    if let WeaveParameter::Spectrum(s) = p {
+                        f(s)?;
+                    }
+                }
+                for y in &mut pattern.yarns {
+                    f(&mut y.kd)?;
+                    f(&mut y.ks)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Emitter {
+    pub(crate) fn for_each_spectrum(&mut self, f: &mut SpectrumFn) -> Result<()> {
+        match self {
+            Emitter::Area(e) => e.radiance.for_each_spectrum(f),
+            Emitter::Point { intensity, .. } | Emitter::PointNormal { intensity, .. } => {
+                f(intensity)
+            }
+            Emitter::Spot {
+                intensity, texture, ..
+            } => {
+                if let Some(t) = texture {
+                    t.for_each_spectrum(f)?;
+                }
+                f(intensity)
+            }
+            Emitter::Directional { irradiance, .. } => f(irradiance),
+            Emitter::Collimated { power, .. } => f(power),
+            Emitter::Constant { radiance, .. } => f(radiance),
+            Emitter::EnvMap { .. } => Ok(()),
+            Emitter::SunSky { sky, .. } => match sky {
+                Some(sky) => f(&mut sky.albedo),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+impl ScatteringParameters {
+    fn for_each_spectrum(&mut self, f: &mut SpectrumFn) -> Result<()> {
+        match self {
+            ScatteringParameters::Coefficients { .. } => Ok(()),
+            ScatteringParameters::Extinction { albedo, .. } => f(albedo),
+        }
+    }
+}
+
+impl Subsurface {
+    fn for_each_spectrum(&mut self, f: &mut SpectrumFn) -> Result<()> {
+        match self {
+            Subsurface::Dipole { coefficients, .. } => coefficients.for_each_spectrum(f),
+        }
+    }
+}
+
+impl ShapeOption {
+    fn for_each_spectrum(&mut self, f: &mut SpectrumFn) -> Result<()> {
+        if let Some(bsdf) = &mut self.bsdf {
+            bsdf.for_each_spectrum(f)?;
+        }
+        if let Some(emitter) = &mut self.emitter {
+            emitter.radiance.for_each_spectrum(f)?;
+        }
+        // Media (interior and exterior) only have coefficients
+        if let Some(subsurface) = &mut self.subsurface {
+            subsurface.for_each_spectrum(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Shape {
+    pub(crate) fn for_each_spectrum(&mut self, f: &mut SpectrumFn) -> Result<()> {
+        match self {
+            Shape::Serialized(s) => s.option.for_each_spectrum(f),
+            Shape::Obj { option, .. }
+            | Shape::Ply { option, .. }
+            | Shape::Cube { option }
+            | Shape::Sphere { option, .. }
+            | Shape::Cylinder { option, .. }
+            | Shape::Rectangle { option }
+            | Shape::Disk { option }
+            | Shape::Instance { option, .. } => option.for_each_spectrum(f),
+            Shape::ShapeGroup { shapes } => {
+                for s in shapes {
+                    s.for_each_spectrum(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Scene {
+    /// Convert the colors of the scene from linear sRGB (Mitsuba's
+    /// convention) to `target` (see `ParseOptions::color_space`)
+    pub fn convert_color_space(&mut self, target: ColorSpace) -> Result<()> {
+        let f = &mut |s: &mut Spectrum| s.convert_color_space(ColorSpace::LinearSRGB, target);
+        for b in self.bsdfs.values_mut() {
+            b.for_each_spectrum(f)?;
+        }
+        for t in self.textures.values_mut() {
+            t.for_each_spectrum(f)?;
+        }
+        for s in self
+            .shapes_id
+            .values_mut()
+            .chain(self.shapes_unamed.iter_mut())
+        {
+            s.for_each_spectrum(f)?;
+        }
+        for e in &mut self.emitters {
+            e.for_each_spectrum(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rgb(a: RGB, b: [f32; 3]) {
+        assert!(
+            (a.r - b[0]).abs() < 1e-3 && (a.g - b[1]).abs() < 1e-3 && (a.b - b[2]).abs() < 1e-3,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn white_is_preserved() {
+        let white = RGB {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+        };
+        assert_rgb(
+            white.convert(ColorSpace::LinearSRGB, ColorSpace::Rec2020),
+            [1.0, 1.0, 1.0],
+        );
+        assert_rgb(
+            white.convert(ColorSpace::LinearSRGB, ColorSpace::ACEScg),
+            [1.0, 1.0, 1.0],
+        );
+        assert_rgb(
+            white.convert(ColorSpace::LinearSRGB, ColorSpace::XYZ),
+            WHITE_D65,
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let c = RGB {
+            r: 0.8,
+            g: 0.3,
+            b: 0.1,
+        };
+        for cs in &[ColorSpace::Rec2020, ColorSpace::ACEScg, ColorSpace::XYZ] {
+            let back = c
+                .convert(ColorSpace::LinearSRGB, *cs)
+                .convert(*cs, ColorSpace::LinearSRGB);
+            assert_rgb(back, [0.8, 0.3, 0.1]);
+        }
+    }
+
+    #[test]
+    fn srgb_to_rec2020_red() {
+        // Pure sRGB red inside Rec.2020 (ITU-R BT.2087)
+        let red = RGB {
+            r: 1.0,
+            g: 0.0,
+            b: 0.0,
+        };
+        assert_rgb(
+            red.convert(ColorSpace::LinearSRGB, ColorSpace::Rec2020),
+            [0.6274, 0.0691, 0.0164],
+        );
+    }
+}
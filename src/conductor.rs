@@ -0,0 +1,208 @@
+//! Conductor database (complex index of refraction).
+//!
+//! Mitsuba's `conductor` and `roughconductor` BSDFs accept a `material` name
+//! whose eta/k are measured spectral data stored inside Mitsuba's
+//! distribution (`data/ior/<name>.eta.spd` and `data/ior/<name>.k.spd`).
+//! This module provides the values pre-integrated to RGB (always available)
+//! and a loader for the underlying spectral samples.
+use crate::{Error, Result, Spectrum};
+use std::path::Path;
+
+/// Complex index of refraction of a conductor
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConductorIOR {
+    pub eta: Spectrum,
+    pub k: Spectrum,
+}
+
+// Precomputed (eta, k) in RGB from Mitsuba
+#[rustfmt::skip]
+const CONDUCTORS: &[(&str, &str, &str)] = &[
+    ("a-C", "2.9296, 2.22909, 1.97023", "0.892218, 0.791926, 0.815701"),
+    ("Ag", "0.155518, 0.116786, 0.138372", "4.83241, 3.12322, 2.14934"),
+    ("AlAs_palik", "3.60456, 3.23388, 2.20695", "0.000688883, -0.000497156, 0.00753"),
+    ("AlAs", "3.60456, 3.23388, 2.20695", "0.000688883, -0.000497156, 0.00753"),
+    ("AlSb_palik", "-0.0339369, 4.14454, 4.6468", "-0.0330091, 0.10024, 1.29296"),
+    ("AlSb", "-0.0339369, 4.14454, 4.6468", "-0.0330091, 0.10024, 1.29296"),
+    ("Al", "1.66026, 0.881462, 0.521613", "9.22807, 6.27106, 4.84111"),
+    ("Au", "0.143552, 0.377438, 1.43825", "3.98397, 2.38495, 1.60434"),
+    ("Be_palik", "4.17079, 3.18615, 2.78579", "3.84741, 3.00959, 2.86869"),
+    ("Be", "4.17079, 3.18615, 2.78579", "3.84741, 3.00959, 2.86869"),
+    ("Cr", "4.48822, 2.90684, 1.66261", "5.21422, 4.2289, 3.75312"),
+    ("CsI_palik", "2.14445, 1.70236, 1.66293", "0, 0, 0"),
+    ("CsI", "2.14445, 1.70236, 1.66293", "0, 0, 0"),
+    ("Cu2O_palik", "3.54545, 2.94339, 2.71336", "0.120343, 0.205637, 0.637298"),
+    ("Cu2O", "3.54545, 2.94339, 2.71336", "0.120343, 0.205637, 0.637298"),
+    ("CuO_palik", "3.25286, 2.44938, 2.2043", "0.520388, 0.569286, 0.726005"),
+    ("CuO", "3.25286, 2.44938, 2.2043", "0.520388, 0.569286, 0.726005"),
+    ("Cu_palik", "0.241275, 0.903804, 1.10182", "3.95032, 2.46606, 2.1349"),
+    ("Cu", "0.208084, 0.919438, 1.10263", "3.92329, 2.45611, 2.14264"),
+    ("d-C_palik", "2.71105, 2.31816, 2.23336", "0, 0, 0"),
+    ("d-C", "2.71105, 2.31816, 2.23336", "0, 0, 0"),
+    ("Hg_palik", "2.42652, 1.45642, 0.914459", "6.34803, 4.39248, 3.42748"),
+    ("HgTe_palik", "4.77919, 3.23163, 2.66017", "1.63198, 1.58015, 1.72981"),
+    ("HgTe", "4.77919, 3.23163, 2.66017", "1.63198, 1.58015, 1.72981"),
+    ("Hg", "2.42652, 1.45642, 0.914459", "6.34803, 4.39248, 3.42748"),
+    ("Ir_palik", "3.08243, 2.08491, 1.62028", "5.59771, 4.06635, 3.27186"),
+    ("Ir", "3.08243, 2.08491, 1.62028", "5.59771, 4.06635, 3.27186"),
+    ("K_palik", "0.0621071, 0.0466027, 0.0384292", "2.12697, 1.3591, 0.9177"),
+    ("K", "0.0621071, 0.0466027, 0.0384292", "2.12697, 1.3591, 0.9177"),
+    ("Li_palik", "0.269451, 0.200441, 0.223339", "3.54131, 2.3517, 1.68708"),
+    ("Li", "0.269451, 0.200441, 0.223339", "3.54131, 2.3517, 1.68708"),
+    ("MgO_palik", "2.08988, 1.65047, 1.5956", "4.38534e-12, -3.64587e-12, 2.53198e-11"),
+    ("MgO", "2.08988, 1.65047, 1.5956", "4.38534e-12, -3.64587e-12, 2.53198e-11"),
+    ("Mo_palik", "4.49907, 3.51237, 2.78469", "4.12556, 3.4205, 3.15286"),
+    ("Mo", "4.49907, 3.51237, 2.78469", "4.12556, 3.4205, 3.15286"),
+    ("Na_palik", "0.0607451, 0.0557257, 0.061748", "3.20798, 2.12669, 1.58797"),
+    ("Nb_palik", "3.40884, 2.78681, 2.39788", "3.44282, 2.73855, 2.57476"),
+    ("Nb", "3.40884, 2.78681, 2.39788", "3.44282, 2.73855, 2.57476"),
+    ("Ni_palik", "2.36454, 1.66584, 1.46819", "4.48772, 3.05528, 2.34846"),
+    ("Rh_palik", "2.59089, 1.86185, 1.55039", "6.79501, 4.70811, 3.9766"),
+    ("Rh", "2.59089, 1.86185, 1.55039", "6.79501, 4.70811, 3.9766"),
+    ("Se-e_palik", "5.56086, 4.22302, 4.0475", "0.761602, 1.0705, 1.5996"),
+    ("Se-e", "5.56086, 4.22302, 4.0475", "0.761602, 1.0705, 1.5996"),
+    ("Se_palik", "3.9737, 2.88842, 2.82447", "0.631111, 0.6311, 0.54084"),
+    ("Se", "3.9737, 2.88842, 2.82447", "0.631111, 0.6311, 0.54084"),
+    ("SiC_palik", "3.17069, 2.52702, 2.47947", "1.54029e-06, -1.49586e-06, 1.47636e-05"),
+    ("SiC", "3.17069, 2.52702, 2.47947", "1.54029e-06, -1.49586e-06, 1.47636e-05"),
+    ("SnTe_palik", "4.538, 1.9804, 1.2824", "0, 0, 0"),
+    ("SnTe", "4.538, 1.9804, 1.2824", "0, 0, 0"),
+    ("Ta_palik", "2.05979, 2.38107, 2.62559", "2.43996, 1.74619, 1.94588"),
+    ("Ta", "2.05979, 2.38107, 2.62559", "2.43996, 1.74619, 1.94588"),
+    ("Te-e_palik", "7.48425, 4.31476, 2.37063", "5.59077, 4.93514, 4.00092"),
+    ("Te-e", "7.48425, 4.31476, 2.37063", "5.59077, 4.93514, 4.00092"),
+    ("Te_palik", "7.36846, 4.4946, 2.63798", "3.26738, 3.51453, 3.2917"),
+    ("Te", "7.36846, 4.4946, 2.63798", "3.26738, 3.51453, 3.2917"),
+    ("ThF4_palik", "1.83057, 1.44222, 1.38804", "0, 0, 0"),
+    ("ThF4", "1.83057, 1.44222, 1.38804", "0, 0, 0"),
+    ("TiC_palik", "3.82591, 2.83346, 2.59154", "3.18532, 2.41109, 2.1752"),
+    ("TiC", "3.82591, 2.83346, 2.59154", "3.18532, 2.41109, 2.1752"),
+    ("TiN_palik", "1.70939, 1.20202, 1.35264", "3.02375, 2.10671, 1.1688"),
+    ("TiN", "1.70939, 1.20202, 1.35264", "3.02375, 2.10671, 1.1688"),
+    ("TiO2-e_palik", "3.10648, 2.51369, 2.585", "6.15808e-05, -5.38042e-05, 0.000381368"),
+    ("TiO2-e", "3.10648, 2.51369, 2.585", "6.15808e-05, -5.38042e-05, 0.000381368"),
+    ("TiO2_palik", "3.45676, 2.80243, 2.90704", "0.000130815, -0.000114216, 0.000809353"),
+    ("TiO2", "3.45676, 2.80243, 2.90704", "0.000130815, -0.000114216, 0.000809353"),
+    ("VC_palik", "3.67232, 2.77627, 2.53826", "2.97645, 2.25387, 1.97359"),
+    ("VC", "3.67232, 2.77627, 2.53826", "2.97645, 2.25387, 1.97359"),
+    ("VN_palik", "2.87842, 2.14604, 1.94788", "2.83558, 2.13656, 1.64581"),
+    ("VN", "2.87842, 2.14604, 1.94788", "2.83558, 2.13656, 1.64581"),
+    ("V_palik", "4.27582, 3.50453, 2.76487", "3.50165, 2.89143, 3.10752"),
+    ("W", "4.37464, 3.29988, 2.99998", "3.49872, 2.60604, 2.27449"),
+    ("none", "0.0, 0.0, 0.0", "1.0, 1.0, 1.0"),
+];
+
+// Some materials are only available as Palik measurements
+const ALIASES: &[(&str, &str)] = &[("Na", "Na_palik"), ("Ni", "Ni_palik"), ("V", "V_palik")];
+
+fn resolve(name: &str) -> Result<&'static str> {
+    if let Some((n, _, _)) = CONDUCTORS.iter().find(|(n, _, _)| *n == name) {
+        return Ok(n);
+    }
+    match ALIASES.iter().find(|(a, _)| *a == name) {
+        Some((_, n)) => Ok(n),
+        None => Err(Error::UnknownMaterial(
+            name.to_string(),
+            conductor_names().join(", "),
+        )),
+    }
+}
+
+/// Names of all the conductors known by Mitsuba (`none` included)
+pub fn conductor_names() -> Vec<&'static str> {
+    CONDUCTORS.iter().map(|(n, _, _)| *n).collect()
+}
+
+/// Lookup the RGB eta/k of a named conductor.
+///
+/// `Ni`, `Na` and `V` are accepted as aliases of their `_palik` variant.
+pub fn lookup_conductor(name: &str) -> Result<ConductorIOR> {
+    let name = resolve(name)?;
+    let (_, eta, k) = CONDUCTORS.iter().find(|(n, _, _)| *n == name).unwrap();
+    Ok(ConductorIOR {
+        eta: Spectrum::from_rgb(eta.to_string()),
+        k: Spectrum::from_rgb(k.to_string()),
+    })
+}
+
+/// Read a Mitsuba `.spd` file (`lambda value` per line, `#` for comments).
+///
+/// The result uses the spectral syntax of Mitsuba (`"λ:v, λ:v, ..."`)
+/// so it can be converted with `Spectrum::to_spectral`.
+pub fn read_spd(filename: &Path) -> Result<Spectrum> {
+    let content = std::fs::read_to_string(filename).map_err(|e| Error::Other(Box::new(e)))?;
+    let mut entries = vec![];
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values = line
+            .split_whitespace()
+            .map(|v| v.parse::<f32>())
+            .collect::<std::result::Result<Vec<_>, _>>();
+        match values.as_ref().map(|v| &v[..]) {
+            Ok([lambda, value]) => entries.push(format!("{}:{}", lambda, value)),
+            _ => return Err(Error::RGB("Wrong SPD entry", line.to_string())),
+        }
+    }
+    Ok(Spectrum {
+        value: entries.join(", "),
+    })
+}
+
+/// Load the measured spectral eta/k of a named conductor from Mitsuba's
+/// `data/ior` directory (`ior_dir`).
+///
+/// `none` (perfect mirror) does not have data files and
+/// returns constant spectra.
+pub fn lookup_conductor_spectral(name: &str, ior_dir: &Path) -> Result<ConductorIOR> {
+    let name = resolve(name)?;
+    if name == "none" {
+        return lookup_conductor(name);
+    }
+    Ok(ConductorIOR {
+        eta: read_spd(&ior_dir.join(format!("{}.eta.spd", name)))?,
+        k: read_spd(&ior_dir.join(format!("{}.k.spd", name)))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_conductor() {
+        let err = lookup_conductor("Unobtainium").unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("Unobtainium"));
+        assert!(msg.contains("Cu"));
+    }
+
+    #[test]
+    fn palik_alias() {
+        assert_eq!(
+            lookup_conductor("Ni").unwrap(),
+            lookup_conductor("Ni_palik").unwrap()
+        );
+    }
+
+    #[test]
+    fn spectral_conductor() {
+        let dir = std::env::temp_dir().join("mitsuba_rs_conductor");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("Au.eta.spd"),
+            "# Gold\n400 1.5\n500 1.0\n600 0.25\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("Au.k.spd"), "400 1.9\n500 1.8\n600 2.9\n").unwrap();
+        let ior = lookup_conductor_spectral("Au", &dir).unwrap();
+        assert_eq!(ior.eta.value, "400:1.5, 500:1, 600:0.25");
+        let k = ior
+            .k
+            .to_spectral(crate::spectral::SpectrumKind::Unbounded)
+            .unwrap();
+        assert!((k.eval(550.0) - 2.35).abs() < 1e-5);
+    }
+}
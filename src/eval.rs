@@ -0,0 +1,1727 @@
+//! Reference evaluation of the BSDFs, following Mitsuba 0.6 (RGB).
+//!
+//! Directions are expressed in the local shading frame (normal along +z)
+//! and point away from the surface. As in Mitsuba, `wi` is the direction
+//! toward the sensor and the returned values include the foreshortening
+//! term `|cos(wo)|`. Refraction follows the radiance convention (the
+//! transmitted value is scaled by the squared relative IOR).
+//!
+//! Parameters need to be constant: textured parameters cannot be evaluated
+//! without a surface position and produce an error. `BSDFModel` parses the
+//! parameters once when the same BSDF is evaluated many times.
+//!
+//! `roughplastic` is not supported: Mitsuba weights its diffuse term with
+//! precomputed rough transmittance tables, which are not available here.
+use crate::{
+    Alpha, BSDFColorFloat, BSDFColorSpectrum, Distribution, Error, MicrofacetType, Result,
+    WardVariant, BSDF, RGB,
+};
+use cgmath::{InnerSpace, Point2, Vector3};
+use std::f32::consts::PI;
+
+const INV_PI: f32 = 1.0 / PI;
+const INV_TWOPI: f32 = 0.5 / PI;
+
+/// Outgoing direction generated by `BSDF::sample`
+#[derive(Debug, Clone)]
+pub struct BSDFSample {
+    pub wo: Vector3<f32>,
+    /// Value divided by the sampling density (`eval / pdf`)
+    pub weight: RGB,
+    /// Solid angle density (or discrete probability when `delta` is set)
+    pub pdf: f32,
+    /// The direction comes from a Dirac delta (e.g., smooth reflection)
+    pub delta: bool,
+}
+
+fn rgb(v: f32) -> RGB {
+    RGB { r: v, g: v, b: v }
+}
+fn add(a: RGB, b: RGB) -> RGB {
+    RGB {
+        r: a.r + b.r,
+        g: a.g + b.g,
+        b: a.b + b.b,
+    }
+}
+fn mul(a: RGB, b: RGB) -> RGB {
+    RGB {
+        r: a.r * b.r,
+        g: a.g * b.g,
+        b: a.b * b.b,
+    }
+}
+fn scale(a: RGB, s: f32) -> RGB {
+    RGB {
+        r: a.r * s,
+        g: a.g * s,
+        b: a.b * s,
+    }
+}
+fn luminance(a: RGB) -> f32 {
+    a.r * 0.212_671 + a.g * 0.715_16 + a.b * 0.072_169
+}
+fn average(a: RGB) -> f32 {
+    (a.r + a.g + a.b) / 3.0
+}
+
+fn constant_spectrum(c: &BSDFColorSpectrum, name: &str) -> Result<RGB> {
+    match c {
+        BSDFColorSpectrum::Constant(v) => v.clone().as_rgb(),
+        BSDFColorSpectrum::Texture(_) => Err(textured(name)),
+    }
+}
+fn constant_f32(c: &BSDFColorFloat, name: &str) -> Result<f32> {
+    match c {
+        BSDFColorFloat::Constant(v) => Ok(*v),
+        BSDFColorFloat::Texture(_) => Err(textured(name)),
+    }
+}
+fn textured(name: &str) -> Error {
+    Error::Unsupported(format!("evaluation of the textured parameter {}", name))
+}
+
+fn reflect(wi: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(-wi.x, -wi.y, wi.z)
+}
+fn reflect_m(wi: Vector3<f32>, m: Vector3<f32>) -> Vector3<f32> {
+    m * (2.0 * wi.dot(m)) - wi
+}
+fn refract_m(wi: Vector3<f32>, m: Vector3<f32>, eta: f32, cos_theta_t: f32) -> Vector3<f32> {
+    let eta = if cos_theta_t < 0.0 { 1.0 / eta } else { eta };
+    m * (wi.dot(m) * eta + cos_theta_t) - wi * eta
+}
+
+fn square_to_cosine_hemisphere(u: Point2<f32>) -> Vector3<f32> {
+    let r = u.x.sqrt();
+    let phi = 2.0 * PI * u.y;
+    Vector3::new(r * phi.cos(), r * phi.sin(), (1.0 - u.x).max(0.0).sqrt())
+}
+
+// Orthonormal basis around n (Duff et al. 2017)
+fn to_world(n: Vector3<f32>, v: Vector3<f32>) -> Vector3<f32> {
+    let sign = 1.0_f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    let s = Vector3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let t = Vector3::new(b, sign + n.y * n.y * a, -n.y);
+    s * v.x + t * v.y + n * v.z
+}
+
+/// Fresnel reflectance of a dielectric interface. Returns the reflectance
+/// and the (signed) cosine of the refracted direction.
+fn fresnel_dielectric_ext(cos_theta_i: f32, eta: f32) -> (f32, f32) {
+    if eta == 1.0 {
+        return (0.0, -cos_theta_i);
+    }
+    let scale = if cos_theta_i > 0.0 { 1.0 / eta } else { eta };
+    let cos_theta_t_sqr = 1.0 - (1.0 - cos_theta_i * cos_theta_i) * (scale * scale);
+    // Total internal reflection
+    if cos_theta_t_sqr <= 0.0 {
+        return (1.0, 0.0);
+    }
+    let cti = cos_theta_i.abs();
+    let ctt = cos_theta_t_sqr.sqrt();
+    let rs = (cti - eta * ctt) / (cti + eta * ctt);
+    let rp = (eta * cti - ctt) / (eta * cti + ctt);
+    let cos_theta_t = if cos_theta_i > 0.0 { -ctt } else { ctt };
+    (0.5 * (rs * rs + rp * rp), cos_theta_t)
+}
+
+fn fresnel_conductor_exact(cos_theta_i: f32, eta: f32, k: f32) -> f32 {
+    let cti2 = cos_theta_i * cos_theta_i;
+    let sti2 = 1.0 - cti2;
+    let sti4 = sti2 * sti2;
+    let temp1 = eta * eta - k * k - sti2;
+    let a2pb2 = (temp1 * temp1 + 4.0 * k * k * eta * eta).max(0.0).sqrt();
+    let a = (0.5 * (a2pb2 + temp1)).max(0.0).sqrt();
+    let term1 = a2pb2 + cti2;
+    let term2 = 2.0 * a * cos_theta_i;
+    let rs2 = (term1 - term2) / (term1 + term2);
+    let term3 = a2pb2 * cti2 + sti4;
+    let term4 = term2 * sti2;
+    let rp2 = rs2 * (term3 - term4) / (term3 + term4);
+    0.5 * (rp2 + rs2)
+}
+
+fn fresnel_conductor(cos_theta_i: f32, eta: RGB, k: RGB) -> RGB {
+    RGB {
+        r: fresnel_conductor_exact(cos_theta_i, eta.r, k.r),
+        g: fresnel_conductor_exact(cos_theta_i, eta.g, k.g),
+        b: fresnel_conductor_exact(cos_theta_i, eta.b, k.b),
+    }
+}
+
+/// Hemispherical average of the dielectric Fresnel reflectance (fits)
+fn fresnel_diffuse_reflectance(eta: f32) -> f32 {
+    if eta < 1.0 {
+        // Egan and Hilgeman (1973)
+        -1.4399 * (eta * eta) + 0.7099 * eta + 0.6681 + 0.0636 / eta
+    } else {
+        // d'Eon and Irving (2011)
+        let inv_eta = 1.0 / eta;
+        let inv_eta2 = inv_eta * inv_eta;
+        let inv_eta3 = inv_eta2 * inv_eta;
+        let inv_eta4 = inv_eta3 * inv_eta;
+        let inv_eta5 = inv_eta4 * inv_eta;
+        0.919_317 - 3.4793 * inv_eta + 6.753_35 * inv_eta2 - 7.809_89 * inv_eta3
+            + 4.985_54 * inv_eta4
+            - 1.368_81 * inv_eta5
+    }
+}
+
+fn erf(x: f32) -> f32 {
+    // Numerical Recipes (erfc Chebyshev fit, relative error < 1.2e-7)
+    let z = f64::from(x.abs());
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let erfc = t * poly.exp();
+    (1.0 - erfc).copysign(f64::from(x)) as f32
+}
+
+fn erfinv(x: f32) -> f32 {
+    // Giles (2010), single precision
+    let mut w = -((1.0 - x) * (1.0 + x)).ln();
+    let p = if w < 5.0 {
+        w -= 2.5;
+        let mut p = 2.810_226_4e-8;
+        p = 3.432_739_4e-7 + p * w;
+        p = -3.523_387_7e-6 + p * w;
+        p = -4.391_506_5e-6 + p * w;
+        p = 0.000_218_580_87 + p * w;
+        p = -0.001_253_725 + p * w;
+        p = -0.004_177_681_6 + p * w;
+        p = 0.246_640_73 + p * w;
+        1.501_409_4 + p * w
+    } else {
+        w = w.sqrt() - 3.0;
+        let mut p = -0.000_200_214_26;
+        p = 0.000_100_950_56 + p * w;
+        p = 0.001_349_343_2 + p * w;
+        p = -0.003_673_428_4 + p * w;
+        p = 0.005_739_507_7 + p * w;
+        p = -0.007_622_461 + p * w;
+        p = 0.009_438_870_5 + p * w;
+        p = 1.001_674 + p * w;
+        2.832_976_8 + p * w
+    };
+    p * x
+}
+
+// Microfacet distribution (Mitsuba's MicrofacetDistribution)
+#[derive(Debug, Clone)]
+struct Microfacet {
+    kind: MicrofacetType,
+    alpha_u: f32,
+    alpha_v: f32,
+    exponent_u: f32,
+    exponent_v: f32,
+    sample_visible: bool,
+}
+
+impl Microfacet {
+    fn new(d: &Distribution) -> Result<Self> {
+        let (alpha_u, alpha_v) = match &d.alpha {
+            Alpha::Isotropic(a) => {
+                let a = constant_f32(a, "alpha")?;
+                (a, a)
+            }
+            Alpha::Anisotropic { u, v } => (constant_f32(u, "alphaU")?, constant_f32(v, "alphaV")?),
+        };
+        // Avoid numerical issues with very smooth surfaces
+        let alpha_u = alpha_u.max(1e-4);
+        let alpha_v = alpha_v.max(1e-4);
+        let is_phong = matches!(
+            d.distribution,
+            MicrofacetType::Phong | MicrofacetType::AshikhminShirley
+        );
+        Ok(Microfacet {
+            kind: d.distribution,
+            alpha_u,
+            alpha_v,
+            exponent_u: (2.0 / (alpha_u * alpha_u) - 2.0).max(0.0),
+            exponent_v: (2.0 / (alpha_v * alpha_v) - 2.0).max(0.0),
+            sample_visible: d.sample_visible && !is_phong,
+        })
+    }
+
+    // Rough dielectrics sample a wider distribution when the visible
+    // normals are not used (Walter et al. 2007), as Mitsuba
+    fn dielectric_sampling(&self, wi: Vector3<f32>) -> Microfacet {
+        if self.sample_visible {
+            return self.clone();
+        }
+        let factor = 1.2 - 0.2 * wi.z.abs().sqrt();
+        let alpha_u = self.alpha_u * factor;
+        let alpha_v = self.alpha_v * factor;
+        Microfacet {
+            alpha_u,
+            alpha_v,
+            exponent_u: (2.0 / (alpha_u * alpha_u) - 2.0).max(0.0),
+            exponent_v: (2.0 / (alpha_v * alpha_v) - 2.0).max(0.0),
+            ..self.clone()
+        }
+    }
+
+    fn is_isotropic(&self) -> bool {
+        self.alpha_u == self.alpha_v
+    }
+
+    fn interpolate_phong_exponent(&self, v: Vector3<f32>) -> f32 {
+        let sin_theta2 = 1.0 - v.z * v.z;
+        if self.is_isotropic() || sin_theta2 <= 1e-20 {
+            return self.exponent_u;
+        }
+        (self.exponent_u * v.x * v.x + self.exponent_v * v.y * v.y) / sin_theta2
+    }
+
+    fn project_roughness(&self, v: Vector3<f32>) -> f32 {
+        let sin_theta2 = 1.0 - v.z * v.z;
+        if self.is_isotropic() || sin_theta2 <= 0.0 {
+            return self.alpha_u;
+        }
+        ((v.x * v.x * self.alpha_u * self.alpha_u + v.y * v.y * self.alpha_v * self.alpha_v)
+            / sin_theta2)
+            .sqrt()
+    }
+
+    fn eval(&self, m: Vector3<f32>) -> f32 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let cos_theta2 = m.z * m.z;
+        let beckmann_exponent = (m.x * m.x / (self.alpha_u * self.alpha_u)
+            + m.y * m.y / (self.alpha_v * self.alpha_v))
+            / cos_theta2;
+        let result = match self.kind {
+            MicrofacetType::Beckmann => {
+                (-beckmann_exponent).exp()
+                    / (PI * self.alpha_u * self.alpha_v * cos_theta2 * cos_theta2)
+            }
+            MicrofacetType::GGX => {
+                let root = (1.0 + beckmann_exponent) * cos_theta2;
+                1.0 / (PI * self.alpha_u * self.alpha_v * root * root)
+            }
+            MicrofacetType::Phong | MicrofacetType::AshikhminShirley => {
+                let exponent = self.interpolate_phong_exponent(m);
+                ((self.exponent_u + 2.0) * (self.exponent_v + 2.0)).sqrt()
+                    * INV_TWOPI
+                    * m.z.powf(exponent)
+            }
+        };
+        // Prevent potential numerical issues
+        if result * m.z < 1e-20 {
+            0.0
+        } else {
+            result
+        }
+    }
+
+    fn smith_g1(&self, v: Vector3<f32>, m: Vector3<f32>) -> f32 {
+        if v.dot(m) * v.z <= 0.0 {
+            return 0.0;
+        }
+        let tan_theta = ((1.0 - v.z * v.z).max(0.0).sqrt() / v.z).abs();
+        if tan_theta == 0.0 {
+            return 1.0;
+        }
+        let alpha = self.project_roughness(v);
+        match self.kind {
+            MicrofacetType::GGX => {
+                let root = alpha * tan_theta;
+                2.0 / (1.0 + (1.0 + root * root).sqrt())
+            }
+            _ => {
+                // Rational approximation of the Beckmann shadowing
+                let a = 1.0 / (alpha * tan_theta);
+                if a >= 1.6 {
+                    1.0
+                } else {
+                    let a_sqr = a * a;
+                    (3.535 * a + 2.181 * a_sqr) / (1.0 + 2.276 * a + 2.577 * a_sqr)
+                }
+            }
+        }
+    }
+
+    fn g(&self, wi: Vector3<f32>, wo: Vector3<f32>, m: Vector3<f32>) -> f32 {
+        self.smith_g1(wi, m) * self.smith_g1(wo, m)
+    }
+
+    fn pdf_visible(&self, wi: Vector3<f32>, m: Vector3<f32>) -> f32 {
+        if wi.z == 0.0 {
+            return 0.0;
+        }
+        self.smith_g1(wi, m) * wi.dot(m).abs() * self.eval(m) / wi.z.abs()
+    }
+
+    /// Density of the sampled normals (`wi` in the upper hemisphere)
+    fn pdf(&self, wi: Vector3<f32>, m: Vector3<f32>) -> f32 {
+        if self.sample_visible {
+            self.pdf_visible(wi, m)
+        } else {
+            self.eval(m) * m.z
+        }
+    }
+
+    fn sample_phong_quadrant(&self, u: f32) -> (f32, f32) {
+        let phi = (((self.exponent_u + 2.0) / (self.exponent_v + 2.0)).sqrt()
+            * (PI * u * 0.5).tan())
+        .atan();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let exponent = self.exponent_u * cos_phi * cos_phi + self.exponent_v * sin_phi * sin_phi;
+        (phi, exponent)
+    }
+
+    // Sample the full distribution (density D(m) cos(m))
+    fn sample_all(&self, u: Point2<f32>) -> Vector3<f32> {
+        let (cos_theta, phi) = match self.kind {
+            MicrofacetType::Beckmann | MicrofacetType::GGX => {
+                let (phi, alpha_sqr) = if self.is_isotropic() {
+                    (2.0 * PI * u.y, self.alpha_u * self.alpha_u)
+                } else {
+                    let phi = (self.alpha_v / self.alpha_u * (PI + 2.0 * PI * u.y).tan()).atan()
+                        + PI * (2.0 * u.y + 0.5).floor();
+                    let (sin_phi, cos_phi) = phi.sin_cos();
+                    let alpha_sqr = 1.0
+                        / (cos_phi * cos_phi / (self.alpha_u * self.alpha_u)
+                            + sin_phi * sin_phi / (self.alpha_v * self.alpha_v));
+                    (phi, alpha_sqr)
+                };
+                let tan_theta2 = if self.kind == MicrofacetType::Beckmann {
+                    -alpha_sqr * (1.0 - u.x).ln()
+                } else {
+                    alpha_sqr * u.x / (1.0 - u.x)
+                };
+                (1.0 / (1.0 + tan_theta2).sqrt(), phi)
+            }
+            MicrofacetType::Phong | MicrofacetType::AshikhminShirley => {
+                let (phi, exponent) = if self.is_isotropic() {
+                    (2.0 * PI * u.y, self.exponent_u)
+                } else if u.y < 0.25 {
+                    self.sample_phong_quadrant(4.0 * u.y)
+                } else if u.y < 0.5 {
+                    let (phi, e) = self.sample_phong_quadrant(4.0 * (0.5 - u.y));
+                    (PI - phi, e)
+                } else if u.y < 0.75 {
+                    let (phi, e) = self.sample_phong_quadrant(4.0 * (u.y - 0.5));
+                    (PI + phi, e)
+                } else {
+                    let (phi, e) = self.sample_phong_quadrant(4.0 * (1.0 - u.y));
+                    (2.0 * PI - phi, e)
+                };
+                (u.x.powf(1.0 / (exponent + 2.0)), phi)
+            }
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        Vector3::new(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta)
+    }
+
+    // Slopes of the visible Beckmann distribution (alpha = 1)
+    fn sample_visible_beckmann11(theta_i: f32, u: Point2<f32>) -> (f32, f32) {
+        // Normal incidence
+        if theta_i < 1e-4 {
+            let r = (-(1.0 - u.x).ln()).sqrt();
+            let (sin_phi, cos_phi) = (2.0 * PI * u.y).sin_cos();
+            return (r * cos_phi, r * sin_phi);
+        }
+        // Numerical inversion of the CDF (Newton-bisection in the erf domain)
+        let sqrt_pi_inv = 1.0 / PI.sqrt();
+        let tan_theta_i = theta_i.tan();
+        let cot_theta_i = 1.0 / tan_theta_i;
+        let mut a = -1.0;
+        let mut c = erf(cot_theta_i);
+        let sample_x = u.x.max(1e-6);
+        let fit = 1.0 + theta_i * (-0.876 + theta_i * (0.4265 - 0.0594 * theta_i));
+        let mut b = c - (1.0 + c) * (1.0 - sample_x).powf(fit);
+        let normalization =
+            1.0 / (1.0 + c + sqrt_pi_inv * tan_theta_i * (-cot_theta_i * cot_theta_i).exp());
+        for _ in 0..10 {
+            if !(b >= a && b <= c) {
+                b = 0.5 * (a + c);
+            }
+            let inv_erf = erfinv(b);
+            let value = normalization
+                * (1.0 + b + sqrt_pi_inv * tan_theta_i * (-inv_erf * inv_erf).exp())
+                - sample_x;
+            let derivative = normalization * (1.0 - inv_erf * tan_theta_i);
+            if value.abs() < 1e-5 {
+                break;
+            }
+            if value > 0.0 {
+                c = b;
+            } else {
+                a = b;
+            }
+            b -= value / derivative;
+        }
+        (erfinv(b), erfinv(2.0 * u.y.max(1e-6) - 1.0))
+    }
+
+    // Sample the visible normals (`wi` in the upper hemisphere)
+    fn sample_visible(&self, wi: Vector3<f32>, u: Point2<f32>) -> Vector3<f32> {
+        // Stretch the configuration to the unit roughness
+        let wi_s = Vector3::new(self.alpha_u * wi.x, self.alpha_v * wi.y, wi.z).normalize();
+        if self.kind == MicrofacetType::GGX {
+            // Heitz (2018)
+            let lensq = wi_s.x * wi_s.x + wi_s.y * wi_s.y;
+            let t1 = if lensq > 0.0 {
+                Vector3::new(-wi_s.y, wi_s.x, 0.0) / lensq.sqrt()
+            } else {
+                Vector3::new(1.0, 0.0, 0.0)
+            };
+            let t2 = wi_s.cross(t1);
+            let r = u.x.sqrt();
+            let (sin_phi, cos_phi) = (2.0 * PI * u.y).sin_cos();
+            let p1 = r * cos_phi;
+            let s = 0.5 * (1.0 + wi_s.z);
+            let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * sin_phi;
+            let n = t1 * p1 + t2 * p2 + wi_s * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+            return Vector3::new(self.alpha_u * n.x, self.alpha_v * n.y, n.z.max(0.0)).normalize();
+        }
+
+        let (theta, phi) = if wi_s.z < 0.99999 {
+            (wi_s.z.acos(), wi_s.y.atan2(wi_s.x))
+        } else {
+            (0.0, 0.0)
+        };
+        let (sx, sy) = Self::sample_visible_beckmann11(theta, u);
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let sx_r = cos_phi * sx - sin_phi * sy;
+        let sy_r = sin_phi * sx + cos_phi * sy;
+        Vector3::new(-sx_r * self.alpha_u, -sy_r * self.alpha_v, 1.0).normalize()
+    }
+
+    /// Sample a normal and returns its density (`wi` in the upper hemisphere)
+    fn sample(&self, wi: Vector3<f32>, u: Point2<f32>) -> Option<(Vector3<f32>, f32)> {
+        let m = if self.sample_visible {
+            self.sample_visible(wi, u)
+        } else {
+            self.sample_all(u)
+        };
+        let pdf = self.pdf(wi, m);
+        if pdf < 1e-20 || !m.x.is_finite() || !m.y.is_finite() {
+            None
+        } else {
+            Some((m, pdf))
+        }
+    }
+}
+
+// BSDF with resolved (constant) parameters
+#[derive(Debug, Clone)]
+enum Model {
+    Diffuse {
+        reflectance: RGB,
+    },
+    OrenNayar {
+        reflectance: RGB,
+        alpha: f32,
+        use_fast_approx: bool,
+    },
+    Phong {
+        exponent: f32,
+        ks: RGB,
+        kd: RGB,
+        specular_weight: f32,
+    },
+    Ward {
+        variant: WardVariant,
+        alpha_u: f32,
+        alpha_v: f32,
+        ks: RGB,
+        kd: RGB,
+        specular_weight: f32,
+    },
+    Plastic {
+        eta: f32,
+        ks: RGB,
+        kd: RGB,
+        nonlinear: bool,
+        fdr_int: f32,
+        specular_weight: f32,
+    },
+    Conductor {
+        distribution: Option<Microfacet>,
+        eta: RGB,
+        k: RGB,
+        ks: RGB,
+    },
+    Dielectric {
+        distribution: Option<Microfacet>,
+        eta: f32,
+        ks: RGB,
+        kt: RGB,
+        thin: bool,
+    },
+    TwoSided(Box<Model>),
+    Mixture {
+        weights: Vec<f32>,
+        bsdfs: Vec<Model>,
+    },
+    Mask {
+        opacity: RGB,
+        bsdf: Box<Model>,
+    },
+}
+
+fn specular_weight(ks: RGB, kd: RGB) -> f32 {
+    let s = luminance(ks);
+    let d = luminance(kd);
+    if s + d > 0.0 {
+        s / (s + d)
+    } else {
+        0.5
+    }
+}
+
+impl Model {
+    fn new(bsdf: &BSDF) -> Result<Self> {
+        Ok(match bsdf {
+            BSDF::Diffuse { reflectance } => Model::Diffuse {
+                reflectance: constant_spectrum(reflectance, "reflectance")?,
+            },
+            BSDF::Roughtdiffuse {
+                reflectance,
+                alpha,
+                use_fast_approx,
+            } => Model::OrenNayar {
+                reflectance: constant_spectrum(reflectance, "reflectance")?,
+                alpha: constant_f32(alpha, "alpha")?,
+                use_fast_approx: *use_fast_approx,
+            },
+            BSDF::Phong {
+                exponent,
+                specular_reflectance,
+                diffuse_reflectance,
+            } => {
+                let ks = constant_spectrum(specular_reflectance, "specularReflectance")?;
+                let kd = constant_spectrum(diffuse_reflectance, "diffuseReflectance")?;
+                Model::Phong {
+                    exponent: constant_f32(exponent, "exponent")?,
+                    ks,
+                    kd,
+                    specular_weight: specular_weight(ks, kd),
+                }
+            }
+            BSDF::Ward {
+                variant,
+                alpha,
+                specular_reflectance,
+                diffuse_reflectance,
+            } => {
+                let (alpha_u, alpha_v) = match alpha {
+                    Alpha::Isotropic(a) => {
+                        let a = constant_f32(a, "alpha")?;
+                        (a, a)
+                    }
+                    Alpha::Anisotropic { u, v } => {
+                        (constant_f32(u, "alphaU")?, constant_f32(v, "alphaV")?)
+                    }
+                };
+                let ks = constant_spectrum(specular_reflectance, "specularReflectance")?;
+                let kd = constant_spectrum(diffuse_reflectance, "diffuseReflectance")?;
+                Model::Ward {
+                    variant: variant.clone(),
+                    alpha_u,
+                    alpha_v,
+                    ks,
+                    kd,
+                    specular_weight: specular_weight(ks, kd),
+                }
+            }
+            BSDF::Plastic {
+                distribution,
+                int_ior,
+                ext_ior,
+                specular_reflectance,
+                diffuse_reflectance,
+                nonlinear,
+            } => {
+                if distribution.is_some() {
+                    return Err(Error::Unsupported(
+                        "evaluation of roughplastic (rough transmittance tables)".to_string(),
+                    ));
+                }
+                let ks = constant_spectrum(specular_reflectance, "specularReflectance")?;
+                let kd = constant_spectrum(diffuse_reflectance, "diffuseReflectance")?;
+                let eta = int_ior / ext_ior;
+                Model::Plastic {
+                    eta,
+                    ks,
+                    kd,
+                    nonlinear: *nonlinear,
+                    fdr_int: fresnel_diffuse_reflectance(1.0 / eta),
+                    specular_weight: specular_weight(ks, kd),
+                }
+            }
+            BSDF::Conductor {
+                distribution,
+                eta,
+                k,
+                ext_eta,
+                specular_reflectance,
+                ..
+            } => Model::Conductor {
+                distribution: distribution.as_ref().map(Microfacet::new).transpose()?,
+                eta: scale(eta.clone().as_rgb()?, 1.0 / ext_eta),
+                k: scale(k.clone().as_rgb()?, 1.0 / ext_eta),
+                ks: constant_spectrum(specular_reflectance, "specularReflectance")?,
+            },
+            BSDF::Dielectric {
+                distribution,
+                int_ior,
+                ext_ior,
+                specular_reflectance,
+                specular_transmittance,
+                thin,
+            } => {
+                if *thin && distribution.is_some() {
+                    return Err(Error::Unsupported(
+                        "evaluation of rough thin dielectrics".to_string(),
+                    ));
+                }
+                Model::Dielectric {
+                    distribution: distribution.as_ref().map(Microfacet::new).transpose()?,
+                    eta: int_ior / ext_ior,
+                    ks: constant_spectrum(specular_reflectance, "specularReflectance")?,
+                    kt: constant_spectrum(specular_transmittance, "specularTransmittance")?,
+                    thin: *thin,
+                }
+            }
+            BSDF::TwoSided { bsdf } => Model::TwoSided(Box::new(Model::new(bsdf)?)),
+            BSDF::MixtureBSDF { weights, bsdfs } => Model::Mixture {
+                weights: weights.clone(),
+                bsdfs: bsdfs.iter().map(Model::new).collect::<Result<Vec<_>>>()?,
+            },
+            BSDF::Mask { opacity, bsdf } => Model::Mask {
+                opacity: constant_spectrum(opacity, "opacity")?,
+                bsdf: Box::new(Model::new(bsdf)?),
+            },
+            _ => {
+                return Err(Error::Unsupported(format!(
+                    "evaluation of the BSDF {:?}",
+                    bsdf
+                )))
+            }
+        })
+    }
+
+    // Ward specular lobe (value, density)
+    fn ward_specular(
+        variant: &WardVariant,
+        alpha_u: f32,
+        alpha_v: f32,
+        wi: Vector3<f32>,
+        wo: Vector3<f32>,
+    ) -> (f32, f32) {
+        let h = wi + wo;
+        let factor1 = match variant {
+            WardVariant::Ward => 1.0 / (4.0 * PI * alpha_u * alpha_v * (wi.z * wo.z).sqrt()),
+            WardVariant::WardDuer => 1.0 / (4.0 * PI * alpha_u * alpha_v * wi.z * wo.z),
+            WardVariant::Balanced => h.dot(h) / (PI * alpha_u * alpha_v * h.z.powi(4)),
+        };
+        let factor2 = h.x / alpha_u;
+        let factor3 = h.y / alpha_v;
+        let exponent = -(factor2 * factor2 + factor3 * factor3) / (h.z * h.z);
+        let value = factor1 * exponent.exp();
+        let value = if value > 1e-10 { value } else { 0.0 };
+
+        let h = h.normalize();
+        let pdf = exponent.exp() / (4.0 * PI * alpha_u * alpha_v * h.dot(wi) * h.z.powi(3));
+        (value, pdf)
+    }
+
+    // Transmission through the smooth plastic interface
+    fn plastic_transmittance(eta: f32, cos_theta: f32) -> f32 {
+        1.0 - fresnel_dielectric_ext(cos_theta, eta).0
+    }
+
+    fn plastic_diffuse(kd: RGB, nonlinear: bool, fdr_int: f32) -> RGB {
+        if nonlinear {
+            RGB {
+                r: kd.r / (1.0 - kd.r * fdr_int),
+                g: kd.g / (1.0 - kd.g * fdr_int),
+                b: kd.b / (1.0 - kd.b * fdr_int),
+            }
+        } else {
+            scale(kd, 1.0 / (1.0 - fdr_int))
+        }
+    }
+
+    fn eval(&self, wi: Vector3<f32>, wo: Vector3<f32>) -> RGB {
+        match self {
+            Model::Diffuse { reflectance } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return rgb(0.0);
+                }
+                scale(*reflectance, INV_PI * wo.z)
+            }
+            Model::OrenNayar {
+                reflectance,
+                alpha,
+                use_fast_approx,
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return rgb(0.0);
+                }
+                // Conversion from the RMS slope to the standard deviation
+                let sigma = alpha * std::f32::consts::FRAC_1_SQRT_2;
+                let sigma2 = sigma * sigma;
+                let sin_theta_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+                let sin_theta_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+                let cos_phi_diff = if sin_theta_i > 1e-4 && sin_theta_o > 1e-4 {
+                    (wi.x * wo.x + wi.y * wo.y) / (sin_theta_i * sin_theta_o)
+                } else {
+                    0.0
+                };
+
+                if *use_fast_approx {
+                    let a = 1.0 - 0.5 * sigma2 / (sigma2 + 0.33);
+                    let b = 0.45 * sigma2 / (sigma2 + 0.09);
+                    let (sin_alpha, tan_beta) = if wi.z > wo.z {
+                        (sin_theta_o, sin_theta_i / wi.z)
+                    } else {
+                        (sin_theta_i, sin_theta_o / wo.z)
+                    };
+                    scale(
+                        *reflectance,
+                        INV_PI * wo.z * (a + b * cos_phi_diff.max(0.0) * sin_alpha * tan_beta),
+                    )
+                } else {
+                    let theta_i = wi.z.min(1.0).acos();
+                    let theta_o = wo.z.min(1.0).acos();
+                    let alpha = theta_i.max(theta_o);
+                    let beta = theta_i.min(theta_o);
+                    let sin_alpha = alpha.sin();
+                    let tan_beta = beta.tan();
+
+                    let tmp = sigma2 / (sigma2 + 0.09);
+                    let tmp2 = (4.0 * INV_PI * INV_PI) * alpha * beta;
+                    let tmp3 = 2.0 * beta * INV_PI;
+
+                    let c1 = 1.0 - 0.5 * sigma2 / (sigma2 + 0.33);
+                    let c2 = if cos_phi_diff >= 0.0 {
+                        0.45 * tmp * sin_alpha
+                    } else {
+                        0.45 * tmp * (sin_alpha - tmp3 * tmp3 * tmp3)
+                    };
+                    let c3 = 0.125 * tmp * tmp2 * tmp2;
+                    let c4 = 0.17 * sigma2 / (sigma2 + 0.13) * (1.0 - cos_phi_diff * tmp3 * tmp3);
+
+                    let l1 = scale(
+                        *reflectance,
+                        INV_PI
+                            * (c1
+                                + cos_phi_diff * c2 * tan_beta
+                                + (1.0 - cos_phi_diff.abs()) * c3 * (0.5 * (alpha + beta)).tan()),
+                    );
+                    let l2 = scale(mul(*reflectance, *reflectance), c4 * INV_PI);
+                    scale(add(l1, l2), wo.z)
+                }
+            }
+            Model::Phong {
+                exponent, ks, kd, ..
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return rgb(0.0);
+                }
+                let alpha = wo.dot(reflect(wi));
+                let spec = if alpha > 0.0 {
+                    scale(*ks, (exponent + 2.0) * INV_TWOPI * alpha.powf(*exponent))
+                } else {
+                    rgb(0.0)
+                };
+                scale(add(scale(*kd, INV_PI), spec), wo.z)
+            }
+            Model::Ward {
+                variant,
+                alpha_u,
+                alpha_v,
+                ks,
+                kd,
+                ..
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return rgb(0.0);
+                }
+                let (spec, _) = Self::ward_specular(variant, *alpha_u, *alpha_v, wi, wo);
+                scale(add(scale(*ks, spec), scale(*kd, INV_PI)), wo.z)
+            }
+            Model::Plastic {
+                eta,
+                kd,
+                nonlinear,
+                fdr_int,
+                ..
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return rgb(0.0);
+                }
+                let diff = Self::plastic_diffuse(*kd, *nonlinear, *fdr_int);
+                let t12 = Self::plastic_transmittance(*eta, wi.z);
+                let t21 = Self::plastic_transmittance(*eta, wo.z);
+                scale(diff, INV_PI * wo.z * t12 * t21 / (eta * eta))
+            }
+            Model::Conductor {
+                distribution: Some(d),
+                eta,
+                k,
+                ks,
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return rgb(0.0);
+                }
+                let h = (wi + wo).normalize();
+                let d_value = d.eval(h);
+                if d_value == 0.0 {
+                    return rgb(0.0);
+                }
+                let f = mul(fresnel_conductor(wi.dot(h), *eta, *k), *ks);
+                scale(f, d_value * d.g(wi, wo, h) / (4.0 * wi.z))
+            }
+            Model::Dielectric {
+                distribution: Some(d),
+                eta,
+                ks,
+                kt,
+                ..
+            } => {
+                if wi.z == 0.0 {
+                    return rgb(0.0);
+                }
+                let reflect = wi.z * wo.z > 0.0;
+                let eta_rel = if wi.z > 0.0 { *eta } else { 1.0 / eta };
+                let h = if reflect {
+                    (wo + wi).normalize()
+                } else {
+                    (wi + wo * eta_rel).normalize()
+                };
+                let h = h * h.z.signum();
+                let d_value = d.eval(h);
+                if d_value == 0.0 {
+                    return rgb(0.0);
+                }
+                let f = fresnel_dielectric_ext(wi.dot(h), *eta).0;
+                let g = d.g(wi, wo, h);
+                if reflect {
+                    scale(*ks, f * d_value * g / (4.0 * wi.z.abs()))
+                } else {
+                    let sqrt_denom = wi.dot(h) + eta_rel * wo.dot(h);
+                    let value =
+                        ((1.0 - f) * d_value * g * eta_rel * eta_rel * wi.dot(h) * wo.dot(h))
+                            / (wi.z * sqrt_denom * sqrt_denom);
+                    // Radiance is scaled when crossing the interface
+                    let factor = 1.0 / eta_rel;
+                    scale(*kt, (value * factor * factor).abs())
+                }
+            }
+            // Only Dirac deltas
+            Model::Conductor { .. } | Model::Dielectric { .. } => rgb(0.0),
+            Model::TwoSided(bsdf) => {
+                let (wi, wo) = Self::two_sided(wi, wo);
+                bsdf.eval(wi, wo)
+            }
+            Model::Mixture { weights, bsdfs } => weights
+                .iter()
+                .zip(bsdfs)
+                .fold(rgb(0.0), |acc, (w, b)| add(acc, scale(b.eval(wi, wo), *w))),
+            Model::Mask { opacity, bsdf } => mul(*opacity, bsdf.eval(wi, wo)),
+        }
+    }
+
+    fn pdf(&self, wi: Vector3<f32>, wo: Vector3<f32>) -> f32 {
+        match self {
+            Model::Diffuse { .. } | Model::OrenNayar { .. } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return 0.0;
+                }
+                wo.z * INV_PI
+            }
+            Model::Phong {
+                exponent,
+                specular_weight,
+                ..
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return 0.0;
+                }
+                let alpha = wo.dot(reflect(wi));
+                let spec = if alpha > 0.0 {
+                    alpha.powf(*exponent) * (exponent + 1.0) * INV_TWOPI
+                } else {
+                    0.0
+                };
+                specular_weight * spec + (1.0 - specular_weight) * wo.z * INV_PI
+            }
+            Model::Ward {
+                variant,
+                alpha_u,
+                alpha_v,
+                specular_weight,
+                ..
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return 0.0;
+                }
+                let (_, spec) = Self::ward_specular(variant, *alpha_u, *alpha_v, wi, wo);
+                specular_weight * spec + (1.0 - specular_weight) * wo.z * INV_PI
+            }
+            Model::Plastic {
+                eta,
+                specular_weight,
+                ..
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return 0.0;
+                }
+                let fi = fresnel_dielectric_ext(wi.z, *eta).0;
+                let prob_specular = fi * specular_weight
+                    / (fi * specular_weight + (1.0 - fi) * (1.0 - specular_weight));
+                (1.0 - prob_specular) * wo.z * INV_PI
+            }
+            Model::Conductor {
+                distribution: Some(d),
+                ..
+            } => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return 0.0;
+                }
+                let h = (wi + wo).normalize();
+                d.pdf(wi, h) / (4.0 * wo.dot(h).abs())
+            }
+            Model::Dielectric {
+                distribution: Some(d),
+                eta,
+                ..
+            } => {
+                if wi.z == 0.0 {
+                    return 0.0;
+                }
+                let reflect = wi.z * wo.z > 0.0;
+                let (h, dwh_dwo) = if reflect {
+                    let h = (wo + wi).normalize();
+                    (h, 1.0 / (4.0 * wo.dot(h)))
+                } else {
+                    let eta_rel = if wi.z > 0.0 { *eta } else { 1.0 / eta };
+                    let h = (wi + wo * eta_rel).normalize();
+                    let sqrt_denom = wi.dot(h) + eta_rel * wo.dot(h);
+                    (h, eta_rel * eta_rel * wo.dot(h) / (sqrt_denom * sqrt_denom))
+                };
+                let h = h * h.z.signum();
+                let prob = d.dielectric_sampling(wi).pdf(wi * wi.z.signum(), h);
+                let f = fresnel_dielectric_ext(wi.dot(h), *eta).0;
+                let prob = if reflect { prob * f } else { prob * (1.0 - f) };
+                (prob * dwh_dwo).abs()
+            }
+            Model::Conductor { .. } | Model::Dielectric { .. } => 0.0,
+            Model::TwoSided(bsdf) => {
+                let (wi, wo) = Self::two_sided(wi, wo);
+                bsdf.pdf(wi, wo)
+            }
+            Model::Mixture { weights, bsdfs } => {
+                let total = weights.iter().sum::<f32>();
+                weights
+                    .iter()
+                    .zip(bsdfs)
+                    .map(|(w, b)| b.pdf(wi, wo) * w / total)
+                    .sum()
+            }
+            Model::Mask { opacity, bsdf } => average(*opacity) * bsdf.pdf(wi, wo),
+        }
+    }
+
+    fn two_sided(wi: Vector3<f32>, wo: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        if wi.z < 0.0 {
+            (
+                Vector3::new(wi.x, wi.y, -wi.z),
+                Vector3::new(wo.x, wo.y, -wo.z),
+            )
+        } else {
+            (wi, wo)
+        }
+    }
+
+    // Sample with a non-delta direction: weight and density from eval/pdf
+    fn sampled(&self, wi: Vector3<f32>, wo: Vector3<f32>) -> Option<BSDFSample> {
+        let pdf = self.pdf(wi, wo);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+        Some(BSDFSample {
+            wo,
+            weight: scale(self.eval(wi, wo), 1.0 / pdf),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn delta(wo: Vector3<f32>, weight: RGB, pdf: f32) -> Option<BSDFSample> {
+        Some(BSDFSample {
+            wo,
+            weight,
+            pdf,
+            delta: true,
+        })
+    }
+
+    fn sample(&self, wi: Vector3<f32>, uc: f32, u: Point2<f32>) -> Option<BSDFSample> {
+        match self {
+            Model::Diffuse { .. } | Model::OrenNayar { .. } => {
+                if wi.z <= 0.0 {
+                    return None;
+                }
+                self.sampled(wi, square_to_cosine_hemisphere(u))
+            }
+            Model::Phong {
+                exponent,
+                specular_weight,
+                ..
+            } => {
+                if wi.z <= 0.0 {
+                    return None;
+                }
+                let wo = if uc < *specular_weight {
+                    // Sample the lobe around the mirror direction
+                    let cos_alpha = u.x.powf(1.0 / (exponent + 1.0));
+                    let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+                    let (sin_phi, cos_phi) = (2.0 * PI * u.y).sin_cos();
+                    let local = Vector3::new(sin_alpha * cos_phi, sin_alpha * sin_phi, cos_alpha);
+                    to_world(reflect(wi), local)
+                } else {
+                    square_to_cosine_hemisphere(u)
+                };
+                if wo.z <= 0.0 {
+                    return None;
+                }
+                self.sampled(wi, wo)
+            }
+            Model::Ward {
+                alpha_u,
+                alpha_v,
+                specular_weight,
+                ..
+            } => {
+                if wi.z <= 0.0 {
+                    return None;
+                }
+                let wo = if uc < *specular_weight {
+                    let mut phi_h = (alpha_v / alpha_u * (2.0 * PI * u.y).tan()).atan();
+                    if u.y > 0.5 {
+                        phi_h += PI;
+                    }
+                    let (sin_phi_h, cos_phi_h) = phi_h.sin_cos();
+                    let theta_h = ((-u.x.ln()).max(0.0)
+                        / (cos_phi_h * cos_phi_h / (alpha_u * alpha_u)
+                            + sin_phi_h * sin_phi_h / (alpha_v * alpha_v)))
+                        .sqrt()
+                        .atan();
+                    let (sin_theta_h, cos_theta_h) = theta_h.sin_cos();
+                    let h = Vector3::new(
+                        sin_theta_h * cos_phi_h,
+                        sin_theta_h * sin_phi_h,
+                        cos_theta_h,
+                    );
+                    reflect_m(wi, h)
+                } else {
+                    square_to_cosine_hemisphere(u)
+                };
+                if wo.z <= 0.0 {
+                    return None;
+                }
+                self.sampled(wi, wo)
+            }
+            Model::Plastic {
+                eta,
+                ks,
+                kd,
+                nonlinear,
+                fdr_int,
+                specular_weight,
+            } => {
+                if wi.z <= 0.0 {
+                    return None;
+                }
+                let fi = fresnel_dielectric_ext(wi.z, *eta).0;
+                let prob_specular = fi * specular_weight
+                    / (fi * specular_weight + (1.0 - fi) * (1.0 - specular_weight));
+                if uc < prob_specular {
+                    Self::delta(reflect(wi), scale(*ks, fi / prob_specular), prob_specular)
+                } else {
+                    let wo = square_to_cosine_hemisphere(u);
+                    let fo = fresnel_dielectric_ext(wo.z, *eta).0;
+                    let diff = Self::plastic_diffuse(*kd, *nonlinear, *fdr_int);
+                    Some(BSDFSample {
+                        wo,
+                        weight: scale(
+                            diff,
+                            (1.0 - fi) * (1.0 - fo) / (eta * eta * (1.0 - prob_specular)),
+                        ),
+                        pdf: (1.0 - prob_specular) * wo.z * INV_PI,
+                        delta: false,
+                    })
+                }
+            }
+            Model::Conductor {
+                distribution: None,
+                eta,
+                k,
+                ks,
+            } => {
+                if wi.z <= 0.0 {
+                    return None;
+                }
+                Self::delta(
+                    reflect(wi),
+                    mul(*ks, fresnel_conductor(wi.z, *eta, *k)),
+                    1.0,
+                )
+            }
+            Model::Conductor {
+                distribution: Some(d),
+                ..
+            } => {
+                if wi.z <= 0.0 {
+                    return None;
+                }
+                let (m, _) = d.sample(wi, u)?;
+                let wo = reflect_m(wi, m);
+                if wo.z <= 0.0 {
+                    return None;
+                }
+                self.sampled(wi, wo)
+            }
+            Model::Dielectric {
+                distribution: None,
+                eta,
+                ks,
+                kt,
+                thin: false,
+            } => {
+                let (f, cos_theta_t) = fresnel_dielectric_ext(wi.z, *eta);
+                if uc <= f {
+                    Self::delta(reflect(wi), *ks, f)
+                } else {
+                    let eta_t = if cos_theta_t < 0.0 { 1.0 / eta } else { *eta };
+                    let wo = Vector3::new(-eta_t * wi.x, -eta_t * wi.y, cos_theta_t);
+                    Self::delta(wo, scale(*kt, eta_t * eta_t), 1.0 - f)
+                }
+            }
+            Model::Dielectric {
+                distribution: None,
+                eta,
+                ks,
+                kt,
+                thin: true,
+            } => {
+                // Account for the internal reflections inside the slab
+                let mut r = fresnel_dielectric_ext(wi.z.abs(), *eta).0;
+                let t = 1.0 - r;
+                if r < 1.0 {
+                    r += t * t * r / (1.0 - r * r);
+                }
+                if uc <= r {
+                    Self::delta(reflect(wi), *ks, r)
+                } else {
+                    Self::delta(-wi, *kt, 1.0 - r)
+                }
+            }
+            Model::Dielectric {
+                distribution: Some(d),
+                eta,
+                ..
+            } => {
+                if wi.z == 0.0 {
+                    return None;
+                }
+                let (m, _) = d.dielectric_sampling(wi).sample(wi * wi.z.signum(), u)?;
+                let (f, cos_theta_t) = fresnel_dielectric_ext(wi.dot(m), *eta);
+                let wo = if uc <= f {
+                    let wo = reflect_m(wi, m);
+                    if wi.z * wo.z <= 0.0 {
+                        return None;
+                    }
+                    wo
+                } else {
+                    if cos_theta_t == 0.0 {
+                        return None;
+                    }
+                    let wo = refract_m(wi, m, *eta, cos_theta_t);
+                    if wi.z * wo.z >= 0.0 {
+                        return None;
+                    }
+                    wo
+                };
+                self.sampled(wi, wo)
+            }
+            Model::TwoSided(bsdf) => {
+                if wi.z < 0.0 {
+                    let mut s = bsdf.sample(Vector3::new(wi.x, wi.y, -wi.z), uc, u)?;
+                    s.wo.z = -s.wo.z;
+                    Some(s)
+                } else {
+                    bsdf.sample(wi, uc, u)
+                }
+            }
+            Model::Mixture { weights, bsdfs } => {
+                // Choose a component and reuse the random number
+                let total = weights.iter().sum::<f32>();
+                let mut cdf = 0.0;
+                let mut entry = bsdfs.len() - 1;
+                let mut uc_entry = uc;
+                for (i, w) in weights.iter().enumerate() {
+                    let p = w / total;
+                    if uc < cdf + p || i == bsdfs.len() - 1 {
+                        entry = i;
+                        uc_entry = ((uc - cdf) / p).min(1.0 - f32::EPSILON);
+                        break;
+                    }
+                    cdf += p;
+                }
+                let s = bsdfs[entry].sample(wi, uc_entry, u)?;
+                if s.delta {
+                    let p = weights[entry] / total;
+                    Self::delta(s.wo, scale(s.weight, weights[entry] / p), s.pdf * p)
+                } else {
+                    self.sampled(wi, s.wo)
+                }
+            }
+            Model::Mask { opacity, bsdf } => {
+                let prob_nested = average(*opacity);
+                if uc < prob_nested {
+                    let s = bsdf.sample(wi, uc / prob_nested, u)?;
+                    if s.delta {
+                        Self::delta(
+                            s.wo,
+                            scale(mul(s.weight, *opacity), 1.0 / prob_nested),
+                            s.pdf * prob_nested,
+                        )
+                    } else {
+                        self.sampled(wi, s.wo)
+                    }
+                } else {
+                    // Straight transmission through the transparent part
+                    let transparency = add(rgb(1.0), scale(*opacity, -1.0));
+                    Self::delta(
+                        -wi,
+                        scale(transparency, 1.0 / (1.0 - prob_nested)),
+                        1.0 - prob_nested,
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// BSDF with its parameters parsed (spectra converted to RGB), to be
+/// evaluated many times
+#[derive(Debug, Clone)]
+pub struct BSDFModel(Model);
+
+impl BSDFModel {
+    pub fn new(bsdf: &BSDF) -> Result<Self> {
+        Ok(BSDFModel(Model::new(bsdf)?))
+    }
+
+    /// Evaluate the BSDF times the foreshortening (`f(wi, wo) |cos(wo)|`).
+    /// Dirac deltas (smooth reflection/refraction) are not included.
+    pub fn eval(&self, wi: Vector3<f32>, wo: Vector3<f32>) -> RGB {
+        self.0.eval(wi, wo)
+    }
+
+    /// Solid angle density of `sample` for the direction `wo`
+    pub fn pdf(&self, wi: Vector3<f32>, wo: Vector3<f32>) -> f32 {
+        self.0.pdf(wi, wo)
+    }
+
+    /// Sample an outgoing direction. `uc` selects the component (e.g.,
+    /// reflection or refraction) and `u` samples the direction.
+    pub fn sample(&self, wi: Vector3<f32>, uc: f32, u: Point2<f32>) -> Option<BSDFSample> {
+        self.0.sample(wi, uc, u)
+    }
+}
+
+// Single evaluations (use BSDFModel to avoid parsing the parameters again)
+impl BSDF {
+    /// See `BSDFModel::eval`
+    pub fn eval(&self, wi: Vector3<f32>, wo: Vector3<f32>) -> Result<RGB> {
+        Ok(BSDFModel::new(self)?.eval(wi, wo))
+    }
+
+    /// See `BSDFModel::pdf`
+    pub fn pdf(&self, wi: Vector3<f32>, wo: Vector3<f32>) -> Result<f32> {
+        Ok(BSDFModel::new(self)?.pdf(wi, wo))
+    }
+
+    /// See `BSDFModel::sample`
+    pub fn sample(&self, wi: Vector3<f32>, uc: f32, u: Point2<f32>) -> Result<Option<BSDFSample>> {
+        Ok(BSDFModel::new(self)?.sample(wi, uc, u))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BSDFColor, Spectrum};
+
+    // Xorshift random number generator
+    struct Sampler(u64);
+    impl Sampler {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+        fn point(&mut self) -> Point2<f32> {
+            Point2::new(self.next(), self.next())
+        }
+    }
+
+    fn c(v: &str) -> BSDFColorSpectrum {
+        BSDFColor::Constant(Spectrum {
+            value: v.to_string(),
+        })
+    }
+
+    fn distribution(kind: MicrofacetType, u: f32, v: f32, sample_visible: bool) -> Distribution {
+        Distribution {
+            distribution: kind,
+            alpha: if u == v {
+                Alpha::Isotropic(BSDFColor::Constant(u))
+            } else {
+                Alpha::Anisotropic {
+                    u: BSDFColor::Constant(u),
+                    v: BSDFColor::Constant(v),
+                }
+            },
+            sample_visible,
+        }
+    }
+
+    fn direction(theta: f32, phi: f32) -> Vector3<f32> {
+        Vector3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        )
+    }
+
+    fn diffuse() -> BSDF {
+        BSDF::Diffuse {
+            reflectance: c("0.2, 0.5, 0.8"),
+        }
+    }
+
+    fn glossy_bsdfs() -> Vec<(&'static str, BSDF)> {
+        let mut bsdfs = vec![
+            ("diffuse", diffuse()),
+            (
+                "roughdiffuse",
+                BSDF::Roughtdiffuse {
+                    reflectance: c("0.5"),
+                    alpha: BSDFColor::Constant(0.4),
+                    use_fast_approx: false,
+                },
+            ),
+            (
+                "roughdiffuse_fast",
+                BSDF::Roughtdiffuse {
+                    reflectance: c("0.5"),
+                    alpha: BSDFColor::Constant(0.4),
+                    use_fast_approx: true,
+                },
+            ),
+            (
+                "phong",
+                BSDF::Phong {
+                    exponent: BSDFColor::Constant(30.0),
+                    specular_reflectance: c("0.2"),
+                    diffuse_reflectance: c("0.5"),
+                },
+            ),
+            (
+                "ward",
+                BSDF::Ward {
+                    variant: WardVariant::Balanced,
+                    alpha: Alpha::Anisotropic {
+                        u: BSDFColor::Constant(0.2),
+                        v: BSDFColor::Constant(0.35),
+                    },
+                    specular_reflectance: c("0.2"),
+                    diffuse_reflectance: c("0.5"),
+                },
+            ),
+            (
+                "mixture",
+                BSDF::MixtureBSDF {
+                    weights: vec![0.3, 0.7],
+                    bsdfs: vec![
+                        diffuse(),
+                        BSDF::Phong {
+                            exponent: BSDFColor::Constant(10.0),
+                            specular_reflectance: c("0.4"),
+                            diffuse_reflectance: c("0.1"),
+                        },
+                    ],
+                },
+            ),
+        ];
+        for (kind, visible) in &[
+            (MicrofacetType::Beckmann, true),
+            (MicrofacetType::Beckmann, false),
+            (MicrofacetType::GGX, true),
+            (MicrofacetType::GGX, false),
+            (MicrofacetType::Phong, false),
+        ] {
+            bsdfs.push((
+                "roughconductor",
+                BSDF::Conductor {
+                    distribution: Some(distribution(*kind, 0.25, 0.4, *visible)),
+                    material: None,
+                    eta: Spectrum::from_rgb("0.2, 0.92, 1.1".to_string()),
+                    k: Spectrum::from_rgb("3.9, 2.45, 2.14".to_string()),
+                    ext_eta: 1.0,
+                    specular_reflectance: c("1.0"),
+                },
+            ));
+        }
+        bsdfs
+    }
+
+    fn rough_dielectric(kind: MicrofacetType, visible: bool) -> BSDF {
+        BSDF::Dielectric {
+            distribution: Some(distribution(kind, 0.3, 0.3, visible)),
+            int_ior: 1.5,
+            ext_ior: 1.0,
+            specular_reflectance: c("1.0"),
+            specular_transmittance: c("1.0"),
+            thin: false,
+        }
+    }
+
+    // Average of the sample weights (directional albedo)
+    fn albedo(bsdf: &BSDF, wi: Vector3<f32>, n: usize) -> RGB {
+        let mut sampler = Sampler(0x1234_5678_9abc);
+        let mut sum = rgb(0.0);
+        for _ in 0..n {
+            let uc = sampler.next();
+            if let Some(s) = bsdf.sample(wi, uc, sampler.point()).unwrap() {
+                sum = add(sum, s.weight);
+            }
+        }
+        scale(sum, 1.0 / n as f32)
+    }
+
+    // Integral of eval (and pdf) with uniform sphere sampling
+    fn integrate(bsdf: &BSDF, wi: Vector3<f32>, n: usize) -> (RGB, f32) {
+        let mut sampler = Sampler(0x9876_5432_1fed);
+        let mut sum = rgb(0.0);
+        let mut sum_pdf = 0.0;
+        for _ in 0..n {
+            let u = sampler.point();
+            let z = 1.0 - 2.0 * u.x;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let phi = 2.0 * PI * u.y;
+            let wo = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+            sum = add(sum, bsdf.eval(wi, wo).unwrap());
+            sum_pdf += bsdf.pdf(wi, wo).unwrap();
+        }
+        (
+            scale(sum, 4.0 * PI / n as f32),
+            sum_pdf * 4.0 * PI / n as f32,
+        )
+    }
+
+    fn assert_close(a: RGB, b: RGB, tol: f32, name: &str) {
+        for (x, y) in &[(a.r, b.r), (a.g, b.g), (a.b, b.b)] {
+            assert!((x - y).abs() <= tol, "{}: {:?} != {:?}", name, a, b);
+        }
+    }
+
+    #[test]
+    fn energy_conservation() {
+        let mut bsdfs = glossy_bsdfs();
+        bsdfs.push((
+            "conductor",
+            BSDF::Conductor {
+                distribution: None,
+                material: None,
+                eta: Spectrum::from_f32(0.2),
+                k: Spectrum::from_f32(3.9),
+                ext_eta: 1.0,
+                specular_reflectance: c("1.0"),
+            },
+        ));
+        bsdfs.push((
+            "plastic",
+            BSDF::Plastic {
+                distribution: None,
+                int_ior: 1.49,
+                ext_ior: 1.0,
+                specular_reflectance: c("1.0"),
+                diffuse_reflectance: c("0.9"),
+                nonlinear: false,
+            },
+        ));
+        bsdfs.push((
+            "dielectric",
+            BSDF::Dielectric {
+                distribution: None,
+                int_ior: 1.5,
+                ext_ior: 1.0,
+                specular_reflectance: c("1.0"),
+                specular_transmittance: c("1.0"),
+                thin: true,
+            },
+        ));
+        bsdfs.push((
+            "roughdielectric",
+            rough_dielectric(MicrofacetType::GGX, true),
+        ));
+        bsdfs.push((
+            "mask",
+            BSDF::Mask {
+                opacity: c("0.3, 0.5, 0.7"),
+                bsdf: Box::new(diffuse()),
+            },
+        ));
+        for (name, bsdf) in &bsdfs {
+            for theta in &[0.0, 0.5, 1.0, 1.4] {
+                let a = albedo(bsdf, direction(*theta, 0.3), 10_000);
+                for v in &[a.r, a.g, a.b] {
+                    assert!(*v <= 1.02 && *v >= 0.0, "{} albedo {:?}", name, a);
+                }
+            }
+        }
+
+        // The thin dielectric and the mask do not absorb
+        let a = albedo(&bsdfs[bsdfs.len() - 3].1, direction(0.7, 0.0), 100);
+        assert_close(a, rgb(1.0), 1e-4, "dielectric");
+    }
+
+    #[test]
+    fn sampling_matches_eval() {
+        let mut bsdfs = glossy_bsdfs();
+        for kind in &[MicrofacetType::Beckmann, MicrofacetType::GGX] {
+            bsdfs.push(("roughdielectric", rough_dielectric(*kind, true)));
+            bsdfs.push(("roughdielectric", rough_dielectric(*kind, false)));
+        }
+        for (name, bsdf) in &bsdfs {
+            for theta in &[0.2, 0.9] {
+                let wi = direction(*theta, 1.0);
+                // Importance sampling and uniform sampling agree
+                let a = albedo(bsdf, wi, 50_000);
+                let (b, pdf) = integrate(bsdf, wi, 200_000);
+                assert_close(a, b, 0.02, name);
+                // The density integrates to one (minus the rejected samples)
+                assert!(pdf <= 1.02 && pdf > 0.8, "{}: pdf integral {}", name, pdf);
+
+                let mut sampler = Sampler(42);
+                for _ in 0..100 {
+                    let uc = sampler.next();
+                    if let Some(s) = bsdf.sample(wi, uc, sampler.point()).unwrap() {
+                        let pdf = bsdf.pdf(wi, s.wo).unwrap();
+                        assert!((pdf - s.pdf).abs() <= 1e-3 * pdf, "{}", name);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_sampling() {
+        // Without the visible normals, the sampled Beckmann distribution
+        // is widened by 1.2 - 0.2 sqrt(|cos(wi)|)
+        let bsdf = rough_dielectric(MicrofacetType::Beckmann, false);
+        let wi = direction(1.3, 0.0);
+        let wo = direction(0.4, PI);
+        let h = (wi + wo).normalize();
+        let alpha = 0.3 * (1.2 - 0.2 * wi.z.sqrt());
+        let tan_theta2 = (1.0 - h.z * h.z) / (h.z * h.z);
+        let d = (-tan_theta2 / (alpha * alpha)).exp() / (PI * alpha * alpha * h.z.powi(4));
+        let f = fresnel_dielectric_ext(wi.dot(h), 1.5).0;
+        let expected = d * h.z * f / (4.0 * wo.dot(h));
+        let pdf = bsdf.pdf(wi, wo).unwrap();
+        assert!(
+            (pdf - expected).abs() <= 1e-4 * expected,
+            "{} != {}",
+            pdf,
+            expected
+        );
+    }
+
+    #[test]
+    fn reciprocity() {
+        let mut bsdfs = glossy_bsdfs();
+        bsdfs.push((
+            "roughdielectric",
+            rough_dielectric(MicrofacetType::GGX, true),
+        ));
+        let mut sampler = Sampler(7);
+        for (name, bsdf) in &bsdfs {
+            for _ in 0..100 {
+                let wi = direction(sampler.next() * 1.5, sampler.next() * 2.0 * PI);
+                let wo = direction(sampler.next() * 1.5, sampler.next() * 2.0 * PI);
+                let a = scale(bsdf.eval(wi, wo).unwrap(), 1.0 / wo.z);
+                let b = scale(bsdf.eval(wo, wi).unwrap(), 1.0 / wi.z);
+                let tol = 1e-3 * (1.0 + a.r.max(a.g).max(a.b));
+                assert_close(a, b, tol, name);
+            }
+        }
+
+        // Transmission (wi outside, wo inside): with the radiance scaling,
+        // f(wi, wo) / eta_i^2 = f(wo, wi) / eta_o^2 (Veach 1997)
+        let eta: f32 = 1.5;
+        let bsdf = BSDFModel::new(&rough_dielectric(MicrofacetType::GGX, true)).unwrap();
+        let mut nonzero = 0;
+        for _ in 0..100 {
+            let wi = direction(sampler.next() * 1.5, sampler.next() * 2.0 * PI);
+            let wo = -direction(sampler.next() * 1.5, sampler.next() * 2.0 * PI);
+            let a = scale(bsdf.eval(wi, wo), eta * eta / wo.z.abs());
+            let b = scale(bsdf.eval(wo, wi), 1.0 / wi.z.abs());
+            if a.r > 0.0 {
+                nonzero += 1;
+            }
+            let tol = 1e-3 * (1.0 + a.r.max(a.g).max(a.b));
+            assert_close(a, b, tol, "roughdielectric transmission");
+        }
+        assert!(nonzero > 10);
+    }
+
+    #[test]
+    fn textured_parameters() {
+        let bsdf = BSDF::Diffuse {
+            reflectance: BSDFColor::Texture(crate::Texture::Checkerboard {
+                color0: Box::new(c("0.4")),
+                color1: Box::new(c("0.2")),
+                to_uv: crate::UVTransform::default(),
+            }),
+        };
+        let wi = direction(0.0, 0.0);
+        assert!(bsdf.eval(wi, wi).is_err());
+    }
+
+    #[test]
+    fn unsupported() {
+        // Mitsuba's rough transmittance tables are required
+        let bsdf = BSDF::Plastic {
+            distribution: Some(distribution(MicrofacetType::GGX, 0.3, 0.3, true)),
+            int_ior: 1.49,
+            ext_ior: 1.0,
+            specular_reflectance: c("1.0"),
+            diffuse_reflectance: c("0.5"),
+            nonlinear: false,
+        };
+        let wi = direction(0.0, 0.0);
+        assert!(matches!(bsdf.eval(wi, wi), Err(Error::Unsupported(_))));
+    }
+}
@@ -0,0 +1,517 @@
+//! Image decoding for `bitmap` textures and `envmap` emitters.
+//!
+//! PNG, JPEG, OpenEXR and Radiance HDR files are decoded with the `image`
+//! crate, PFM files are read directly. As in Mitsuba, low dynamic range
+//! formats are assumed to be sRGB encoded unless a `gamma` is given, while
+//! high dynamic range formats are linear.
+use crate::{BSDFColor, Emitter, Error, FilterType, Result, Texture, TextureScale, WrapMode, RGB};
+
+use cgmath::Point2;
+use std::collections::HashMap;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Decoded image of a `bitmap` texture. The same file gives different
+/// images depending on the `gamma` and `channel` of the texture.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitmapKey {
+    filename: String,
+    gamma: Option<u32>, // f32 bits
+    channel: Option<String>,
+}
+
+impl BitmapKey {
+    /// Key of a `bitmap` texture (None for other textures)
+    pub fn new(texture: &Texture) -> Option<Self> {
+        match texture {
+            Texture::Bitmap {
+                filename,
+                gamma,
+                channel,
+                ..
+            } => Some(BitmapKey {
+                filename: filename.clone(),
+                gamma: gamma.map(f32::to_bits),
+                channel: channel.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+}
+
+/// Linear float image (rows stored from top to bottom)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// 1 (selected channel), 3 (RGB) or 4 (RGBA)
+    pub channels: usize,
+    pub data: Vec<f32>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: usize, y: usize) -> &[f32] {
+        let i = (y * self.width + x) * self.channels;
+        &self.data[i..i + self.channels]
+    }
+
+    // Texel with the wrap mode applied (zero and one give a constant outside)
+    fn texel(&self, x: i64, y: i64, wrap_u: WrapMode, wrap_v: WrapMode) -> RGB {
+        let wrap = |v: i64, size: usize, mode: WrapMode| -> std::result::Result<usize, f32> {
+            let size = size as i64;
+            match mode {
+                WrapMode::Repeat => Ok(v.rem_euclid(size) as usize),
+                WrapMode::Clamp => Ok(v.max(0).min(size - 1) as usize),
+                WrapMode::Mirror => {
+                    let v = v.rem_euclid(2 * size);
+                    Ok(if v >= size { 2 * size - v - 1 } else { v } as usize)
+                }
+                WrapMode::Zero if v < 0 || v >= size => Err(0.0),
+                WrapMode::One if v < 0 || v >= size => Err(1.0),
+                WrapMode::Zero | WrapMode::One => Ok(v as usize),
+            }
+        };
+        match (wrap(x, self.width, wrap_u), wrap(y, self.height, wrap_v)) {
+            (Ok(x), Ok(y)) => {
+                let p = self.pixel(x, y);
+                if self.channels == 1 {
+                    RGB {
+                        r: p[0],
+                        g: p[0],
+                        b: p[0],
+                    }
+                } else {
+                    RGB {
+                        r: p[0],
+                        g: p[1],
+                        b: p[2],
+                    }
+                }
+            }
+            (Err(v), _) | (_, Err(v)) => RGB { r: v, g: v, b: v },
+        }
+    }
+
+    /// Look up the image at `uv` (the origin is the top left corner).
+    /// Without ray differentials, EWA and trilinear filtering reduce to
+    /// a bilinear interpolation.
+    pub fn eval(
+        &self,
+        uv: Point2<f32>,
+        wrap_u: WrapMode,
+        wrap_v: WrapMode,
+        filter: FilterType,
+    ) -> RGB {
+        let x = uv.x * self.width as f32;
+        let y = uv.y * self.height as f32;
+        if filter == FilterType::Nearest {
+            return self.texel(x.floor() as i64, y.floor() as i64, wrap_u, wrap_v);
+        }
+
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let lerp = |a: RGB, b: RGB, t: f32| RGB {
+            r: a.r * (1.0 - t) + b.r * t,
+            g: a.g * (1.0 - t) + b.g * t,
+            b: a.b * (1.0 - t) + b.b * t,
+        };
+        let top = lerp(
+            self.texel(x0, y0, wrap_u, wrap_v),
+            self.texel(x0 + 1, y0, wrap_u, wrap_v),
+            dx,
+        );
+        let bottom = lerp(
+            self.texel(x0, y0 + 1, wrap_u, wrap_v),
+            self.texel(x0 + 1, y0 + 1, wrap_u, wrap_v),
+            dx,
+        );
+        lerp(top, bottom, dy)
+    }
+
+    // Keep a single channel (r, g, b or a)
+    fn select_channel(self, channel: &str) -> Result<Image> {
+        let index = match channel {
+            "r" => 0,
+            "g" => 1,
+            "b" => 2,
+            "a" if self.channels == 4 => 3,
+            _ => {
+                return Err(Error::InvalidParameter(
+                    "channel".to_string(),
+                    format!(
+                        "{:?} is not a valid channel (r, g, b or a if the image has alpha)",
+                        channel
+                    ),
+                ))
+            }
+        };
+        let data = self.data.chunks(self.channels).map(|p| p[index]).collect();
+        Ok(Image {
+            width: self.width,
+            height: self.height,
+            channels: 1,
+            data,
+        })
+    }
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Other(Box::new(e))
+}
+
+fn pfm_error(reason: &str) -> Error {
+    Error::Other(Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("PFM: {}", reason),
+    )))
+}
+
+/// Decode a PFM file (Portable Float Map)
+pub fn parse_pfm<R: BufRead>(f: &mut R) -> Result<Image> {
+    // The header is made of three tokens followed by a single whitespace
+    let mut token = || -> Result<String> {
+        let mut token = vec![];
+        let mut byte = [0; 1];
+        loop {
+            f.read_exact(&mut byte).map_err(io_error)?;
+            if byte[0].is_ascii_whitespace() {
+                if token.is_empty() {
+                    continue;
+                }
+                return String::from_utf8(token).map_err(|_| pfm_error("invalid header"));
+            }
+            token.push(byte[0]);
+        }
+    };
+    let channels = match &token()?[..] {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(pfm_error("invalid magic number")),
+    };
+    let mut dimension = || -> Result<usize> {
+        token()?
+            .parse::<usize>()
+            .map_err(|_| pfm_error("invalid dimension"))
+    };
+    let width = dimension()?;
+    let height = dimension()?;
+    let scale = token()?
+        .parse::<f32>()
+        .map_err(|_| pfm_error("invalid scale"))?;
+
+    let mut bytes = vec![0; width * height * channels * 4];
+    f.read_exact(&mut bytes).map_err(io_error)?;
+    let values = bytes
+        .chunks(4)
+        .map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            // A negative scale denotes little endian data
+            if scale < 0.0 {
+                f32::from_le_bytes(b)
+            } else {
+                f32::from_be_bytes(b)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Rows are stored from bottom to top, gray images are expanded to RGB
+    let mut data = Vec::with_capacity(width * height * 3);
+    for row in values.chunks(width * channels).rev() {
+        for p in row.chunks(channels) {
+            data.extend_from_slice(&[p[0], p[channels / 2], p[channels - 1]]);
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        channels: 3,
+        data,
+    })
+}
+
+// Decode the image and indicates if it is low dynamic range
+fn decode(filename: &Path) -> Result<(Image, bool)> {
+    let extension = filename
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    if extension.as_deref() == Some("pfm") {
+        let f = File::open(filename).map_err(io_error)?;
+        return Ok((parse_pfm(&mut BufReader::new(f))?, false));
+    }
+
+    let image = image::open(filename).map_err(|e| Error::Other(Box::new(e)))?;
+    let ldr = !matches!(
+        image,
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    );
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let image = if image.color().has_alpha() {
+        Image {
+            width,
+            height,
+            channels: 4,
+            data: image.to_rgba32f().into_raw(),
+        }
+    } else {
+        Image {
+            width,
+            height,
+            channels: 3,
+            data: image.to_rgb32f().into_raw(),
+        }
+    };
+    Ok((image, ldr))
+}
+
+/// Read an image (`filename` relative to `wk`) as linear values.
+/// `gamma` overrides the encoding of the file (-1 for sRGB) and
+/// `channel` (r, g, b or a) keeps only one channel.
+pub fn read_image(
+    filename: &str,
+    gamma: Option<f32>,
+    channel: Option<&str>,
+    wk: &Path,
+) -> Result<Image> {
+    let filename = wk.join(Path::new(filename));
+    let (mut image, ldr) = decode(&filename)?;
+
+    // The alpha channel is always linear
+    let color_channels = image.channels.min(3);
+    let convert: Option<Box<dyn Fn(f32) -> f32>> = match gamma {
+        Some(-1.0) => Some(Box::new(srgb_to_linear)),
+        Some(g) if g != 1.0 => Some(Box::new(move |v: f32| v.max(0.0).powf(g))),
+        Some(_) => None,
+        None if ldr => Some(Box::new(srgb_to_linear)),
+        None => None,
+    };
+    if let Some(convert) = convert {
+        for p in image.data.chunks_mut(image.channels) {
+            for v in &mut p[..color_channels] {
+                *v = convert(*v);
+            }
+        }
+    }
+
+    match channel {
+        Some(c) => image.select_channel(c),
+        None => Ok(image),
+    }
+}
+
+/// Read the image of a `bitmap` texture (`filename` relative to `wk`)
+pub fn read_bitmap(texture: &Texture, wk: &Path) -> Result<Image> {
+    match texture {
+        Texture::Bitmap {
+            filename,
+            gamma,
+            channel,
+            ..
+        } => read_image(filename, *gamma, channel.as_deref(), wk),
+        _ => Err(Error::Unsupported(format!(
+            "image loading of the texture {:?}",
+            texture
+        ))),
+    }
+}
+
+/// Read the images of the bitmaps used by a texture (including nested
+/// textures), see `Texture::eval_with_images`
+pub fn read_bitmaps(texture: &Texture, wk: &Path) -> Result<HashMap<BitmapKey, Image>> {
+    fn collect<'a>(texture: &'a Texture, bitmaps: &mut Vec<&'a Texture>) {
+        match texture {
+            Texture::Bitmap { .. } => bitmaps.push(texture),
+            Texture::Checkerboard { color0, color1, .. } => {
+                for c in &[color0, color1] {
+                    if let BSDFColor::Texture(t) = &***c {
+                        collect(t, bitmaps);
+                    }
+                }
+            }
+            Texture::Scale { texture, value } => {
+                collect(texture, bitmaps);
+                if let TextureScale::Texture(t) = value {
+                    collect(t, bitmaps);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut bitmaps = vec![];
+    collect(texture, &mut bitmaps);
+    let mut images = HashMap::new();
+    for b in bitmaps {
+        if let std::collections::hash_map::Entry::Vacant(e) =
+            images.entry(BitmapKey::new(b).unwrap())
+        {
+            e.insert(read_bitmap(b, wk)?);
+        }
+    }
+    Ok(images)
+}
+
+/// Read the image of an `envmap` emitter (`filename` relative to `wk`).
+/// The emitter `scale` is not applied.
+pub fn read_envmap(emitter: &Emitter, wk: &Path) -> Result<Image> {
+    match emitter {
+        Emitter::EnvMap {
+            filename, gamma, ..
+        } => read_image(filename, *gamma, None, wk),
+        _ => Err(Error::Unsupported(format!(
+            "image loading of the emitter {:?}",
+            emitter
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn pfm(magic: &str, values: &[f32]) -> Vec<u8> {
+        let mut data = format!("{}\n2 2\n-1.0\n", magic).into_bytes();
+        for v in values {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn read_pfm() {
+        // Bottom row first
+        let values = [1.0, 2.0, 3.0, 4.0];
+        let image = parse_pfm(&mut Cursor::new(pfm("Pf", &values))).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 2, 3));
+        assert_eq!(image.pixel(0, 0), &[3.0, 3.0, 3.0]);
+        assert_eq!(image.pixel(1, 1), &[2.0, 2.0, 2.0]);
+
+        let values = (0..12).map(|v| v as f32).collect::<Vec<_>>();
+        let image = parse_pfm(&mut Cursor::new(pfm("PF", &values))).unwrap();
+        assert_eq!(image.pixel(1, 0), &[9.0, 10.0, 11.0]);
+        assert!(parse_pfm(&mut Cursor::new(pfm("P6", &values))).is_err());
+    }
+
+    #[test]
+    fn gamma_and_channel() {
+        let wk = std::env::temp_dir();
+        let png = image::RgbImage::from_raw(2, 1, vec![0, 128, 255, 255, 255, 255]).unwrap();
+        png.save(wk.join("mitsuba_rs_image.png")).unwrap();
+
+        // sRGB by default
+        let image = read_image("mitsuba_rs_image.png", None, None, &wk).unwrap();
+        assert_eq!(image.channels, 3);
+        assert_eq!(image.pixel(0, 0)[0], 0.0);
+        assert!((image.pixel(0, 0)[1] - 0.2158605).abs() < 1e-4);
+        assert_eq!(image.pixel(1, 0), &[1.0, 1.0, 1.0]);
+
+        let image = read_image("mitsuba_rs_image.png", Some(1.0), Some("g"), &wk).unwrap();
+        assert_eq!(image.channels, 1);
+        assert!((image.data[0] - 128.0 / 255.0).abs() < 1e-6);
+        assert!(read_image("mitsuba_rs_image.png", None, Some("a"), &wk).is_err());
+
+        // High dynamic range images are linear
+        std::fs::write(wk.join("mitsuba_rs_image.pfm"), pfm("Pf", &[0.5; 4])).unwrap();
+        let image = read_image("mitsuba_rs_image.pfm", None, None, &wk).unwrap();
+        assert_eq!(image.data, vec![0.5; 12]);
+        let image = read_image("mitsuba_rs_image.pfm", Some(2.0), None, &wk).unwrap();
+        assert_eq!(image.data, vec![0.25; 12]);
+    }
+
+    #[test]
+    fn lookup() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            channels: 1,
+            data: vec![0.0, 1.0],
+        };
+        let eval = |u: f32, wrap: WrapMode, filter: FilterType| {
+            image
+                .eval(Point2::new(u, 0.5), wrap, WrapMode::Clamp, filter)
+                .r
+        };
+        assert_eq!(eval(0.25, WrapMode::Repeat, FilterType::EWA), 0.0);
+        assert_eq!(eval(0.5, WrapMode::Repeat, FilterType::Trilinear), 0.5);
+        assert_eq!(eval(0.6, WrapMode::Repeat, FilterType::Nearest), 1.0);
+        assert_eq!(eval(1.25, WrapMode::Repeat, FilterType::Nearest), 0.0);
+        assert_eq!(eval(1.25, WrapMode::Mirror, FilterType::Nearest), 1.0);
+        assert_eq!(eval(1.25, WrapMode::Clamp, FilterType::Nearest), 1.0);
+        assert_eq!(eval(-0.5, WrapMode::One, FilterType::Nearest), 1.0);
+        assert_eq!(eval(1.5, WrapMode::Zero, FilterType::Nearest), 0.0);
+        // Bilinear interpolation with the border
+        assert_eq!(eval(0.0, WrapMode::Repeat, FilterType::EWA), 0.5);
+        assert_eq!(eval(0.0, WrapMode::Clamp, FilterType::EWA), 0.0);
+
+        let texture = crate::Texture::Bitmap {
+            filename: "image.pfm".to_string(),
+            wrap_mode_u: WrapMode::Repeat,
+            wrap_mode_v: WrapMode::Repeat,
+            filter_type: FilterType::Nearest,
+            max_anisotropy: 20.0,
+            gamma: None,
+            channel: None,
+            cache: None,
+            to_uv: crate::UVTransform::new(
+                cgmath::Vector2::new(0.5, 0.0),
+                cgmath::Vector2::new(1.0, 1.0),
+            ),
+        };
+        let mut images = HashMap::new();
+        images.insert(BitmapKey::new(&texture).unwrap(), image.clone());
+        let v = texture
+            .eval_with_images(Point2::new(0.25, 0.5), &images)
+            .unwrap();
+        assert_eq!(v.g, 1.0);
+        assert!(texture
+            .eval_with_images(Point2::new(0.25, 0.5), &HashMap::new())
+            .is_err());
+    }
+
+    #[test]
+    fn bitmaps_channels() {
+        // Two channels of the same file (e.g., roughness and metallic)
+        let wk = std::env::temp_dir();
+        let values = [0.1, 0.2, 0.3].repeat(4);
+        std::fs::write(wk.join("mitsuba_rs_channels.pfm"), pfm("PF", &values)).unwrap();
+        let bitmap = |channel: &str| crate::Texture::Bitmap {
+            filename: "mitsuba_rs_channels.pfm".to_string(),
+            wrap_mode_u: WrapMode::Repeat,
+            wrap_mode_v: WrapMode::Repeat,
+            filter_type: FilterType::Nearest,
+            max_anisotropy: 20.0,
+            gamma: None,
+            channel: Some(channel.to_string()),
+            cache: None,
+            to_uv: crate::UVTransform::default(),
+        };
+        let texture = crate::Texture::Checkerboard {
+            color0: Box::new(BSDFColor::Texture(bitmap("r"))),
+            color1: Box::new(BSDFColor::Texture(bitmap("g"))),
+            to_uv: crate::UVTransform::default(),
+        };
+        let images = read_bitmaps(&texture, &wk).unwrap();
+        assert_eq!(images.len(), 2);
+        let r = texture.eval_with_images(Point2::new(0.25, 0.25), &images);
+        let g = texture.eval_with_images(Point2::new(0.75, 0.25), &images);
+        assert_eq!(r.unwrap().r, 0.1);
+        assert_eq!(g.unwrap().r, 0.2);
+    }
+}
@@ -0,0 +1,147 @@
+//! Dielectric index of refraction catalogue.
+//!
+//! Named media follow Mitsuba's table (`intIOR`/`extIOR` parameters).
+//! Some common glasses also provide dispersion formulas to evaluate
+//! the index of refraction at a given wavelength.
+use crate::{Error, Result, Value};
+
+// IOR at 20°C (from Mitsuba's documentation)
+const IOR_DATA: &[(&str, f32)] = &[
+    ("vacuum", 1.0),
+    ("helium", 1.000036),
+    ("hydrogen", 1.000132),
+    ("air", 1.000277),
+    ("carbon dioxide", 1.00045),
+    ("water", 1.3330),
+    ("acetone", 1.36),
+    ("ethanol", 1.361),
+    ("carbon tetrachloride", 1.461),
+    ("glycerol", 1.4729),
+    ("benzene", 1.501),
+    ("silicone oil", 1.52045),
+    ("bromine", 1.661),
+    ("water ice", 1.31),
+    ("fused quartz", 1.458),
+    ("pyrex", 1.470),
+    ("acrylic glass", 1.49),
+    ("polypropylene", 1.49),
+    ("bk7", 1.5046),
+    ("sodium chloride", 1.544),
+    ("amber", 1.55),
+    ("pet", 1.5750),
+    ("diamond", 2.419),
+];
+
+/// Wavelength dependent index of refraction (λ in nm)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    /// `n² = 1 + Σ b_i λ² / (λ² - c_i)` (λ in μm, c_i in μm²)
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+    /// `n = a + b / λ²` (λ in μm, b in μm²)
+    Cauchy { a: f32, b: f32 },
+}
+impl Dispersion {
+    /// Index of refraction at the wavelength `lambda` (nm)
+    pub fn eval(&self, lambda: f32) -> f32 {
+        let l2 = (lambda * 1e-3) * (lambda * 1e-3);
+        match self {
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>();
+                n2.sqrt()
+            }
+            Dispersion::Cauchy { a, b } => a + b / l2,
+        }
+    }
+}
+
+#[rustfmt::skip]
+const DISPERSION_DATA: &[(&str, Dispersion)] = &[
+    // Schott N-BK7 datasheet
+    ("bk7", Dispersion::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_4],
+        c: [0.006_000_698_7, 0.020_017_914, 103.560_65],
+    }),
+    // Malitson (1965)
+    ("fused quartz", Dispersion::Sellmeier {
+        b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+        c: [0.004_679_148, 0.013_512_06, 97.934],
+    }),
+    // Peter (1923)
+    ("diamond", Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    }),
+];
+
+/// Names of all the media known by Mitsuba
+pub fn ior_names() -> Vec<&'static str> {
+    IOR_DATA.iter().map(|(n, _)| *n).collect()
+}
+
+/// Lookup the index of refraction of a named medium (case insensitive).
+///
+/// Numeric strings (e.g. `"1.5"`) are also accepted.
+pub fn lookup_ior(name: &str) -> Result<f32> {
+    if let Ok(v) = name.trim().parse::<f32>() {
+        return Ok(v);
+    }
+    let lower = name.trim().to_lowercase();
+    match IOR_DATA.iter().find(|(n, _)| *n == lower) {
+        Some((_, v)) => Ok(*v),
+        None => Err(Error::UnknownMaterial(
+            name.to_string(),
+            ior_names().join(", "),
+        )),
+    }
+}
+
+/// Lookup the dispersion formula of a named medium (case insensitive).
+///
+/// Only some glasses provide one (`bk7`, `fused quartz` and `diamond`).
+pub fn lookup_dispersion(name: &str) -> Option<Dispersion> {
+    let lower = name.trim().to_lowercase();
+    DISPERSION_DATA
+        .iter()
+        .find(|(n, _)| *n == lower)
+        .map(|(_, d)| *d)
+}
+
+impl Value {
+    /// Index of refraction given as a float, a numeric string or a named medium
+    pub fn as_ior(self) -> Result<f32> {
+        match self {
+            Value::Float(v) => Ok(v),
+            Value::Integer(v) => Ok(v as f32),
+            Value::String(v) => lookup_ior(&v),
+            _ => Err(Error::Value("as_ior", self)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_and_numeric() {
+        assert_eq!(lookup_ior("BK7").unwrap(), 1.5046);
+        assert_eq!(lookup_ior("water ice").unwrap(), 1.31);
+        assert_eq!(lookup_ior("1.33").unwrap(), 1.33);
+        assert_eq!(Value::Float(1.2).as_ior().unwrap(), 1.2);
+        assert!(lookup_ior("kryptonite").is_err());
+    }
+
+    #[test]
+    fn dispersion() {
+        // Reference values at the sodium D line (587.6nm)
+        let bk7 = lookup_dispersion("bk7").unwrap();
+        assert!((bk7.eval(587.6) - 1.5168).abs() < 1e-3);
+        let quartz = lookup_dispersion("fused quartz").unwrap();
+        assert!((quartz.eval(587.6) - 1.4585).abs() < 1e-3);
+        let diamond = lookup_dispersion("diamond").unwrap();
+        assert!((diamond.eval(587.6) - 2.4175).abs() < 2e-3);
+        // Normal dispersion: blue is refracted more than red
+        assert!(bk7.eval(450.0) > bk7.eval(650.0));
+        assert!(lookup_dispersion("air").is_none());
+    }
+}
@@ -2,23 +2,27 @@ extern crate cgmath;
 extern crate xml;
 #[macro_use]
 extern crate lazy_static;
-#[cfg(feature = "serialized")]
+#[cfg(any(feature = "serialized", feature = "measured"))]
 extern crate byteorder;
 #[cfg(feature = "serialized")]
 extern crate miniz_oxide;
 #[cfg(feature = "serialized")]
 #[macro_use]
 extern crate bitflags;
+#[cfg(feature = "images")]
+extern crate image;
 #[cfg(feature = "ply")]
 extern crate ply_rs;
 #[macro_use]
 extern crate quick_error;
 
 use cgmath::*;
+use scattering::ScatteringParameters;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Read;
+use weave::{WeaveParameter, WeavePattern};
 use xml::reader::{EventReader, Events, XmlEvent};
 
 quick_error! {
@@ -36,6 +40,18 @@ quick_error! {
         UnknownReference(name: String) {
             display("Unknown reference (name: {:?})", name)
         }
+        /// Unknown named material
+        UnknownMaterial(name: String, valid: String) {
+            display("Unknown material {:?} (valid names: {})", name, valid)
+        }
+        /// Invalid parameter (value or combination)
+        InvalidParameter(name: String, reason: String) {
+            display("Invalid parameter {}: {}", name, reason)
+        }
+        /// Feature not supported (e.g., evaluation of a textured parameter)
+        Unsupported(what: String) {
+            display("Unsupported: {}", what)
+        }
         /// Attribute not found
         AttribNotFound(name: String, additional_info: String) {
             display("Impossible to found {} attribute when parsing {}", name, additional_info)
@@ -48,116 +64,7 @@ quick_error! {
 }
 type Result<T> = std::result::Result<T, Error>;
 
-lazy_static! {
-    static ref IOR_DATA: HashMap<String, f32> = {
-        let mut m = HashMap::new();
-        m.insert("vacuum".to_string(), 1.0);
-        m.insert("helium".to_string(), 1.000036);
-        m.insert("hydrogen".to_string(), 1.000132);
-        m.insert("air".to_string(), 1.000277);
-        m.insert("carbon dioxide".to_string(), 1.00045);
-        m.insert("water".to_string(), 1.3330);
-        m.insert("acetone".to_string(), 1.36);
-        m.insert("ethanol".to_string(), 1.361);
-        m.insert("carbon tetrachloride".to_string(), 1.461);
-        m.insert("glycerol".to_string(), 1.4729);
-        m.insert("benzene".to_string(), 1.501);
-        m.insert("silicone oil".to_string(), 1.52045);
-        m.insert("bromine".to_string(), 1.661);
-        m.insert("water ice".to_string(), 1.31);
-        m.insert("fused quartz".to_string(), 1.458);
-        m.insert("pyrex".to_string(), 1.470);
-        m.insert("acrylic glass".to_string(), 1.49);
-        m.insert("polypropylene".to_string(), 1.49);
-        m.insert("bk7".to_string(), 1.5046);
-        m.insert("sodium chloride".to_string(), 1.544);
-        m.insert("amber".to_string(), 1.55);
-        m.insert("pet".to_string(), 1.5750);
-        m.insert("diamond".to_string(), 2.419);
-        m
-    };
-}
-
-lazy_static! {
-    // Precomputed (eta, k) in RGB from Mitsuba
-    static ref MATERIALS: HashMap<String, (String, String)> = {
-        let mut m = HashMap::new();
-        m.insert("a-C".to_string(), ("2.9296, 2.22909, 1.97023".to_string(), "0.892218, 0.791926, 0.815701".to_string()));
-        m.insert("Ag".to_string(), ("0.155518, 0.116786, 0.138372".to_string(), "4.83241, 3.12322, 2.14934".to_string()));
-        m.insert("AlAs_palik".to_string(), ("3.60456, 3.23388, 2.20695".to_string(), "0.000688883, -0.000497156, 0.00753".to_string()));
-        m.insert("AlAs".to_string(), ("3.60456, 3.23388, 2.20695".to_string(), "0.000688883, -0.000497156, 0.00753".to_string()));
-        m.insert("AlSb_palik".to_string(), ("-0.0339369, 4.14454, 4.6468".to_string(), "-0.0330091, 0.10024, 1.29296".to_string()));
-        m.insert("AlSb".to_string(), ("-0.0339369, 4.14454, 4.6468".to_string(), "-0.0330091, 0.10024, 1.29296".to_string()));
-        m.insert("Al".to_string(), ("1.66026, 0.881462, 0.521613".to_string(), "9.22807, 6.27106, 4.84111".to_string()));
-        m.insert("Au".to_string(), ("0.143552, 0.377438, 1.43825".to_string(), "3.98397, 2.38495, 1.60434".to_string()));
-        m.insert("Be_palik".to_string(), ("4.17079, 3.18615, 2.78579".to_string(), "3.84741, 3.00959, 2.86869".to_string()));
-        m.insert("Be".to_string(), ("4.17079, 3.18615, 2.78579".to_string(), "3.84741, 3.00959, 2.86869".to_string()));
-        m.insert("Cr".to_string(), ("4.48822, 2.90684, 1.66261".to_string(), "5.21422, 4.2289, 3.75312".to_string()));
-        m.insert("CsI_palik".to_string(), ("2.14445, 1.70236, 1.66293".to_string(), "0, 0, 0".to_string()));
-        m.insert("CsI".to_string(), ("2.14445, 1.70236, 1.66293".to_string(), "0, 0, 0".to_string()));
-        m.insert("Cu2O_palik".to_string(), ("3.54545, 2.94339, 2.71336".to_string(), "0.120343, 0.205637, 0.637298".to_string()));
-        m.insert("Cu2O".to_string(), ("3.54545, 2.94339, 2.71336".to_string(), "0.120343, 0.205637, 0.637298".to_string()));
-        m.insert("CuO_palik".to_string(), ("3.25286, 2.44938, 2.2043".to_string(), "0.520388, 0.569286, 0.726005".to_string()));
-        m.insert("CuO".to_string(), ("3.25286, 2.44938, 2.2043".to_string(), "0.520388, 0.569286, 0.726005".to_string()));
-        m.insert("Cu_palik".to_string(), ("0.241275, 0.903804, 1.10182".to_string(), "3.95032, 2.46606, 2.1349".to_string()));
-        m.insert("Cu".to_string(), ("0.208084, 0.919438, 1.10263".to_string(), "3.92329, 2.45611, 2.14264".to_string()));
-        m.insert("d-C_palik".to_string(), ("2.71105, 2.31816, 2.23336".to_string(), "0, 0, 0".to_string()));
-        m.insert("d-C".to_string(), ("2.71105, 2.31816, 2.23336".to_string(), "0, 0, 0".to_string()));
-        m.insert("Hg_palik".to_string(), ("2.42652, 1.45642, 0.914459".to_string(), "6.34803, 4.39248, 3.42748".to_string()));
-        m.insert("HgTe_palik".to_string(), ("4.77919, 3.23163, 2.66017".to_string(), "1.63198, 1.58015, 1.72981".to_string()));
-        m.insert("HgTe".to_string(), ("4.77919, 3.23163, 2.66017".to_string(), "1.63198, 1.58015, 1.72981".to_string()));
-        m.insert("Hg".to_string(), ("2.42652, 1.45642, 0.914459".to_string(), "6.34803, 4.39248, 3.42748".to_string()));
-        m.insert("Ir_palik".to_string(), ("3.08243, 2.08491, 1.62028".to_string(), "5.59771, 4.06635, 3.27186".to_string()));
-        m.insert("Ir".to_string(), ("3.08243, 2.08491, 1.62028".to_string(), "5.59771, 4.06635, 3.27186".to_string()));
-        m.insert("K_palik".to_string(), ("0.0621071, 0.0466027, 0.0384292".to_string(), "2.12697, 1.3591, 0.9177".to_string()));
-        m.insert("K".to_string(), ("0.0621071, 0.0466027, 0.0384292".to_string(), "2.12697, 1.3591, 0.9177".to_string()));
-        m.insert("Li_palik".to_string(), ("0.269451, 0.200441, 0.223339".to_string(), "3.54131, 2.3517, 1.68708".to_string()));
-        m.insert("Li".to_string(), ("0.269451, 0.200441, 0.223339".to_string(), "3.54131, 2.3517, 1.68708".to_string()));
-        m.insert("MgO_palik".to_string(), ("2.08988, 1.65047, 1.5956".to_string(), "4.38534e-12, -3.64587e-12, 2.53198e-11".to_string()));
-        m.insert("MgO".to_string(), ("2.08988, 1.65047, 1.5956".to_string(), "4.38534e-12, -3.64587e-12, 2.53198e-11".to_string()));
-        m.insert("Mo_palik".to_string(), ("4.49907, 3.51237, 2.78469".to_string(), "4.12556, 3.4205, 3.15286".to_string()));
-        m.insert("Mo".to_string(), ("4.49907, 3.51237, 2.78469".to_string(), "4.12556, 3.4205, 3.15286".to_string()));
-        m.insert("Na_palik".to_string(), ("0.0607451, 0.0557257, 0.061748".to_string(), "3.20798, 2.12669, 1.58797".to_string()));
-        m.insert("Nb_palik".to_string(), ("3.40884, 2.78681, 2.39788".to_string(), "3.44282, 2.73855, 2.57476".to_string()));
-        m.insert("Nb".to_string(), ("3.40884, 2.78681, 2.39788".to_string(), "3.44282, 2.73855, 2.57476".to_string()));
-        m.insert("Ni_palik".to_string(), ("2.36454, 1.66584, 1.46819".to_string(), "4.48772, 3.05528, 2.34846".to_string()));
-        m.insert("Rh_palik".to_string(), ("2.59089, 1.86185, 1.55039".to_string(), "6.79501, 4.70811, 3.9766".to_string()));
-        m.insert("Rh".to_string(), ("2.59089, 1.86185, 1.55039".to_string(), "6.79501, 4.70811, 3.9766".to_string()));
-        m.insert("Se-e_palik".to_string(), ("5.56086, 4.22302, 4.0475".to_string(), "0.761602, 1.0705, 1.5996".to_string()));
-        m.insert("Se-e".to_string(), ("5.56086, 4.22302, 4.0475".to_string(), "0.761602, 1.0705, 1.5996".to_string()));
-        m.insert("Se_palik".to_string(), ("3.9737, 2.88842, 2.82447".to_string(), "0.631111, 0.6311, 0.54084".to_string()));
-        m.insert("Se".to_string(), ("3.9737, 2.88842, 2.82447".to_string(), "0.631111, 0.6311, 0.54084".to_string()));
-        m.insert("SiC_palik".to_string(), ("3.17069, 2.52702, 2.47947".to_string(), "1.54029e-06, -1.49586e-06, 1.47636e-05".to_string()));
-        m.insert("SiC".to_string(), ("3.17069, 2.52702, 2.47947".to_string(), "1.54029e-06, -1.49586e-06, 1.47636e-05".to_string()));
-        m.insert("SnTe_palik".to_string(), ("4.538, 1.9804, 1.2824".to_string(), "0, 0, 0".to_string()));
-        m.insert("SnTe".to_string(), ("4.538, 1.9804, 1.2824".to_string(), "0, 0, 0".to_string()));
-        m.insert("Ta_palik".to_string(), ("2.05979, 2.38107, 2.62559".to_string(), "2.43996, 1.74619, 1.94588".to_string()));
-        m.insert("Ta".to_string(), ("2.05979, 2.38107, 2.62559".to_string(), "2.43996, 1.74619, 1.94588".to_string()));
-        m.insert("Te-e_palik".to_string(), ("7.48425, 4.31476, 2.37063".to_string(), "5.59077, 4.93514, 4.00092".to_string()));
-        m.insert("Te-e".to_string(), ("7.48425, 4.31476, 2.37063".to_string(), "5.59077, 4.93514, 4.00092".to_string()));
-        m.insert("Te_palik".to_string(), ("7.36846, 4.4946, 2.63798".to_string(), "3.26738, 3.51453, 3.2917".to_string()));
-        m.insert("Te".to_string(), ("7.36846, 4.4946, 2.63798".to_string(), "3.26738, 3.51453, 3.2917".to_string()));
-        m.insert("ThF4_palik".to_string(), ("1.83057, 1.44222, 1.38804".to_string(), "0, 0, 0".to_string()));
-        m.insert("ThF4".to_string(), ("1.83057, 1.44222, 1.38804".to_string(), "0, 0, 0".to_string()));
-        m.insert("TiC_palik".to_string(), ("3.82591, 2.83346, 2.59154".to_string(), "3.18532, 2.41109, 2.1752".to_string()));
-        m.insert("TiC".to_string(), ("3.82591, 2.83346, 2.59154".to_string(), "3.18532, 2.41109, 2.1752".to_string()));
-        m.insert("TiN_palik".to_string(), ("1.70939, 1.20202, 1.35264".to_string(), "3.02375, 2.10671, 1.1688".to_string()));
-        m.insert("TiN".to_string(), ("1.70939, 1.20202, 1.35264".to_string(), "3.02375, 2.10671, 1.1688".to_string()));
-        m.insert("TiO2-e_palik".to_string(), ("3.10648, 2.51369, 2.585".to_string(), "6.15808e-05, -5.38042e-05, 0.000381368".to_string()));
-        m.insert("TiO2-e".to_string(), ("3.10648, 2.51369, 2.585".to_string(), "6.15808e-05, -5.38042e-05, 0.000381368".to_string()));
-        m.insert("TiO2_palik".to_string(), ("3.45676, 2.80243, 2.90704".to_string(), "0.000130815, -0.000114216, 0.000809353".to_string()));
-        m.insert("TiO2".to_string(), ("3.45676, 2.80243, 2.90704".to_string(), "0.000130815, -0.000114216, 0.000809353".to_string()));
-        m.insert("VC_palik".to_string(), ("3.67232, 2.77627, 2.53826".to_string(), "2.97645, 2.25387, 1.97359".to_string()));
-        m.insert("VC".to_string(), ("3.67232, 2.77627, 2.53826".to_string(), "2.97645, 2.25387, 1.97359".to_string()));
-        m.insert("VN_palik".to_string(), ("2.87842, 2.14604, 1.94788".to_string(), "2.83558, 2.13656, 1.64581".to_string()));
-        m.insert("VN".to_string(), ("2.87842, 2.14604, 1.94788".to_string(), "2.83558, 2.13656, 1.64581".to_string()));
-        m.insert("V_palik".to_string(), ("4.27582, 3.50453, 2.76487".to_string(), "3.50165, 2.89143, 3.10752".to_string()));
-        m.insert("W".to_string(), ("4.37464, 3.29988, 2.99998".to_string(), "3.49872, 2.60604, 2.27449".to_string()));
-        m.insert("none".to_string(), ("0.0, 0.0, 0.0".to_string(), "1.0, 1.0, 1.0".to_string()));
-        m
-    };
-}
-
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct RGB {
     pub r: f32,
     pub g: f32,
@@ -301,32 +208,41 @@ value_as!(as_point, Value::Point => Point3<f32>);
 
 impl Value {
     // Conversions
-    pub fn as_bsdf_color_spec(self, scene: &Scene) -> BSDFColorSpectrum {
+    /// Spectrum where a float gives a constant (gray) spectrum
+    pub fn as_spectrum_or_gray(self) -> Result<Spectrum> {
         match self {
-            Value::Spectrum(v) => BSDFColorSpectrum::Constant(v),
-            Value::Ref(v) => {
-                let tex = scene.textures.get(&v).unwrap();
-                BSDFColorSpectrum::Texture(tex.clone())
-            }
-            _ => panic!("Wrong type {:?} (as_bsdf_color_spec)", self),
+            Value::Spectrum(v) => Ok(v),
+            Value::Float(v) => Ok(Spectrum::from_f32(v)),
+            _ => Err(Error::Value("as_spectrum_or_gray", self)),
         }
     }
-    pub fn as_bsdf_color_f32(self, scene: &Scene) -> BSDFColorFloat {
+    /// Float where a gray spectrum (same value on all channels) is accepted
+    pub fn as_float_or_gray(self) -> Result<f32> {
         match self {
-            Value::Float(v) => BSDFColorFloat::Constant(v),
-            Value::Ref(v) => {
-                let tex = scene.textures.get(&v).unwrap();
-                BSDFColorFloat::Texture(tex.clone())
-            }
-            _ => panic!("Wrong type {:?} (as_bsdf_color_f32)", self),
+            Value::Float(v) => Ok(v),
+            Value::Spectrum(v) => match v.clone().as_rgb() {
+                Ok(RGB { r, g, b }) if r == g && g == b => Ok(r),
+                _ => Err(Error::Value("as_float_or_gray", Value::Spectrum(v))),
+            },
+            _ => Err(Error::Value("as_float_or_gray", self)),
         }
     }
-
-    fn as_ior(self) -> f32 {
+    pub fn as_bsdf_color_spec(self, scene: &Scene) -> Result<BSDFColorSpectrum> {
+        match self {
+            Value::Ref(v) => match scene.textures.get(&v) {
+                Some(tex) => Ok(BSDFColorSpectrum::Texture(tex.clone())),
+                None => Err(Error::UnknownReference(v)),
+            },
+            _ => Ok(BSDFColorSpectrum::Constant(self.as_spectrum_or_gray()?)),
+        }
+    }
+    pub fn as_bsdf_color_f32(self, scene: &Scene) -> Result<BSDFColorFloat> {
         match self {
-            Value::Float(v) => v,
-            Value::String(v) => *IOR_DATA.get(&v).unwrap(),
-            _ => panic!("Wrong type {:?} (as_ior)", self),
+            Value::Ref(v) => match scene.textures.get(&v) {
+                Some(tex) => Ok(BSDFColorFloat::Texture(tex.clone())),
+                None => Err(Error::UnknownReference(v)),
+            },
+            _ => Ok(BSDFColorFloat::Constant(self.as_float_or_gray()?)),
         }
     }
 }
@@ -431,6 +347,22 @@ fn values_fn<R: Read, F>(
 ) -> Result<(HashMap<String, Value>, Vec<String>)>
 where
     F: FnMut(&mut Events<R>, &str, HashMap<String, String>) -> Result<bool>,
+{
+    values_fn_ordered(events, defaults, strict, |events, t, attrs, _| {
+        other(events, t, attrs)
+    })
+}
+
+// Same as values_fn, but the callback also receives the number of
+// unamed id read so far (to interleave nested objects and references)
+fn values_fn_ordered<R: Read, F>(
+    events: &mut Events<R>,
+    defaults: &HashMap<String, String>,
+    strict: bool,
+    mut other: F,
+) -> Result<(HashMap<String, Value>, Vec<String>)>
+where
+    F: FnMut(&mut Events<R>, &str, HashMap<String, String>, usize) -> Result<bool>,
 {
     let mut map = HashMap::new();
     let mut refs = Vec::new();
@@ -555,7 +487,7 @@ where
                         .iter()
                         .map(|a| (a.name.local_name.clone(), a.value.clone()))
                         .collect();
-                    let captured = other(iter, &name.local_name, map)?;
+                    let captured = other(iter, &name.local_name, map, refs.len())?;
                     if !captured {
                         if strict {
                             panic!("{:?} encounter when parsing values", name)
@@ -600,18 +532,27 @@ fn read_value(m: &mut HashMap<String, Value>, n: &str, d: Value) -> Value {
     }
 }
 
+fn read_spectrum(m: &mut HashMap<String, Value>, n: &str, d: Spectrum) -> Result<Spectrum> {
+    match m.remove(n) {
+        Some(v) => v.as_spectrum_or_gray(),
+        None => Ok(d),
+    }
+}
+
+// Texturable parameters can be given as a value (float or spectrum),
+// a reference to a texture or an inlined texture (`textures`)
 fn read_value_or_texture_spectrum(
     m: &mut HashMap<String, Value>,
     n: &str,
-    d: Value,
+    d: Spectrum,
     textures: &HashMap<String, Texture>,
     scene: &Scene,
-) -> BSDFColorSpectrum {
+) -> Result<BSDFColorSpectrum> {
     match m.remove(n) {
         Some(v) => v.as_bsdf_color_spec(scene),
         None => match textures.get(n) {
-            Some(v) => BSDFColorSpectrum::Texture(v.clone()),
-            None => d.as_bsdf_color_spec(scene),
+            Some(v) => Ok(BSDFColorSpectrum::Texture(v.clone())),
+            None => Ok(BSDFColorSpectrum::Constant(d)),
         },
     }
 }
@@ -619,15 +560,15 @@ fn read_value_or_texture_spectrum(
 fn read_value_or_texture_f32(
     m: &mut HashMap<String, Value>,
     n: &str,
-    d: Value,
+    d: f32,
     textures: &HashMap<String, Texture>,
     scene: &Scene,
-) -> BSDFColorFloat {
+) -> Result<BSDFColorFloat> {
     match m.remove(n) {
         Some(v) => v.as_bsdf_color_f32(scene),
         None => match textures.get(n) {
-            Some(v) => BSDFColorFloat::Texture(v.clone()),
-            None => d.as_bsdf_color_f32(scene),
+            Some(v) => Ok(BSDFColorFloat::Texture(v.clone())),
+            None => Ok(BSDFColorFloat::Constant(d)),
         },
     }
 }
@@ -641,36 +582,136 @@ pub enum Alpha {
     },
 }
 impl Alpha {
-    fn parse(map: &mut HashMap<String, Value>, scene: &Scene) -> Self {
-        let is_alpha = map.get("alpha").is_some();
-        if is_alpha {
-            let alpha = map.remove("alpha").unwrap().as_bsdf_color_f32(scene);
-            Alpha::Isotropic(alpha)
-        } else {
-            let u = read_value(map, "alpha_u", Value::Float(0.1)).as_bsdf_color_f32(scene);
-            let v = read_value(map, "alpha_v", Value::Float(0.1)).as_bsdf_color_f32(scene);
-            if u == v {
-                Alpha::Isotropic(u)
-            } else {
-                Alpha::Anisotropic { u, v }
+    // Anisotropic roughness is named alphaU/alphaV (Mitsuba 0.6)
+    // or alpha_u/alpha_v (Mitsuba 2)
+    const NAMES_U: [&'static str; 2] = ["alphaU", "alpha_u"];
+    const NAMES_V: [&'static str; 2] = ["alphaV", "alpha_v"];
+
+    fn has_anisotropic(
+        map: &HashMap<String, Value>,
+        textures: &HashMap<String, Texture>,
+    ) -> (bool, bool) {
+        let has = |n: &&str| map.contains_key(*n) || textures.contains_key(*n);
+        (Self::NAMES_U.iter().any(has), Self::NAMES_V.iter().any(has))
+    }
+
+    fn parse(
+        map: &mut HashMap<String, Value>,
+        textures: &HashMap<String, Texture>,
+        scene: &Scene,
+    ) -> Result<Self> {
+        let (has_u, has_v) = Self::has_anisotropic(map, textures);
+        if map.contains_key("alpha") || textures.contains_key("alpha") {
+            if has_u || has_v {
+                return Err(Error::InvalidParameter(
+                    "alpha".to_string(),
+                    "please specify either alpha or alphaU/alphaV".to_string(),
+                ));
+            }
+            return Ok(Alpha::Isotropic(read_value_or_texture_f32(
+                map, "alpha", 0.1, textures, scene,
+            )?));
+        }
+
+        let mut read = |names: &[&str; 2]| -> Result<BSDFColorFloat> {
+            let given = names
+                .iter()
+                .filter(|n| map.contains_key(**n) || textures.contains_key(**n))
+                .collect::<Vec<_>>();
+            if given.len() > 1 {
+                return Err(Error::InvalidParameter(
+                    names[0].to_string(),
+                    format!("{} and {} cannot be used together", names[0], names[1]),
+                ));
             }
+            let name = given.first().map_or(names[0], |n| **n);
+            read_value_or_texture_f32(map, name, 0.1, textures, scene)
+        };
+        let u = read(&Self::NAMES_U)?;
+        let v = read(&Self::NAMES_V)?;
+        if u == v {
+            Ok(Alpha::Isotropic(u))
+        } else {
+            Ok(Alpha::Anisotropic { u, v })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MicrofacetType {
+    Beckmann,
+    GGX,
+    Phong,
+    AshikhminShirley,
+}
+impl MicrofacetType {
+    fn parse(name: &str) -> Result<Self> {
+        match &name.to_lowercase()[..] {
+            "beckmann" => Ok(MicrofacetType::Beckmann),
+            "ggx" => Ok(MicrofacetType::GGX),
+            "phong" => Ok(MicrofacetType::Phong),
+            "as" => Ok(MicrofacetType::AshikhminShirley),
+            _ => Err(Error::InvalidParameter(
+                "distribution".to_string(),
+                format!(
+                    "{:?} is not a valid distribution (beckmann, ggx, phong or as)",
+                    name
+                ),
+            )),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Distribution {
-    pub distribution: String,
-    pub alpha: Alpha,
+    pub distribution: MicrofacetType, // beckmann
+    pub alpha: Alpha,                 // 0.1 (Iso)
+    pub sample_visible: bool,         // true (always false for phong and as)
 }
 impl Distribution {
-    fn parse(map: &mut HashMap<String, Value>, scene: &Scene) -> Result<Self> {
+    fn parse(
+        map: &mut HashMap<String, Value>,
+        textures: &HashMap<String, Texture>,
+        scene: &Scene,
+        bsdf_type: &str,
+    ) -> Result<Self> {
         let distribution =
             read_value(map, "distribution", Value::String("beckmann".to_string())).as_string()?;
-        let alpha = Alpha::parse(map, scene);
+        let distribution = MicrofacetType::parse(&distribution)?;
+
+        let (has_u, has_v) = Alpha::has_anisotropic(map, textures);
+        if has_u != has_v {
+            return Err(Error::InvalidParameter(
+                "alphaU".to_string(),
+                "both alphaU and alphaV must be specified".to_string(),
+            ));
+        }
+        let alpha = Alpha::parse(map, textures, scene)?;
+        // Mitsuba does not support anisotropy for layered models
+        if let Alpha::Anisotropic { .. } = alpha {
+            if bsdf_type == "roughplastic" || bsdf_type == "roughcoating" {
+                return Err(Error::InvalidParameter(
+                    "alphaU".to_string(),
+                    format!("{} does not support anisotropic roughness", bsdf_type),
+                ));
+            }
+        }
+
+        let sample_visible = match map.remove("sampleVisible") {
+            Some(v) => v.as_bool()?,
+            None => read_value(map, "sample_visible", Value::Boolean(true)).as_bool()?,
+        };
+        // Visible normal sampling is not available for these distributions
+        let sample_visible = sample_visible
+            && !matches!(
+                distribution,
+                MicrofacetType::Phong | MicrofacetType::AshikhminShirley
+            );
+
         Ok(Self {
             distribution,
             alpha,
+            sample_visible,
         })
     }
 }
@@ -700,6 +741,7 @@ pub enum BSDF {
     Conductor {
         distribution: Option<Distribution>,
         // Potentially read values from materials
+        material: Option<String>, // Cu (None if eta/k are given)
         eta: Spectrum,
         k: Spectrum,
         // Other
@@ -739,6 +781,106 @@ pub enum BSDF {
         opacity: BSDFColorSpectrum,
         bsdf: Box<BSDF>,
     },
+    BumpMap {
+        texture: Texture, // Height map
+        scale: f32,       // 1 (from an enclosing `scale` texture)
+        bsdf: Box<BSDF>,
+    },
+    DiffuseTransmitter {
+        transmittance: BSDFColorSpectrum, // s(0.5)
+    },
+    // Index-matched boundary (e.g., for participating media)
+    Null,
+    // Mitsuba 2 (linear polarizer)
+    Polarizer {
+        theta: BSDFColorFloat,            // 0 (degrees)
+        transmittance: BSDFColorSpectrum, // s(1.0)
+        polarizing: bool,                 // true
+    },
+    Blend {
+        weight: BSDFColorFloat, // 0.5
+        bsdfs: [Box<BSDF>; 2],
+    },
+    Coating {
+        distribution: Option<Distribution>,
+        int_ior: f32,                            // intIOR "bk7"
+        ext_ior: f32,                            // extIOR "air"
+        thickness: f32,                          // 1
+        sigma_a: BSDFColorSpectrum,              // s(0.0)
+        specular_reflectance: BSDFColorSpectrum, // s(1.0)
+        bsdf: Box<BSDF>,
+    },
+    // Mitsuba 2
+    NormalMap {
+        normalmap: Texture, // Tangent space normals (raw bitmap)
+        bsdf: Box<BSDF>,
+    },
+    // Hanrahan-Krueger (single scattering inside a thin layer)
+    HK {
+        coefficients: ScatteringParameters, // skin1
+        thickness: f32,                     // 1
+        phase: PhaseFunction,               // isotropic
+    },
+    // Mitsuba 3 (Disney BSDF)
+    Principled {
+        base_color: BSDFColorSpectrum,          // s(0.5)
+        roughness: BSDFColorFloat,              // 0.5
+        anisotropic: BSDFColorFloat,            // 0
+        metallic: BSDFColorFloat,               // 0
+        spec_trans: BSDFColorFloat,             // 0
+        eta: BSDFColorFloat,                    // 1.5 (or from specular)
+        spec_tint: BSDFColorFloat,              // 0
+        sheen: BSDFColorFloat,                  // 0
+        sheen_tint: BSDFColorFloat,             // 0
+        clearcoat: BSDFColorFloat,              // 0
+        clearcoat_gloss: BSDFColorFloat,        // 0
+        main_specular_sampling_rate: f32,       // 1
+        clearcoat_sampling_rate: f32,           // 1
+        diffuse_reflectance_sampling_rate: f32, // 1
+    },
+    // Mitsuba 3 (Disney BSDF for thin objects)
+    PrincipledThin {
+        base_color: BSDFColorSpectrum,           // s(0.5)
+        roughness: BSDFColorFloat,               // 0.5
+        anisotropic: BSDFColorFloat,             // 0
+        spec_trans: BSDFColorFloat,              // 0
+        eta: BSDFColorFloat,                     // 1.5 (or from specular)
+        spec_tint: BSDFColorFloat,               // 0
+        sheen: BSDFColorFloat,                   // 0
+        sheen_tint: BSDFColorFloat,              // 0
+        flatness: BSDFColorFloat,                // 0
+        diff_trans: BSDFColorFloat,              // 0
+        diffuse_reflectance_sampling_rate: f32,  // 1
+        specular_reflectance_sampling_rate: f32, // 1
+        spec_trans_sampling_rate: f32,           // 1
+        diff_trans_sampling_rate: f32,           // 1
+    },
+    // Mitsuba 2 (RGL tabulated BSDF, see measured module to load it)
+    Measured {
+        filename: String,
+    },
+    // Irawan-Marschner cloth (weave pattern read relative to the scene file)
+    Irawan {
+        filename: String, // weave pattern (.wv)
+        pattern: WeavePattern,
+        repeat_u: f32, // 1
+        repeat_v: f32, // 1
+        // Other parameters (referred as $name inside the weave pattern)
+        parameters: HashMap<String, WeaveParameter>,
+    },
+}
+
+// Split the height map of a bumpmap. Mitsuba scales the height map by
+// enclosing it inside a `scale` texture, in this case the scale factor
+// is extracted.
+fn split_bump_texture(texture: Texture) -> (Texture, f32) {
+    match texture {
+        Texture::Scale {
+            texture,
+            value: TextureScale::Float(scale),
+        } => (*texture, scale),
+        _ => (texture, 1.0),
+    }
 }
 
 impl BSDF {
@@ -763,7 +905,7 @@ impl BSDF {
                         let texture_type = attrs.get("type").unwrap();
 
                         let texture_id = attrs.get("id");
-                        let texture = Texture::parse(events, defaults, &texture_type)?;
+                        let texture = Texture::parse(events, defaults, texture_type, scene)?;
                         textures.insert(texture_name.clone(), texture.clone());
                         if let Some(id) = texture_id {
                             // If there is an id, we need to include it
@@ -780,27 +922,22 @@ impl BSDF {
             "phong" => {
                 let (mut map, refs) = values_fn(event, defaults, true, f_texture)?;
                 assert!(refs.is_empty());
-                let exponent = read_value_or_texture_f32(
-                    &mut map,
-                    "exponent",
-                    Value::Float(30.0),
-                    &textures,
-                    scene,
-                );
+                let exponent =
+                    read_value_or_texture_f32(&mut map, "exponent", 30.0, &textures, scene)?;
                 let specular_reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "specularReflectance",
-                    Value::Spectrum(Spectrum::from_f32(0.2)),
+                    Spectrum::from_f32(0.2),
                     &textures,
                     scene,
-                );
+                )?;
                 let diffuse_reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "diffuseReflectance",
-                    Value::Spectrum(Spectrum::from_f32(0.5)),
+                    Spectrum::from_f32(0.5),
                     &textures,
                     scene,
-                );
+                )?;
                 Ok(BSDF::Phong {
                     exponent,
                     specular_reflectance,
@@ -814,10 +951,10 @@ impl BSDF {
                 let reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "reflectance",
-                    Value::Spectrum(Spectrum::from_f32(0.5)),
+                    Spectrum::from_f32(0.5),
                     &textures,
                     scene,
-                );
+                )?;
 
                 // If we use the default parameter, we will try
                 // an alternative name
@@ -826,10 +963,10 @@ impl BSDF {
                         read_value_or_texture_spectrum(
                             &mut map,
                             "diffuseReflectance",
-                            Value::Spectrum(Spectrum::from_f32(0.5)),
+                            Spectrum::from_f32(0.5),
                             &textures,
                             scene,
-                        )
+                        )?
                     } else {
                         reflectance
                     };
@@ -840,30 +977,30 @@ impl BSDF {
                 let (mut map, refs) = values_fn(event, defaults, true, f_texture)?;
                 assert!(refs.is_empty());
                 let distribution = if bsdf_type == "roughdielectric" {
-                    Some(Distribution::parse(&mut map, scene)?)
+                    Some(Distribution::parse(&mut map, &textures, scene, bsdf_type)?)
                 } else {
                     None
                 };
                 let thin = bsdf_type == "thindielectric";
 
                 let int_ior =
-                    read_value(&mut map, "intIOR", Value::String("bk7".to_string())).as_ior();
+                    read_value(&mut map, "intIOR", Value::String("bk7".to_string())).as_ior()?;
                 let ext_ior =
-                    read_value(&mut map, "extIOR", Value::String("air".to_string())).as_ior();
+                    read_value(&mut map, "extIOR", Value::String("air".to_string())).as_ior()?;
                 let specular_reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "specularReflectance",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
+                    Spectrum::from_f32(1.0),
                     &textures,
                     scene,
-                );
+                )?;
                 let specular_transmittance = read_value_or_texture_spectrum(
                     &mut map,
                     "specularTransmittance",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
+                    Spectrum::from_f32(1.0),
                     &textures,
                     scene,
-                );
+                )?;
 
                 Ok(BSDF::Dielectric {
                     distribution,
@@ -897,13 +1034,381 @@ impl BSDF {
                 let opacity = read_value_or_texture_spectrum(
                     &mut map,
                     "opacity",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
+                    Spectrum::from_f32(1.0),
                     &textures,
                     scene,
-                );
+                )?;
  
                 Ok(BSDF::Mask { opacity, bsdf: Box::new(bsdfs[0].clone()) })
             }
+            "bumpmap" | "normalmap" => {
+                // Read the nested BSDF and the texture
+                let mut bsdfs = vec![];
+                let mut bump = None;
+                let f = |events: &mut Events<R>,
+                         t: &str,
+                         attrs: HashMap<String, String>|
+                 -> Result<bool> {
+                    match t {
+                        "bsdf" => {
+                            let bsdf_type = attrs.get("type").unwrap();
+                            bsdfs.push(BSDF::parse(events, defaults, bsdf_type, scene)?);
+                        }
+                        "texture" => {
+                            let texture_type = attrs.get("type").unwrap();
+                            let texture = Texture::parse(events, defaults, texture_type, scene)?;
+                            bump = Some(split_bump_texture(texture));
+                        }
+                        _ => panic!("Bumpmap encounter unexpected token {:?}", t),
+                    }
+                    Ok(true)
+                };
+                let (mut map, refs) = values_fn(event, defaults, true, f)?;
+
+                // Nested BSDF and texture can be references
+                for r in refs {
+                    if let Some(v) = scene.bsdfs.get(&r) {
+                        bsdfs.push(v.clone());
+                    } else if let Some(v) = scene.textures.get(&r) {
+                        bump = Some(split_bump_texture(v.clone()));
+                    } else {
+                        return Err(Error::UnknownReference(r));
+                    }
+                }
+                assert_eq!(bsdfs.len(), 1);
+                let bsdf = Box::new(bsdfs.pop().unwrap());
+                let (texture, scale) = bump.expect("Bumpmap need a nested texture");
+
+                if bsdf_type == "bumpmap" {
+                    // Mitsuba 2 gives the scale directly to the bumpmap
+                    let scale =
+                        scale * read_value(&mut map, "scale", Value::Float(1.0)).as_float()?;
+                    Ok(BSDF::BumpMap {
+                        texture,
+                        scale,
+                        bsdf,
+                    })
+                } else {
+                    // Normals cannot be scaled
+                    if scale != 1.0 {
+                        return Err(Error::InvalidParameter(
+                            "normalmap".to_string(),
+                            "the normal map cannot be a scaled texture".to_string(),
+                        ));
+                    }
+                    Ok(BSDF::NormalMap {
+                        normalmap: texture,
+                        bsdf,
+                    })
+                }
+            }
+            "difftrans" => {
+                let (mut map, refs) = values_fn(event, defaults, true, f_texture)?;
+                assert!(refs.is_empty());
+                let transmittance = read_value_or_texture_spectrum(
+                    &mut map,
+                    "transmittance",
+                    Spectrum::from_f32(0.5),
+                    &textures,
+                    scene,
+                )?;
+                Ok(BSDF::DiffuseTransmitter { transmittance })
+            }
+            "null" => {
+                let (_, refs) = values(event, defaults, true)?;
+                assert!(refs.is_empty());
+                Ok(BSDF::Null)
+            }
+            "hk" => {
+                let mut phase = None;
+                let f = |events: &mut Events<R>,
+                         t: &str,
+                         attrs: HashMap<String, String>|
+                 -> Result<bool> {
+                    match t {
+                        "phase" => {
+                            let phase_type = attrs.get("type").unwrap();
+                            phase = Some(PhaseFunction::parse(events, defaults, phase_type)?);
+                        }
+                        _ => panic!("HK encounter unexpected token {:?}", t),
+                    }
+                    Ok(true)
+                };
+                let (mut map, refs) = values_fn(event, defaults, true, f)?;
+                assert!(refs.is_empty());
+                let coefficients = ScatteringParameters::parse(&mut map, "skin1")?;
+                let thickness = read_value(&mut map, "thickness", Value::Float(1.0)).as_float()?;
+                Ok(BSDF::HK {
+                    coefficients,
+                    thickness,
+                    phase: phase.unwrap_or(PhaseFunction::Isotropic),
+                })
+            }
+            "principled" | "principledthin" => {
+                let (mut map, refs) = values_fn(event, defaults, true, f_texture)?;
+                assert!(refs.is_empty());
+                let base_color = read_value_or_texture_spectrum(
+                    &mut map,
+                    "base_color",
+                    Spectrum::from_f32(0.5),
+                    &textures,
+                    scene,
+                )?;
+                // The IOR can be given directly or through the specular amount
+                let specular = map.remove("specular");
+                if specular.is_some() && (map.contains_key("eta") || textures.contains_key("eta")) {
+                    return Err(Error::InvalidParameter(
+                        "specular".to_string(),
+                        "cannot be specified together with eta".to_string(),
+                    ));
+                }
+                let eta = match specular {
+                    Some(v) => {
+                        let specular = v.as_float_or_gray()?;
+                        BSDFColorFloat::Constant(2.0 / (1.0 - (0.08 * specular).sqrt()) - 1.0)
+                    }
+                    None => read_value_or_texture_f32(&mut map, "eta", 1.5, &textures, scene)?,
+                };
+                let mut float =
+                    |n: &str, d: f32| read_value_or_texture_f32(&mut map, n, d, &textures, scene);
+                let roughness = float("roughness", 0.5)?;
+                let anisotropic = float("anisotropic", 0.0)?;
+                let spec_trans = float("spec_trans", 0.0)?;
+                let spec_tint = float("spec_tint", 0.0)?;
+                let sheen = float("sheen", 0.0)?;
+                let sheen_tint = float("sheen_tint", 0.0)?;
+                if bsdf_type == "principled" {
+                    let metallic = float("metallic", 0.0)?;
+                    let clearcoat = float("clearcoat", 0.0)?;
+                    let clearcoat_gloss = float("clearcoat_gloss", 0.0)?;
+                    let mut rate = |n: &str| read_value(&mut map, n, Value::Float(1.0)).as_float();
+                    Ok(BSDF::Principled {
+                        base_color,
+                        roughness,
+                        anisotropic,
+                        metallic,
+                        spec_trans,
+                        eta,
+                        spec_tint,
+                        sheen,
+                        sheen_tint,
+                        clearcoat,
+                        clearcoat_gloss,
+                        main_specular_sampling_rate: rate("main_specular_sampling_rate")?,
+                        clearcoat_sampling_rate: rate("clearcoat_sampling_rate")?,
+                        diffuse_reflectance_sampling_rate: rate(
+                            "diffuse_reflectance_sampling_rate",
+                        )?,
+                    })
+                } else {
+                    let flatness = float("flatness", 0.0)?;
+                    let diff_trans = float("diff_trans", 0.0)?;
+                    let mut rate = |n: &str| read_value(&mut map, n, Value::Float(1.0)).as_float();
+                    Ok(BSDF::PrincipledThin {
+                        base_color,
+                        roughness,
+                        anisotropic,
+                        spec_trans,
+                        eta,
+                        spec_tint,
+                        sheen,
+                        sheen_tint,
+                        flatness,
+                        diff_trans,
+                        diffuse_reflectance_sampling_rate: rate(
+                            "diffuse_reflectance_sampling_rate",
+                        )?,
+                        specular_reflectance_sampling_rate: rate(
+                            "specular_reflectance_sampling_rate",
+                        )?,
+                        spec_trans_sampling_rate: rate("spec_trans_sampling_rate")?,
+                        diff_trans_sampling_rate: rate("diff_trans_sampling_rate")?,
+                    })
+                }
+            }
+            "measured" => {
+                let (mut map, refs) = values(event, defaults, true)?;
+                assert!(refs.is_empty());
+                let filename = map
+                    .remove("filename")
+                    .ok_or_else(|| {
+                        Error::AttribNotFound("filename".to_string(), "measured".to_string())
+                    })?
+                    .as_string()?;
+                Ok(BSDF::Measured { filename })
+            }
+            "irawan" => {
+                let (mut map, refs) = values(event, defaults, true)?;
+                assert!(refs.is_empty());
+                let filename = map
+                    .remove("filename")
+                    .ok_or_else(|| {
+                        Error::AttribNotFound("filename".to_string(), "irawan".to_string())
+                    })?
+                    .as_string()?;
+                let repeat_u = read_value(&mut map, "repeatU", Value::Float(1.0)).as_float()?;
+                let repeat_v = read_value(&mut map, "repeatV", Value::Float(1.0)).as_float()?;
+                let parameters = map
+                    .into_iter()
+                    .map(|(k, v)| {
+                        let v = match v {
+                            Value::Float(v) => WeaveParameter::Float(v),
+                            Value::Integer(v) => WeaveParameter::Float(v as f32),
+                            Value::Spectrum(v) => WeaveParameter::Spectrum(v),
+                            _ => return Err(Error::Value("as_weave_parameter", v)),
+                        };
+                        Ok((k, v))
+                    })
+                    .collect::<Result<HashMap<_, _>>>()?;
+                let pattern = weave::read_weave(&filename, &parameters, &scene.wk)?;
+                Ok(BSDF::Irawan {
+                    filename,
+                    pattern,
+                    repeat_u,
+                    repeat_v,
+                    parameters,
+                })
+            }
+            "polarizer" => {
+                let (mut map, refs) = values_fn(event, defaults, true, f_texture)?;
+                assert!(refs.is_empty());
+                let theta = read_value_or_texture_f32(&mut map, "theta", 0.0, &textures, scene)?;
+                let transmittance = read_value_or_texture_spectrum(
+                    &mut map,
+                    "transmittance",
+                    Spectrum::from_f32(1.0),
+                    &textures,
+                    scene,
+                )?;
+                let polarizing =
+                    read_value(&mut map, "polarizing", Value::Boolean(true)).as_bool()?;
+                Ok(BSDF::Polarizer {
+                    theta,
+                    transmittance,
+                    polarizing,
+                })
+            }
+            "blendbsdf" => {
+                // Read the nested BSDFs and the weight texture
+                // Inlined BSDFs are stored with the number of references
+                // preceding them to keep the document order
+                let mut inlined = vec![];
+                let f = |events: &mut Events<R>,
+                         t: &str,
+                         attrs: HashMap<String, String>,
+                         nb_refs: usize|
+                 -> Result<bool> {
+                    match t {
+                        "bsdf" => {
+                            let bsdf_type = attrs.get("type").unwrap();
+                            let bsdf = BSDF::parse(events, defaults, bsdf_type, scene)?;
+                            inlined.push((nb_refs, bsdf));
+                        }
+                        "texture" => {
+                            let texture_name = attrs.get("name").unwrap();
+                            let texture_type = attrs.get("type").unwrap();
+                            let texture = Texture::parse(events, defaults, texture_type, scene)?;
+                            if let Some(id) = attrs.get("id") {
+                                scene.textures.insert(id.to_string(), texture.clone());
+                            }
+                            textures.insert(texture_name.clone(), texture);
+                        }
+                        _ => panic!("Blendbsdf encounter unexpected token {:?}", t),
+                    }
+                    Ok(true)
+                };
+                let (mut map, refs) = values_fn_ordered(event, defaults, true, f)?;
+                let mut inlined = inlined.into_iter().peekable();
+                let mut bsdfs = vec![];
+                for (i, r) in refs.into_iter().enumerate() {
+                    while let Some((_, v)) = inlined.next_if(|(n, _)| *n == i) {
+                        bsdfs.push(v);
+                    }
+                    match scene.bsdfs.get(&r) {
+                        Some(v) => bsdfs.push(v.clone()),
+                        None => return Err(Error::UnknownReference(r)),
+                    }
+                }
+                bsdfs.extend(inlined.map(|(_, v)| v));
+                assert_eq!(bsdfs.len(), 2, "blendbsdf need exactly two nested BSDFs");
+
+                let weight = read_value_or_texture_f32(&mut map, "weight", 0.5, &textures, scene)?;
+                let bsdf1 = Box::new(bsdfs.pop().unwrap());
+                let bsdf0 = Box::new(bsdfs.pop().unwrap());
+                Ok(BSDF::Blend {
+                    weight,
+                    bsdfs: [bsdf0, bsdf1],
+                })
+            }
+            "coating" | "roughcoating" => {
+                // Read the nested BSDF and the textures
+                let mut bsdfs = vec![];
+                let f = |events: &mut Events<R>,
+                         t: &str,
+                         attrs: HashMap<String, String>|
+                 -> Result<bool> {
+                    match t {
+                        "bsdf" => {
+                            let bsdf_type = attrs.get("type").unwrap();
+                            bsdfs.push(BSDF::parse(events, defaults, bsdf_type, scene)?);
+                        }
+                        "texture" => {
+                            let texture_name = attrs.get("name").unwrap();
+                            let texture_type = attrs.get("type").unwrap();
+                            let texture = Texture::parse(events, defaults, texture_type, scene)?;
+                            if let Some(id) = attrs.get("id") {
+                                scene.textures.insert(id.to_string(), texture.clone());
+                            }
+                            textures.insert(texture_name.clone(), texture);
+                        }
+                        _ => panic!("Coating encounter unexpected token {:?}", t),
+                    }
+                    Ok(true)
+                };
+                let (mut map, refs) = values_fn(event, defaults, true, f)?;
+                for r in refs {
+                    match scene.bsdfs.get(&r) {
+                        Some(v) => bsdfs.push(v.clone()),
+                        None => return Err(Error::UnknownReference(r)),
+                    }
+                }
+                assert_eq!(bsdfs.len(), 1);
+
+                let distribution = if bsdf_type == "roughcoating" {
+                    Some(Distribution::parse(&mut map, &textures, scene, bsdf_type)?)
+                } else {
+                    None
+                };
+                let int_ior =
+                    read_value(&mut map, "intIOR", Value::String("bk7".to_string())).as_ior()?;
+                let ext_ior =
+                    read_value(&mut map, "extIOR", Value::String("air".to_string())).as_ior()?;
+                let thickness = read_value(&mut map, "thickness", Value::Float(1.0)).as_float()?;
+                let sigma_a = read_value_or_texture_spectrum(
+                    &mut map,
+                    "sigmaA",
+                    Spectrum::from_f32(0.0),
+                    &textures,
+                    scene,
+                )?;
+                let specular_reflectance = read_value_or_texture_spectrum(
+                    &mut map,
+                    "specularReflectance",
+                    Spectrum::from_f32(1.0),
+                    &textures,
+                    scene,
+                )?;
+
+                Ok(BSDF::Coating {
+                    distribution,
+                    int_ior,
+                    ext_ior,
+                    thickness,
+                    sigma_a,
+                    specular_reflectance,
+                    bsdf: Box::new(bsdfs.pop().unwrap()),
+                })
+            }
             "twosided" => {
                 // We need to parse the next element, including BSDF
                 let mut bsdfs = vec![];
@@ -933,7 +1438,7 @@ impl BSDF {
                 let (mut map, refs) = values_fn(event, defaults, true, f_texture)?;
                 assert!(refs.is_empty());
                 let distribution = if bsdf_type == "roughplastic" {
-                    Some(Distribution::parse(&mut map, scene)?)
+                    Some(Distribution::parse(&mut map, &textures, scene, bsdf_type)?)
                 } else {
                     None
                 };
@@ -943,23 +1448,23 @@ impl BSDF {
                     "intIOR",
                     Value::String("polypropylene".to_string()),
                 )
-                .as_ior();
+                .as_ior()?;
                 let ext_ior =
-                    read_value(&mut map, "extIOR", Value::String("air".to_string())).as_ior();
+                    read_value(&mut map, "extIOR", Value::String("air".to_string())).as_ior()?;
                 let specular_reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "specularReflectance",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
+                    Spectrum::from_f32(1.0),
                     &textures,
                     scene,
-                );
+                )?;
                 let diffuse_reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "diffuseReflectance",
-                    Value::Spectrum(Spectrum::from_f32(0.5)),
+                    Spectrum::from_f32(0.5),
                     &textures,
                     scene,
-                );
+                )?;
 
                 let nonlinear =
                     read_value(&mut map, "nonlinear", Value::Boolean(false)).as_bool()?;
@@ -979,17 +1484,11 @@ impl BSDF {
                 let reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "reflectance",
-                    Value::Spectrum(Spectrum::from_f32(0.5)),
+                    Spectrum::from_f32(0.5),
                     &textures,
                     scene,
-                );
-                let alpha = read_value_or_texture_f32(
-                    &mut map,
-                    "alpha",
-                    Value::Spectrum(Spectrum::from_f32(0.1)),
-                    &textures,
-                    scene,
-                );
+                )?;
+                let alpha = read_value_or_texture_f32(&mut map, "alpha", 0.2, &textures, scene)?;
                 let use_fast_approx =
                     read_value(&mut map, "useFastApprox", Value::Boolean(false)).as_bool()?;
 
@@ -1047,7 +1546,7 @@ impl BSDF {
                 let (mut map, refs) = values_fn(event, defaults, true, f_texture)?;
                 assert!(refs.is_empty());
                 let distribution = if bsdf_type == "roughconductor" {
-                    Some(Distribution::parse(&mut map, scene)?)
+                    Some(Distribution::parse(&mut map, &textures, scene, bsdf_type)?)
                 } else {
                     None
                 };
@@ -1057,31 +1556,38 @@ impl BSDF {
                     .remove("material")
                     .unwrap_or(Value::String("Cu".to_owned()))
                     .as_string()?;
-                let (eta, k) = MATERIALS.get(&material_name).unwrap();
-                let (eta, k) = (
-                    match map.remove("eta") {
-                        None => Spectrum { value: eta.clone() },
-                        Some(v) => v.as_spectrum()?,
-                    },
-                    match map.remove("k") {
-                        None => Spectrum { value: k.clone() },
-                        Some(v) => v.as_spectrum()?,
-                    },
-                );
+                let ior = conductor::lookup_conductor(&material_name)?;
+                let (eta, k, material) = match (map.remove("eta"), map.remove("k")) {
+                    (Some(eta), Some(k)) => {
+                        (eta.as_spectrum_or_gray()?, k.as_spectrum_or_gray()?, None)
+                    }
+                    (eta, k) => (
+                        match eta {
+                            None => ior.eta,
+                            Some(v) => v.as_spectrum_or_gray()?,
+                        },
+                        match k {
+                            None => ior.k,
+                            Some(v) => v.as_spectrum_or_gray()?,
+                        },
+                        Some(material_name),
+                    ),
+                };
 
                 let ext_eta =
-                    read_value(&mut map, "extEta", Value::String("air".to_string())).as_ior();
+                    read_value(&mut map, "extEta", Value::String("air".to_string())).as_ior()?;
 
                 let specular_reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "specularReflectance",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
+                    Spectrum::from_f32(1.0),
                     &textures,
                     scene,
-                );
+                )?;
 
                 Ok(BSDF::Conductor {
                     distribution,
+                    material,
                     eta,
                     k,
                     ext_eta,
@@ -1095,18 +1601,18 @@ impl BSDF {
                 let specular_reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "specularReflectance",
-                    Value::Spectrum(Spectrum::from_f32(0.2)),
+                    Spectrum::from_f32(0.2),
                     &textures,
                     scene,
-                );
+                )?;
                 let diffuse_reflectance = read_value_or_texture_spectrum(
                     &mut map,
                     "diffuseReflectance",
-                    Value::Spectrum(Spectrum::from_f32(0.5)),
+                    Spectrum::from_f32(0.5),
                     &textures,
                     scene,
-                );
-                let alpha = Alpha::parse(&mut map, scene);
+                )?;
+                let alpha = Alpha::parse(&mut map, &textures, scene)?;
                 let variant =
                     read_value(&mut map, "variant", Value::String("balanced".to_string()))
                         .as_string()?;
@@ -1136,85 +1642,304 @@ pub enum TextureScale {
     Float(f32),
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Texture {
-    Bitmap {
-        /// TODO: wrapMode,
-        /// maxAnisotropy,
-        /// cache,
-        /// channel
-        filename: String,
-        filter_type: String,
-        gamma: f32,
-        offset: Vector2<f32>,
-        scale: Vector2<f32>,
-    },
-    Checkerboard {
-        color0: Spectrum, // 0.4
-        color1: Spectrum, // 0.2
-        offset: Vector2<f32>,
-        scale: Vector2<f32>,
-    },
-    GridTexture {
-        color0: Spectrum, // 0.4
-        color1: Spectrum, // 0.2
-        line_width: f32,  // 0.01
-        offset: Vector2<f32>,
-        scale: Vector2<f32>,
-    },
-    // TODO: Implement scale
-    Scale {
-        texture: Box<Texture>,
-        value: TextureScale,
-    },
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+    Zero,
+    One,
 }
-impl Texture {
-    pub fn parse<R: Read>(
-        events: &mut Events<R>,
-        defaults: &HashMap<String, String>,
-        texture_type: &str,
+impl WrapMode {
+    fn parse(name: &str, value: &str) -> Result<Self> {
+        match &value.to_lowercase()[..] {
+            "repeat" => Ok(WrapMode::Repeat),
+            "mirror" => Ok(WrapMode::Mirror),
+            "clamp" => Ok(WrapMode::Clamp),
+            "zero" => Ok(WrapMode::Zero),
+            "one" => Ok(WrapMode::One),
+            _ => Err(Error::InvalidParameter(
+                name.to_string(),
+                format!(
+                    "{:?} is not a valid wrap mode (repeat, mirror, clamp, zero or one)",
+                    value
+                ),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterType {
+    EWA,
+    Trilinear,
+    Nearest,
+    /// Mitsuba 2 (filter_type)
+    Bilinear,
+}
+impl FilterType {
+    fn parse(name: &str, value: &str) -> Result<Self> {
+        // Mitsuba 2 only provides bilinear and nearest filtering
+        let mitsuba2 = name == "filter_type";
+        match &value.to_lowercase()[..] {
+            "ewa" if !mitsuba2 => Ok(FilterType::EWA),
+            "trilinear" if !mitsuba2 => Ok(FilterType::Trilinear),
+            "bilinear" if mitsuba2 => Ok(FilterType::Bilinear),
+            "nearest" => Ok(FilterType::Nearest),
+            _ => Err(Error::InvalidParameter(
+                name.to_string(),
+                format!(
+                    "{:?} is not a valid filter type ({})",
+                    value,
+                    if mitsuba2 {
+                        "bilinear or nearest"
+                    } else {
+                        "ewa, trilinear or nearest"
+                    }
+                ),
+            )),
+        }
+    }
+}
+
+/// Affine transform (3x3 matrix) from the surface UVs to the texture space
+#[derive(Debug, Clone, PartialEq)]
+pub struct UVTransform(Matrix3<f32>);
+impl Default for UVTransform {
+    fn default() -> Self {
+        UVTransform(Matrix3::one())
+    }
+}
+impl UVTransform {
+    // Mitsuba 0.6 gives the offset and scale (uoffset, uscale, ...),
+    // Mitsuba 2 gives the transform (to_uv)
+    fn parse(map: &mut HashMap<String, Value>, to_uv: Option<Transform>) -> Result<Self> {
+        let names = ["uoffset", "voffset", "uscale", "vscale"];
+        if let Some(to_uv) = to_uv {
+            if let Some(n) = names.iter().find(|n| map.contains_key(**n)) {
+                return Err(Error::InvalidParameter(
+                    n.to_string(),
+                    "cannot be specified together with to_uv".to_string(),
+                ));
+            }
+            // Keep the 2D part of the transform (and its translation)
+            let m = to_uv.as_matrix();
+            return Ok(UVTransform(Matrix3::new(
+                m.x.x, m.x.y, 0.0, m.y.x, m.y.y, 0.0, m.w.x, m.w.y, 1.0,
+            )));
+        }
+
+        let uoffset = read_value(map, "uoffset", Value::Float(0.0)).as_float()?;
+        let voffset = read_value(map, "voffset", Value::Float(0.0)).as_float()?;
+        let uscale = read_value(map, "uscale", Value::Float(1.0)).as_float()?;
+        let vscale = read_value(map, "vscale", Value::Float(1.0)).as_float()?;
+        Ok(UVTransform::new(
+            Vector2::new(uoffset, voffset),
+            Vector2::new(uscale, vscale),
+        ))
+    }
+
+    /// Transform `uv * scale + offset`
+    pub fn new(offset: Vector2<f32>, scale: Vector2<f32>) -> Self {
+        UVTransform(Matrix3::new(
+            scale.x, 0.0, 0.0, 0.0, scale.y, 0.0, offset.x, offset.y, 1.0,
+        ))
+    }
+
+    /// Map surface UVs into the texture space
+    pub fn apply(&self, uv: Point2<f32>) -> Point2<f32> {
+        let p = self.0 * Vector3::new(uv.x, uv.y, 1.0);
+        Point2::new(p.x, p.y)
+    }
+
+    pub fn as_matrix(&self) -> Matrix3<f32> {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurvatureType {
+    Mean,
+    Gaussian,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Texture {
+    Bitmap {
+        filename: String,
+        wrap_mode_u: WrapMode,   // repeat
+        wrap_mode_v: WrapMode,   // wrapModeU
+        filter_type: FilterType, // ewa
+        max_anisotropy: f32,     // 20
+        gamma: Option<f32>,      // None (automatic sRGB detection)
+        channel: Option<String>, // None (all channels)
+        cache: Option<bool>,     // None (automatic)
+        to_uv: UVTransform,      // Id
+    },
+    Checkerboard {
+        color0: Box<BSDFColorSpectrum>, // 0.4
+        color1: Box<BSDFColorSpectrum>, // 0.2
+        to_uv: UVTransform,             // Id
+    },
+    GridTexture {
+        color0: Spectrum,   // 0.4
+        color1: Spectrum,   // 0.2
+        line_width: f32,    // 0.01
+        to_uv: UVTransform, // Id
+    },
+    Scale {
+        texture: Box<Texture>,
+        value: TextureScale, // 1
+    },
+    /// Per-vertex colors of the mesh (PLY or serialized)
+    VertexColors,
+    WireFrame {
+        interior_color: Spectrum, // 0.5
+        edge_color: Spectrum,     // 0.1
+        line_width: f32,          // 0 (automatic)
+        step_width: f32,          // 0.5 [0-1]
+    },
+    Curvature {
+        curvature: CurvatureType, // mean
+        scale: f32,               // 1
+    },
+}
+impl Texture {
+    pub fn parse<R: Read>(
+        events: &mut Events<R>,
+        defaults: &HashMap<String, String>,
+        texture_type: &str,
+        scene: &mut Scene,
     ) -> Result<Self> {
-        let (mut map, refs) = values(events, defaults, true)?;
-        assert!(refs.is_empty());
+        // Nested textures (named or not)
+        let mut textures = HashMap::new();
+        let mut nested = vec![];
+        let mut to_uv = None;
+        let f = |events: &mut Events<R>, t: &str, attrs: HashMap<String, String>| -> Result<bool> {
+            match t {
+                "transform" => to_uv = Some(Transform::parse(events)),
+                "texture" => {
+                    let texture_type = attrs.get("type").unwrap();
+                    let texture = Texture::parse(events, defaults, texture_type, scene)?;
+                    if let Some(id) = attrs.get("id") {
+                        scene.textures.insert(id.to_string(), texture.clone());
+                    }
+                    match attrs.get("name") {
+                        Some(name) => {
+                            textures.insert(name.clone(), texture);
+                        }
+                        None => nested.push(texture),
+                    }
+                }
+                _ => panic!("Texture encounter unexpected token {:?}", t),
+            }
+            Ok(true)
+        };
+        let (mut map, refs) = values_fn(events, defaults, true, f)?;
+        for r in refs {
+            match scene.textures.get(&r) {
+                Some(v) => nested.push(v.clone()),
+                None => return Err(Error::UnknownReference(r)),
+            }
+        }
 
-        // Read offset and scale
-        let uoffset = read_value(&mut map, "uoffset", Value::Float(0.0)).as_float()?;
-        let voffset = read_value(&mut map, "voffset", Value::Float(0.0)).as_float()?;
-        let uscale = read_value(&mut map, "uscale", Value::Float(1.0)).as_float()?;
-        let vscale = read_value(&mut map, "vscale", Value::Float(1.0)).as_float()?;
-        let offset = Vector2::new(uoffset, voffset);
-        let scale = Vector2::new(uscale, vscale);
+        let to_uv = UVTransform::parse(&mut map, to_uv)?;
 
         match texture_type {
             "bitmap" => {
                 let filename = map.remove("filename").unwrap().as_string()?;
-                let filter_type = read_value(
-                    &mut map,
-                    "filterType",
-                    Value::String("trilinear".to_string()),
-                )
-                .as_string()?;
-                let gamma = read_value(&mut map, "alpha", Value::Float(1.0)).as_float()?;
+                // wrapModeU and wrapModeV default to wrapMode (wrap_mode for Mitsuba 2)
+                let (wrap_name, wrap_mode) = match map.remove("wrap_mode") {
+                    Some(v) => ("wrap_mode", v.as_string()?),
+                    None => (
+                        "wrapMode",
+                        read_value(&mut map, "wrapMode", Value::String("repeat".to_string()))
+                            .as_string()?,
+                    ),
+                };
+                let wrap_mode_u = match map.remove("wrapModeU") {
+                    Some(v) => WrapMode::parse("wrapModeU", &v.as_string()?)?,
+                    None => WrapMode::parse(wrap_name, &wrap_mode)?,
+                };
+                let wrap_mode_v = match map.remove("wrapModeV") {
+                    Some(v) => WrapMode::parse("wrapModeV", &v.as_string()?)?,
+                    None => wrap_mode_u,
+                };
+                let filter_type = match map.remove("filter_type") {
+                    Some(v) => FilterType::parse("filter_type", &v.as_string()?)?,
+                    None => {
+                        let v =
+                            read_value(&mut map, "filterType", Value::String("ewa".to_string()))
+                                .as_string()?;
+                        FilterType::parse("filterType", &v)?
+                    }
+                };
+                let max_anisotropy =
+                    read_value(&mut map, "maxAnisotropy", Value::Float(20.0)).as_float()?;
+                if max_anisotropy < 1.0 {
+                    return Err(Error::InvalidParameter(
+                        "maxAnisotropy".to_string(),
+                        format!("must be greater or equal to 1 (got {})", max_anisotropy),
+                    ));
+                }
+                // A gamma of -1 denotes the sRGB curve
+                let gamma = match map.remove("gamma") {
+                    Some(v) => Some(v.as_float()?),
+                    // Mitsuba 2 raw textures (e.g., normal maps) are linear
+                    None => {
+                        let raw = read_value(&mut map, "raw", Value::Boolean(false)).as_bool()?;
+                        if raw {
+                            Some(1.0)
+                        } else {
+                            None
+                        }
+                    }
+                };
+                if gamma == Some(0.0) {
+                    return Err(Error::InvalidParameter(
+                        "gamma".to_string(),
+                        "must be different from 0".to_string(),
+                    ));
+                }
+                let channel = match map.remove("channel") {
+                    Some(v) => Some(v.as_string()?.to_lowercase()),
+                    None => None,
+                };
+                let cache = match map.remove("cache") {
+                    Some(v) => Some(v.as_bool()?),
+                    None => None,
+                };
                 Ok(Texture::Bitmap {
                     filename,
+                    wrap_mode_u,
+                    wrap_mode_v,
                     filter_type,
+                    max_anisotropy,
                     gamma,
-                    offset,
-                    scale,
+                    channel,
+                    cache,
+                    to_uv,
                 })
             }
             "checkerboard" => {
-                let color0 =
-                    read_value(&mut map, "color0", Value::Spectrum(Spectrum::from_f32(0.4)))
-                        .as_spectrum()?;
-                let color1 =
-                    read_value(&mut map, "color1", Value::Spectrum(Spectrum::from_f32(0.2)))
-                        .as_spectrum()?;
+                let color0 = read_value_or_texture_spectrum(
+                    &mut map,
+                    "color0",
+                    Spectrum::from_f32(0.4),
+                    &textures,
+                    scene,
+                )?;
+                let color1 = read_value_or_texture_spectrum(
+                    &mut map,
+                    "color1",
+                    Spectrum::from_f32(0.2),
+                    &textures,
+                    scene,
+                )?;
                 Ok(Texture::Checkerboard {
-                    color0,
-                    color1,
-                    offset,
-                    scale,
+                    color0: Box::new(color0),
+                    color1: Box::new(color1),
+                    to_uv,
                 })
             }
             "gridtexture" => {
@@ -1230,19 +1955,213 @@ impl Texture {
                     color0,
                     color1,
                     line_width,
-                    offset,
-                    scale,
+                    to_uv,
+                })
+            }
+            "vertexcolors" => Ok(Texture::VertexColors),
+            "wireframe" => {
+                let interior_color =
+                    read_spectrum(&mut map, "interiorColor", Spectrum::from_f32(0.5))?;
+                let edge_color = read_spectrum(&mut map, "edgeColor", Spectrum::from_f32(0.1))?;
+                let line_width = read_value(&mut map, "lineWidth", Value::Float(0.0)).as_float()?;
+                let step_width = read_value(&mut map, "stepWidth", Value::Float(0.5)).as_float()?;
+                if line_width < 0.0 {
+                    return Err(Error::InvalidParameter(
+                        "lineWidth".to_string(),
+                        format!("must be positive (got {})", line_width),
+                    ));
+                }
+                if !(0.0..=1.0).contains(&step_width) {
+                    return Err(Error::InvalidParameter(
+                        "stepWidth".to_string(),
+                        format!("must be between 0 and 1 (got {})", step_width),
+                    ));
+                }
+                Ok(Texture::WireFrame {
+                    interior_color,
+                    edge_color,
+                    line_width,
+                    step_width,
+                })
+            }
+            "curvature" => {
+                let curvature =
+                    read_value(&mut map, "curvature", Value::String("mean".to_string()))
+                        .as_string()?;
+                let curvature = match &curvature.to_lowercase()[..] {
+                    "mean" => CurvatureType::Mean,
+                    "gaussian" => CurvatureType::Gaussian,
+                    _ => {
+                        return Err(Error::InvalidParameter(
+                            "curvature".to_string(),
+                            format!("{:?} must be mean or gaussian", curvature),
+                        ))
+                    }
+                };
+                let scale = read_value(&mut map, "scale", Value::Float(1.0)).as_float()?;
+                Ok(Texture::Curvature { curvature, scale })
+            }
+            "scale" => {
+                // The scale can be a value or a texture, any other
+                // texture is the scaled one
+                let value = match map.remove("scale") {
+                    None => match textures.remove("scale") {
+                        Some(t) => TextureScale::Texture(Box::new(t)),
+                        None => TextureScale::Float(1.0),
+                    },
+                    Some(Value::Float(v)) => TextureScale::Float(v),
+                    Some(Value::Spectrum(v)) => TextureScale::Spectrum(v),
+                    Some(v) => match v.as_bsdf_color_spec(scene)? {
+                        BSDFColor::Texture(t) => TextureScale::Texture(Box::new(t)),
+                        BSDFColor::Constant(v) => TextureScale::Spectrum(v),
+                    },
+                };
+                nested.extend(textures.into_values());
+                if nested.len() != 1 {
+                    return Err(Error::InvalidParameter(
+                        "texture".to_string(),
+                        format!(
+                            "scale need exactly one nested texture (got {})",
+                            nested.len()
+                        ),
+                    ));
+                }
+                Ok(Texture::Scale {
+                    texture: Box::new(nested.pop().unwrap()),
+                    value,
                 })
             }
             _ => panic!("Unsupported texture type: {}", texture_type),
         }
     }
+
+    /// Evaluate the texture at the surface coordinates `uv` (before the
+    /// texture offset and scale), following Mitsuba's definitions.
+    /// Bitmaps need to be loaded (see `eval_with_images`) and textures
+    /// relying on the mesh (vertex colors, wireframe and curvature)
+    /// cannot be evaluated.
+    pub fn eval(&self, uv: Point2<f32>) -> Result<RGB> {
+        self.eval_impl(uv, &|texture, _| {
+            Err(Error::Unsupported(format!(
+                "evaluation of the texture {:?} (images not loaded)",
+                texture
+            )))
+        })
+    }
+
+    /// Map surface UVs into the texture space
+    /// (identity for the textures without UV transform)
+    pub fn to_texture_space(&self, uv: Point2<f32>) -> Point2<f32> {
+        match self {
+            Texture::Bitmap { to_uv, .. }
+            | Texture::Checkerboard { to_uv, .. }
+            | Texture::GridTexture { to_uv, .. } => to_uv.apply(uv),
+            _ => uv,
+        }
+    }
+
+    /// Evaluate the texture as a float (average of the channels)
+    pub fn eval_f32(&self, uv: Point2<f32>) -> Result<f32> {
+        let v = self.eval(uv)?;
+        Ok((v.r + v.g + v.b) / 3.0)
+    }
+
+    /// Evaluate the texture with the decoded bitmap images
+    /// (see `images::read_bitmaps`)
+    #[cfg(feature = "images")]
+    pub fn eval_with_images(
+        &self,
+        uv: Point2<f32>,
+        images: &HashMap<images::BitmapKey, images::Image>,
+    ) -> Result<RGB> {
+        self.eval_impl(uv, &|texture, uv| match texture {
+            Texture::Bitmap {
+                filename,
+                wrap_mode_u,
+                wrap_mode_v,
+                filter_type,
+                ..
+            } => match images.get(&images::BitmapKey::new(texture).unwrap()) {
+                Some(image) => Ok(image.eval(uv, *wrap_mode_u, *wrap_mode_v, *filter_type)),
+                None => Err(Error::UnknownReference(filename.clone())),
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn eval_impl(
+        &self,
+        uv: Point2<f32>,
+        bitmap: &dyn Fn(&Texture, Point2<f32>) -> Result<RGB>,
+    ) -> Result<RGB> {
+        let color = |c: &BSDFColorSpectrum| match c {
+            BSDFColor::Constant(v) => v.clone().as_rgb(),
+            BSDFColor::Texture(t) => t.eval_impl(uv, bitmap),
+        };
+        match self {
+            Texture::Bitmap { .. } => bitmap(self, self.to_texture_space(uv)),
+            Texture::Checkerboard { color0, color1, .. } => {
+                let uv = self.to_texture_space(uv);
+                let x = 2 * ((uv.x * 2.0) as i32).rem_euclid(2) - 1;
+                let y = 2 * ((uv.y * 2.0) as i32).rem_euclid(2) - 1;
+                if x * y == 1 {
+                    color(color0)
+                } else {
+                    color(color1)
+                }
+            }
+            Texture::GridTexture {
+                color0,
+                color1,
+                line_width,
+                ..
+            } => {
+                let uv = self.to_texture_space(uv);
+                let mut x = uv.x - uv.x.floor();
+                let mut y = uv.y - uv.y.floor();
+                if x > 0.5 {
+                    x -= 1.0;
+                }
+                if y > 0.5 {
+                    y -= 1.0;
+                }
+                if x.abs() < *line_width || y.abs() < *line_width {
+                    color1.clone().as_rgb()
+                } else {
+                    color0.clone().as_rgb()
+                }
+            }
+            Texture::Scale { texture, value } => {
+                let v = texture.eval_impl(uv, bitmap)?;
+                let s = match value {
+                    TextureScale::Float(s) => RGB {
+                        r: *s,
+                        g: *s,
+                        b: *s,
+                    },
+                    TextureScale::Spectrum(s) => s.clone().as_rgb()?,
+                    TextureScale::Texture(t) => t.eval_impl(uv, bitmap)?,
+                };
+                Ok(RGB {
+                    r: v.r * s.r,
+                    g: v.g * s.g,
+                    b: v.b * s.b,
+                })
+            }
+            Texture::VertexColors | Texture::WireFrame { .. } | Texture::Curvature { .. } => {
+                Err(Error::Unsupported(format!(
+                    "evaluation of the texture {:?} (needs the mesh)",
+                    self
+                )))
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct AreaEmitter {
-    pub radiance: Spectrum,
-    pub sampling_weight: f32, // 1
+    pub radiance: BSDFColorSpectrum, // 1
+    pub sampling_weight: f32,        // 1
 }
 
 #[derive(Debug)]
@@ -1258,6 +2177,79 @@ pub struct SkyEmitterParam {
     pub albedo: Spectrum, // 0.15
 }
 
+#[derive(Debug, Clone, Copy)]
+enum SunSkyDefault {
+    Float(f32),
+    Integer(i32),
+    Spectrum(f32),
+}
+
+// Parameters of the sun, sky and sunsky emitters:
+// (key, emitter types, default, valid range)
+#[rustfmt::skip]
+const SUN_SKY_PARAMETERS: &[(&str, &[&str], SunSkyDefault, f32, f32)] = &[
+    ("turbidity", &["sun", "sky", "sunsky"], SunSkyDefault::Float(3.0), 1.0, 10.0),
+    ("resolution", &["sun", "sky", "sunsky"], SunSkyDefault::Integer(512), 2.0, f32::INFINITY),
+    ("scale", &["sun", "sky"], SunSkyDefault::Float(1.0), 0.0, f32::INFINITY),
+    ("sunRadiusScale", &["sun", "sunsky"], SunSkyDefault::Float(1.0), 0.0, f32::INFINITY),
+    ("stretch", &["sky", "sunsky"], SunSkyDefault::Float(1.0), 1.0, 2.0),
+    ("albedo", &["sky", "sunsky"], SunSkyDefault::Spectrum(0.15), 0.0, 1.0),
+    ("skyScale", &["sunsky"], SunSkyDefault::Float(1.0), 0.0, f32::INFINITY),
+    ("sunScale", &["sunsky"], SunSkyDefault::Float(1.0), 0.0, f32::INFINITY),
+];
+
+// Read the parameters of the emitter type (or their default values)
+// and check their range
+fn read_sun_sky_parameters(
+    map: &mut HashMap<String, Value>,
+    emitter_type: &str,
+) -> Result<HashMap<&'static str, Value>> {
+    let mut params = HashMap::new();
+    for (key, emitters, default, min, max) in SUN_SKY_PARAMETERS {
+        if !emitters.contains(&emitter_type) {
+            if map.contains_key(*key) {
+                return Err(Error::InvalidParameter(
+                    key.to_string(),
+                    format!("not a parameter of the {} emitter", emitter_type),
+                ));
+            }
+            continue;
+        }
+
+        let (value, values) = match default {
+            SunSkyDefault::Float(v) => {
+                let v = read_value(map, key, Value::Float(*v)).as_float()?;
+                (Value::Float(v), vec![v])
+            }
+            SunSkyDefault::Integer(v) => {
+                let v = read_value(map, key, Value::Integer(*v)).as_int()?;
+                (Value::Integer(v), vec![v as f32])
+            }
+            SunSkyDefault::Spectrum(v) => {
+                let v = read_spectrum(map, key, Spectrum::from_f32(*v))?;
+                // Only RGB values are checked
+                let values = match v.clone().as_rgb() {
+                    Ok(rgb) => vec![rgb.r, rgb.g, rgb.b],
+                    Err(_) => vec![],
+                };
+                (Value::Spectrum(v), values)
+            }
+        };
+        if let Some(v) = values.iter().find(|v| **v < *min || **v > *max) {
+            return Err(Error::InvalidParameter(
+                key.to_string(),
+                if max.is_infinite() {
+                    format!("{} is below {}", v, min)
+                } else {
+                    format!("{} is outside [{}, {}]", v, min, max)
+                },
+            ));
+        }
+        params.insert(*key, value);
+    }
+    Ok(params)
+}
+
 #[derive(Debug)]
 pub enum SunDirection {
     Vector(Vector3<f32>),
@@ -1280,11 +2272,28 @@ impl SunDirection {
     ) -> Result<Self> {
         let sun_direction = map.remove("sunDirection");
         if let Some(sun_direction) = sun_direction {
+            let date_and_pos = [
+                "year",
+                "month",
+                "day",
+                "hour",
+                "minute",
+                "second",
+                "latitude",
+                "longitude",
+                "timezone",
+            ];
+            if let Some(n) = date_and_pos.iter().find(|n| map.contains_key(**n)) {
+                return Err(Error::InvalidParameter(
+                    n.to_string(),
+                    "cannot be specified together with sunDirection".to_string(),
+                ));
+            }
             Ok(SunDirection::Vector(sun_direction.as_vec()?))
         } else {
             // Date
             let year = read_value(&mut map, "year", Value::Integer(2010)).as_int()?;
-            let month = read_value(&mut map, "month", Value::Integer(10)).as_int()?;
+            let month = read_value(&mut map, "month", Value::Integer(7)).as_int()?;
             let day = read_value(&mut map, "day", Value::Integer(10)).as_int()?;
             // Time
             let hour = read_value(&mut map, "hour", Value::Float(15.0)).as_float()?;
@@ -1307,6 +2316,97 @@ impl SunDirection {
             })
         }
     }
+
+    /// Direction toward the sun in the emitter space (normalized, y up and
+    /// north along -z). See `Emitter::sun_direction` for the world space.
+    ///
+    /// For dates and positions, the sun position follows the algorithm of
+    /// Blanco-Muriel et al. (2001) as Mitsuba 0.6 (`sun.h`).
+    pub fn to_vector(&self) -> Vector3<f32> {
+        match self {
+            SunDirection::Vector(v) => v.normalize(),
+            SunDirection::DateAndPos {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                second,
+                latitude,
+                longitude,
+                timezone,
+            } => {
+                let (theta, phi) = sun_coordinates(
+                    (*year, *month, *day),
+                    (*hour as f64, *minute as f64, *second as f64),
+                    (*latitude as f64, *longitude as f64, *timezone as f64),
+                );
+                // Mitsuba's toSphere
+                Vector3::new(
+                    (phi.sin() * theta.sin()) as f32,
+                    theta.cos() as f32,
+                    (-phi.cos() * theta.sin()) as f32,
+                )
+            }
+        }
+    }
+}
+
+// Zenith angle and azimuth (from north, clockwise) of the sun in radians
+fn sun_coordinates(
+    (year, month, day): (i32, i32, i32),
+    (hour, minute, second): (f64, f64, f64),
+    (latitude, longitude, timezone): (f64, f64, f64),
+) -> (f64, f64) {
+    const EARTH_MEAN_RADIUS: f64 = 6371.01; // km
+    const ASTRONOMICAL_UNIT: f64 = 149_597_890.0; // km
+
+    // Elapsed days since JD 2451545.0 (noon 1 January 2000 UT)
+    let dec_hours = hour - timezone + (minute + second / 60.0) / 60.0;
+    let aux1 = (month - 14) / 12;
+    let aux2 = (1461 * (year + 4800 + aux1)) / 4 + (367 * (month - 2 - 12 * aux1)) / 12
+        - (3 * ((year + 4900 + aux1) / 100)) / 4
+        + day
+        - 32075;
+    let julian_date = aux2 as f64 - 0.5 + dec_hours / 24.0;
+    let elapsed_days = julian_date - 2451545.0;
+
+    // Ecliptic coordinates
+    let omega = 2.1429 - 0.001_039_459_4 * elapsed_days;
+    let mean_longitude = 4.895_063 + 0.017_202_791_698 * elapsed_days;
+    let anomaly = 6.240_06 + 0.017_201_969_9 * elapsed_days;
+    let ecliptic_longitude =
+        mean_longitude + 0.033_416_07 * anomaly.sin() + 0.000_348_94 * (2.0 * anomaly).sin()
+            - 0.000_113_4
+            - 0.000_020_3 * omega.sin();
+    let ecliptic_obliquity = 0.409_092_8 - 6.214e-9 * elapsed_days + 0.000_039_6 * omega.cos();
+
+    // Celestial coordinates
+    let sin_ecliptic_longitude = ecliptic_longitude.sin();
+    let mut right_ascension =
+        (ecliptic_obliquity.cos() * sin_ecliptic_longitude).atan2(ecliptic_longitude.cos());
+    if right_ascension < 0.0 {
+        right_ascension += 2.0 * std::f64::consts::PI;
+    }
+    let declination = (ecliptic_obliquity.sin() * sin_ecliptic_longitude).asin();
+
+    // Local coordinates
+    let greenwich_mean_sidereal_time = 6.697_424_324_2 + 0.065_709_828_3 * elapsed_days + dec_hours;
+    let local_mean_sidereal_time = (greenwich_mean_sidereal_time * 15.0 + longitude).to_radians();
+    let latitude = latitude.to_radians();
+    let hour_angle = local_mean_sidereal_time - right_ascension;
+    let mut zenith = (latitude.cos() * hour_angle.cos() * declination.cos()
+        + declination.sin() * latitude.sin())
+    .acos();
+    let mut azimuth = (-hour_angle.sin())
+        .atan2(declination.tan() * latitude.cos() - latitude.sin() * hour_angle.cos());
+    if azimuth < 0.0 {
+        azimuth += 2.0 * std::f64::consts::PI;
+    }
+
+    // Parallax correction
+    zenith += (EARTH_MEAN_RADIUS / ASTRONOMICAL_UNIT) * zenith.sin();
+    (zenith, azimuth)
 }
 
 #[derive(Debug)]
@@ -1367,13 +2467,13 @@ pub enum Emitter {
     },
     // SunSky, Sun or Sky
     SunSky {
+        to_world: Transform,  // Id
         turbidity: f32,       // f32 [1-10], default 3
         resolution: u32,      // u32 512
         sampling_weight: f32, // f32 1.0
         sun_direction: SunDirection,
         sun: Option<SunEmitterParam>,
         sky: Option<SkyEmitterParam>,
-        // toWorld -> Not supported
     },
 }
 impl Emitter {
@@ -1381,13 +2481,24 @@ impl Emitter {
         events: &mut Events<R>,
         defaults: &HashMap<String, String>,
         emitter_type: &str,
+        scene: &mut Scene,
     ) -> Result<Self> {
         let mut to_world = Transform(Matrix4::one());
-        // TODO: Texture
-        let f = |events: &mut Events<R>, t: &str, _: HashMap<String, String>| -> Result<bool> {
+        // Textures (area radiance or spot texture)
+        let mut textures = HashMap::new();
+        let f = |events: &mut Events<R>, t: &str, attrs: HashMap<String, String>| -> Result<bool> {
             match t {
                 "transform" => to_world = Transform::parse(events),
-                _ => panic!("Unexpected token!"),
+                "texture" => {
+                    let texture_name = attrs.get("name").unwrap();
+                    let texture_type = attrs.get("type").unwrap();
+                    let texture = Texture::parse(events, defaults, texture_type, scene)?;
+                    if let Some(id) = attrs.get("id") {
+                        scene.textures.insert(id.to_string(), texture.clone());
+                    }
+                    textures.insert(texture_name.clone(), texture);
+                }
+                _ => panic!("Emitter encounter unexpected token {:?}", t),
             }
             Ok(true)
         };
@@ -1399,7 +2510,14 @@ impl Emitter {
             read_value(&mut map, "samplingWeight", Value::Float(1.0)).as_float()?;
         match emitter_type {
             "area" => {
-                let radiance = map.remove("radiance").unwrap().as_spectrum()?;
+                // Mitsuba defaults to D65 (white in RGB mode)
+                let radiance = read_value_or_texture_spectrum(
+                    &mut map,
+                    "radiance",
+                    Spectrum::from_f32(1.0),
+                    &textures,
+                    scene,
+                )?;
                 Ok(Emitter::Area(AreaEmitter {
                     radiance,
                     sampling_weight,
@@ -1407,12 +2525,7 @@ impl Emitter {
             }
             "point" => {
                 let position = map.remove("position").unwrap().as_point()?;
-                let intensity = read_value(
-                    &mut map,
-                    "intensity",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
-                )
-                .as_spectrum()?;
+                let intensity = read_spectrum(&mut map, "intensity", Spectrum::from_f32(1.0))?;
                 Ok(Emitter::Point {
                     to_world,
                     position,
@@ -1423,12 +2536,7 @@ impl Emitter {
             "point-normal" => {
                 let position = map.remove("position").unwrap().as_point()?;
                 let normal = map.remove("normal").unwrap().as_vec()?;
-                let intensity = read_value(
-                    &mut map,
-                    "intensity",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
-                )
-                .as_spectrum()?;
+                let intensity = read_spectrum(&mut map, "intensity", Spectrum::from_f32(1.0))?;
                 Ok(Emitter::PointNormal {
                     to_world,
                     position,
@@ -1438,12 +2546,7 @@ impl Emitter {
                 })
             }
             "spot" => {
-                let intensity = read_value(
-                    &mut map,
-                    "intensity",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
-                )
-                .as_spectrum()?;
+                let intensity = read_spectrum(&mut map, "intensity", Spectrum::from_f32(1.0))?;
                 let cutoff_angle =
                     read_value(&mut map, "cutoffAngle", Value::Float(20.0)).as_float()?;
                 let beam_width = read_value(
@@ -1452,7 +2555,19 @@ impl Emitter {
                     Value::Float(cutoff_angle * 3.0 / 4.0),
                 )
                 .as_float()?;
-                let texture = None;
+                // Projected texture (no constant allowed)
+                let texture = match map.remove("texture") {
+                    Some(v) => match v.as_bsdf_color_spec(scene)? {
+                        BSDFColor::Texture(t) => Some(t),
+                        BSDFColor::Constant(_) => {
+                            return Err(Error::InvalidParameter(
+                                "texture".to_string(),
+                                "must be a texture".to_string(),
+                            ))
+                        }
+                    },
+                    None => textures.remove("texture"),
+                };
                 Ok(Emitter::Spot {
                     to_world,
                     intensity,
@@ -1464,12 +2579,7 @@ impl Emitter {
             }
             "directional" => {
                 let direction = map.remove("direction").unwrap().as_vec()?;
-                let irradiance = read_value(
-                    &mut map,
-                    "irradiance",
-                    Value::Spectrum(Spectrum::from_f32(1.0)),
-                )
-                .as_spectrum()?;
+                let irradiance = read_spectrum(&mut map, "irradiance", Spectrum::from_f32(1.0))?;
                 Ok(Emitter::Directional {
                     to_world,
                     direction,
@@ -1478,7 +2588,7 @@ impl Emitter {
                 })
             }
             "collimated" => {
-                let power = map.remove("power").unwrap().as_spectrum()?;
+                let power = read_spectrum(&mut map, "power", Spectrum::from_f32(1.0))?;
                 Ok(Emitter::Collimated {
                     to_world,
                     power,
@@ -1486,7 +2596,7 @@ impl Emitter {
                 })
             }
             "constant" => {
-                let radiance = map.remove("radiance").unwrap().as_spectrum()?;
+                let radiance = read_spectrum(&mut map, "radiance", Spectrum::from_f32(1.0))?;
                 Ok(Emitter::Constant {
                     radiance,
                     sampling_weight,
@@ -1513,74 +2623,42 @@ impl Emitter {
                 })
             }
             "sunsky" | "sun" | "sky" => {
-                let turbidity = read_value(&mut map, "turbidity", Value::Float(3.0)).as_float()?;
-                let resolution =
-                    read_value(&mut map, "turbidity", Value::Integer(512)).as_int()? as u32;
+                let mut params = read_sun_sky_parameters(&mut map, emitter_type)?;
+                let mut param = |key: &str| params.remove(key).unwrap();
+                let turbidity = param("turbidity").as_float()?;
+                let resolution = param("resolution").as_int()? as u32;
                 let sun_direction = SunDirection::parse(&mut map, defaults)?;
                 let (sun, sky) = match emitter_type {
-                    "sun" => {
-                        let scale = read_value(&mut map, "scale", Value::Float(1.0)).as_float()?;
-                        let radius_scale =
-                            read_value(&mut map, "sunRadiusScale", Value::Float(1.0)).as_float()?;
-                        (
-                            Some(SunEmitterParam {
-                                scale,
-                                radius_scale,
-                            }),
-                            None,
-                        )
-                    }
-                    "sky" => {
-                        let scale = read_value(&mut map, "scale", Value::Float(1.0)).as_float()?;
-                        let stretch =
-                            read_value(&mut map, "stretch", Value::Float(1.0)).as_float()?;
-                        let albedo = read_value(
-                            &mut map,
-                            "stretch",
-                            Value::Spectrum(Spectrum::from_f32(0.15)),
-                        )
-                        .as_spectrum()?;
-                        (
-                            None,
-                            Some(SkyEmitterParam {
-                                scale,
-                                stretch,
-                                albedo,
-                            }),
-                        )
-                    }
-                    "sunsky" => {
-                        // Sky
-                        let sky_scale =
-                            read_value(&mut map, "skyScale", Value::Float(1.0)).as_float()?;
-                        let stretch =
-                            read_value(&mut map, "stretch", Value::Float(1.0)).as_float()?;
-                        let albedo = read_value(
-                            &mut map,
-                            "stretch",
-                            Value::Spectrum(Spectrum::from_f32(0.15)),
-                        )
-                        .as_spectrum()?;
-                        // Sun
-                        let sun_scale =
-                            read_value(&mut map, "sunScale", Value::Float(1.0)).as_float()?;
-                        let radius_scale =
-                            read_value(&mut map, "sunRadiusScale", Value::Float(1.0)).as_float()?;
-                        (
-                            Some(SunEmitterParam {
-                                scale: sun_scale,
-                                radius_scale,
-                            }),
-                            Some(SkyEmitterParam {
-                                scale: sky_scale,
-                                stretch,
-                                albedo,
-                            }),
-                        )
-                    }
-                    _ => unimplemented!(),
+                    "sun" => (
+                        Some(SunEmitterParam {
+                            scale: param("scale").as_float()?,
+                            radius_scale: param("sunRadiusScale").as_float()?,
+                        }),
+                        None,
+                    ),
+                    "sky" => (
+                        None,
+                        Some(SkyEmitterParam {
+                            scale: param("scale").as_float()?,
+                            stretch: param("stretch").as_float()?,
+                            albedo: param("albedo").as_spectrum()?,
+                        }),
+                    ),
+                    "sunsky" => (
+                        Some(SunEmitterParam {
+                            scale: param("sunScale").as_float()?,
+                            radius_scale: param("sunRadiusScale").as_float()?,
+                        }),
+                        Some(SkyEmitterParam {
+                            scale: param("skyScale").as_float()?,
+                            stretch: param("stretch").as_float()?,
+                            albedo: param("albedo").as_spectrum()?,
+                        }),
+                    ),
+                    _ => unreachable!(),
                 };
                 Ok(Emitter::SunSky {
+                    to_world,
                     turbidity,
                     resolution,
                     sun_direction,
@@ -1602,6 +2680,22 @@ impl Emitter {
             _ => panic!("Wrong emitter type {:?} (as_area)", self),
         }
     }
+
+    /// Direction toward the sun of the `sun`, `sky` and `sunsky` emitters
+    /// in world space (`to_world` applied as Mitsuba), None otherwise
+    pub fn sun_direction(&self) -> Option<Vector3<f32>> {
+        match self {
+            Emitter::SunSky {
+                to_world,
+                sun_direction,
+                ..
+            } => {
+                let d = to_world.clone().as_matrix() * sun_direction.to_vector().extend(0.0);
+                Some(d.truncate().normalize())
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1683,6 +2777,48 @@ impl Medium {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum Subsurface {
+    Dipole {
+        coefficients: ScatteringParameters, // skin1
+        scale: f32,                         // 1
+        int_ior: f32,                       // intIOR "bk7"
+        ext_ior: f32,                       // extIOR "air"
+        irr_samples: i32,                   // 16
+    },
+}
+
+impl Subsurface {
+    pub fn parse<R: Read>(
+        events: &mut Events<R>,
+        defaults: &HashMap<String, String>,
+        subsurface_type: &str,
+    ) -> Result<Self> {
+        let (mut map, refs) = values(events, defaults, true)?;
+        assert!(refs.is_empty());
+        match subsurface_type {
+            "dipole" => {
+                let coefficients = ScatteringParameters::parse(&mut map, "skin1")?;
+                let scale = read_value(&mut map, "scale", Value::Float(1.0)).as_float()?;
+                let int_ior =
+                    read_value(&mut map, "intIOR", Value::String("bk7".to_string())).as_ior()?;
+                let ext_ior =
+                    read_value(&mut map, "extIOR", Value::String("air".to_string())).as_ior()?;
+                let irr_samples =
+                    read_value(&mut map, "irrSamples", Value::Integer(16)).as_int()?;
+                Ok(Subsurface::Dipole {
+                    coefficients,
+                    scale,
+                    int_ior,
+                    ext_ior,
+                    irr_samples,
+                })
+            }
+            _ => panic!("[ERROR] Uncovered {} subsurface type", subsurface_type),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ShapeOption {
     pub flip_normal: bool, // false
@@ -1691,6 +2827,7 @@ pub struct ShapeOption {
     pub emitter: Option<AreaEmitter>,
     pub interior: Option<Medium>,
     pub exterior: Option<Medium>,
+    pub subsurface: Option<Subsurface>,
 }
 
 #[derive(Debug)]
@@ -1763,6 +2900,7 @@ impl Shape {
         let mut bsdf = None;
         let mut to_world = None;
         let mut emitter = None;
+        let mut subsurface = None;
         let mut shapes = vec![];
         let mut shape = None; // Only for instance
 
@@ -1775,7 +2913,12 @@ impl Shape {
                 "transform" => to_world = Some(Transform::parse(events)),
                 "emitter" => {
                     let emitter_type = attrs.get("type").unwrap();
-                    emitter = Some(Emitter::parse(events, defaults, emitter_type)?.as_area());
+                    emitter =
+                        Some(Emitter::parse(events, defaults, emitter_type, scene)?.as_area());
+                }
+                "subsurface" => {
+                    let subsurface_type = attrs.get("type").unwrap();
+                    subsurface = Some(Subsurface::parse(events, defaults, subsurface_type)?);
                 }
                 "shape" => {
                     let shape_type = attrs.get("type").unwrap();
@@ -1834,6 +2977,7 @@ impl Shape {
             emitter,
             interior,
             exterior,
+            subsurface,
         };
 
         // Read some values in advance to reduce the redundancy
@@ -1966,7 +3110,9 @@ impl Transform {
                         let z = found_attrib_or(&attributes, "z", "0.0")
                             .parse::<f32>()
                             .unwrap();
-                        trans = trans * Matrix4::from_translation(Vector3::new(x, y, z));
+                        // Transposed as the other operations (see the final transpose)
+                        trans =
+                            trans * Matrix4::from_translation(Vector3::new(x, y, z)).transpose();
                         opened += 1;
                     }
                     "scale" => {
@@ -2167,6 +3313,8 @@ pub struct Scene {
     pub sensors: Vec<Sensor>,
     pub emitters: Vec<Emitter>,
     pub medium: HashMap<String, Medium>,
+    // Directory of the scene file being parsed (for the included files)
+    wk: std::path::PathBuf,
 }
 impl Scene {
     // TODO:
@@ -2179,6 +3327,20 @@ impl Scene {
 pub mod ply;
 #[cfg(feature = "serialized")]
 pub mod serialized;
+pub mod color;
+pub mod conductor;
+pub mod ior;
+pub mod spectral;
+pub mod scattering;
+pub mod weave;
+#[cfg(feature = "measured")]
+pub mod measured;
+#[cfg(feature = "eval")]
+pub mod eval;
+#[cfg(feature = "images")]
+pub mod images;
+#[cfg(feature = "sky")]
+pub mod sky;
 
 fn parse_scene(filename: &str, mut scene: &mut Scene) -> Result<()> {
     let file = File::open(filename).expect(&format!("Impossible to open {}", filename));
@@ -2186,6 +3348,12 @@ fn parse_scene(filename: &str, mut scene: &mut Scene) -> Result<()> {
 
     let parser = EventReader::new(file);
     let mut defaults = HashMap::new();
+    // Relative filenames are resolved from the current scene file
+    let wk = std::path::Path::new(filename)
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let wk = std::mem::replace(&mut scene.wk, wk);
 
     let mut iter = parser.into_iter();
     loop {
@@ -2212,7 +3380,7 @@ fn parse_scene(filename: &str, mut scene: &mut Scene) -> Result<()> {
                 "texture" => {
                     let texture_id = found_attrib_or_error(&attributes, "id", "texture")?;
                     let texture_type = found_attrib_or_error(&attributes, "type", "texture")?;
-                    let texture = Texture::parse(&mut iter, &defaults, &texture_type)?;
+                    let texture = Texture::parse(&mut iter, &defaults, &texture_type, &mut scene)?;
                     scene.textures.insert(texture_id, texture);
                 }
                 "sensor" => {
@@ -2222,7 +3390,7 @@ fn parse_scene(filename: &str, mut scene: &mut Scene) -> Result<()> {
                 }
                 "emitter" => {
                     let emitter_type = found_attrib_or_error(&attributes, "type", "emitter")?;
-                    let emitter = Emitter::parse(&mut iter, &defaults, &emitter_type)?;
+                    let emitter = Emitter::parse(&mut iter, &defaults, &emitter_type, &mut scene)?;
                     scene.emitters.push(emitter);
                 }
                 "default" => {
@@ -2286,6 +3454,7 @@ fn parse_scene(filename: &str, mut scene: &mut Scene) -> Result<()> {
                             emitter: None,
                             interior: None,
                             exterior: None,
+                            subsurface: None,
                         },
                     });
                 }
@@ -2302,10 +3471,29 @@ fn parse_scene(filename: &str, mut scene: &mut Scene) -> Result<()> {
         }
     }
 
+    scene.wk = wk;
     Ok(())
 }
 
+/// Options to control the scene parsing
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Color space in which the RGB colors are converted (None keeps
+    /// Mitsuba's linear sRGB). Only bounded, albedo-like quantities
+    /// (reflectances, transmittances, opacities and albedos, including
+    /// the textures providing them) and emitted quantities (radiance,
+    /// irradiance, intensity) are converted. Physical coefficients are
+    /// kept untouched: indices of refraction (eta, k), absorption and
+    /// scattering coefficients (sigmaA, sigmaS, sigmaT) and float
+    /// parameters or multipliers even when given as RGB or textures.
+    pub color_space: Option<color::ColorSpace>,
+}
+
 pub fn parse(file: &str) -> Result<Scene> {
+    parse_with_options(file, &ParseOptions::default())
+}
+
+pub fn parse_with_options(file: &str, options: &ParseOptions) -> Result<Scene> {
     let mut scene = Scene {
         bsdfs: HashMap::new(),
         textures: HashMap::new(),
@@ -2314,8 +3502,12 @@ pub fn parse(file: &str) -> Result<Scene> {
         sensors: Vec::new(),
         emitters: Vec::new(),
         medium: HashMap::new(),
+        wk: std::path::PathBuf::new(),
     };
     parse_scene(file, &mut scene)?;
+    if let Some(color_space) = options.color_space {
+        scene.convert_color_space(color_space)?;
+    }
     Ok(scene)
 }
 
@@ -2426,6 +3618,1362 @@ mod tests {
         print_scene(crate::parse(s));
     }
 
+    // Write the scene inside a temporary file and parse it
+    fn parse_str(name: &str, xml: &str, options: &crate::ParseOptions) -> crate::Scene {
+        let filename = std::env::temp_dir().join(format!("mitsuba_rs_{}.xml", name));
+        std::fs::write(&filename, xml).unwrap();
+        crate::parse_with_options(filename.to_str().unwrap(), options).unwrap()
+    }
+
+    // Same as parse_str, but the scene needs to be rejected
+    fn parse_err(name: &str, xml: &str) {
+        let filename = std::env::temp_dir().join(format!("mitsuba_rs_{}.xml", name));
+        std::fs::write(&filename, xml).unwrap();
+        assert!(crate::parse(filename.to_str().unwrap()).is_err(), "{}", xml);
+    }
+
+    #[test]
+    fn color_space_conversion() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="diffuse" id="red">
+                <rgb name="reflectance" value="1.0, 0.0, 0.0"/>
+            </bsdf>
+            <emitter type="constant">
+                <spectrum name="radiance" value="1.0"/>
+            </emitter>
+        </scene>"#;
+        let options = crate::ParseOptions {
+            color_space: Some(crate::color::ColorSpace::Rec2020),
+        };
+        let scene = parse_str("color_space", xml, &options);
+        let rgb = match &scene.bsdfs["red"] {
+            crate::BSDF::Diffuse {
+                reflectance: crate::BSDFColor::Constant(s),
+            } => s.clone().as_rgb().unwrap(),
+            _ => panic!("Wrong BSDF"),
+        };
+        assert!((rgb.r - 0.6274).abs() < 1e-3);
+        assert!((rgb.g - 0.0691).abs() < 1e-3);
+        let rgb = match &scene.emitters[0] {
+            crate::Emitter::Constant { radiance, .. } => radiance.clone().as_rgb().unwrap(),
+            _ => panic!("Wrong emitter"),
+        };
+        assert!((rgb.r - 1.0).abs() < 1e-3 && (rgb.b - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn bumpmap() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="roughplastic" id="plastic"/>
+            <bsdf type="bumpmap" id="bump">
+                <texture type="scale">
+                    <float name="scale" value="0.5"/>
+                    <texture type="bitmap">
+                        <string name="filename" value="height.png"/>
+                    </texture>
+                </texture>
+                <bsdf type="diffuse"/>
+            </bsdf>
+            <bsdf type="bumpmap" id="bump_ref">
+                <ref id="plastic"/>
+                <texture type="bitmap">
+                    <string name="filename" value="height.png"/>
+                </texture>
+            </bsdf>
+            <bsdf type="normalmap" id="normal">
+                <texture name="normalmap" type="bitmap">
+                    <boolean name="raw" value="true"/>
+                    <string name="filename" value="normal.png"/>
+                </texture>
+                <bsdf type="diffuse"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("bumpmap", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["bump"] {
+            crate::BSDF::BumpMap {
+                texture: crate::Texture::Bitmap { filename, .. },
+                scale,
+                bsdf,
+            } => {
+                assert_eq!(filename, "height.png");
+                assert_eq!(*scale, 0.5);
+                assert!(matches!(**bsdf, crate::BSDF::Diffuse { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["bump_ref"] {
+            crate::BSDF::BumpMap { scale, bsdf, .. } => {
+                assert_eq!(*scale, 1.0);
+                assert!(matches!(**bsdf, crate::BSDF::Plastic { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        assert!(matches!(
+            scene.bsdfs["normal"],
+            crate::BSDF::NormalMap { .. }
+        ));
+
+        // Normal maps cannot be scaled
+        let xml = r#"<scene version="0.6.0"><bsdf type="normalmap" id="normal">
+                <texture type="scale">
+                    <float name="scale" value="0.5"/>
+                    <texture type="bitmap"><string name="filename" value="normal.png"/></texture>
+                </texture>
+                <bsdf type="diffuse"/>
+            </bsdf></scene>"#;
+        parse_err("normalmap_scaled", xml);
+    }
+
+    #[test]
+    fn coating() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="conductor" id="metal">
+                <string name="material" value="Au"/>
+            </bsdf>
+            <bsdf type="coating" id="varnish">
+                <float name="thickness" value="2"/>
+                <rgb name="sigmaA" value="0.1, 0.2, 0.3"/>
+                <bsdf type="diffuse"/>
+            </bsdf>
+            <bsdf type="roughcoating" id="paint">
+                <string name="distribution" value="ggx"/>
+                <float name="alpha" value="0.3"/>
+                <string name="intIOR" value="water"/>
+                <ref id="metal"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("coating", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["varnish"] {
+            crate::BSDF::Coating {
+                distribution,
+                int_ior,
+                thickness,
+                sigma_a: crate::BSDFColor::Constant(sigma_a),
+                bsdf,
+                ..
+            } => {
+                assert!(distribution.is_none());
+                assert_eq!(*int_ior, 1.5046);
+                assert_eq!(*thickness, 2.0);
+                assert_eq!(sigma_a.value, "0.1, 0.2, 0.3");
+                assert!(matches!(**bsdf, crate::BSDF::Diffuse { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["paint"] {
+            crate::BSDF::Coating {
+                distribution: Some(distribution),
+                int_ior,
+                bsdf,
+                ..
+            } => {
+                assert_eq!(distribution.distribution, crate::MicrofacetType::GGX);
+                assert!(distribution.sample_visible);
+                assert_eq!(*int_ior, 1.333);
+                assert!(matches!(**bsdf, crate::BSDF::Conductor { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+    }
+
+    #[test]
+    fn microfacet_distribution() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="roughconductor" id="brushed">
+                <string name="distribution" value="as"/>
+                <float name="alphaU" value="0.05"/>
+                <float name="alphaV" value="0.3"/>
+            </bsdf>
+            <bsdf type="roughdielectric" id="glass">
+                <float name="alpha_u" value="0.2"/>
+                <float name="alpha_v" value="0.2"/>
+                <boolean name="sampleVisible" value="false"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("microfacet", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["brushed"] {
+            crate::BSDF::Conductor {
+                distribution: Some(d),
+                ..
+            } => {
+                assert_eq!(d.distribution, crate::MicrofacetType::AshikhminShirley);
+                assert!(!d.sample_visible);
+                assert!(matches!(d.alpha, crate::Alpha::Anisotropic { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["glass"] {
+            crate::BSDF::Dielectric {
+                distribution: Some(d),
+                ..
+            } => {
+                assert_eq!(d.distribution, crate::MicrofacetType::Beckmann);
+                assert!(!d.sample_visible);
+                assert!(matches!(
+                    d.alpha,
+                    crate::Alpha::Isotropic(crate::BSDFColor::Constant(a)) if a == 0.2
+                ));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+    }
+
+    #[test]
+    fn microfacet_distribution_errors() {
+        let invalid = [
+            // Unknown distribution
+            r#"<string name="distribution" value="blinn"/>"#,
+            // Isotropic and anisotropic roughness together
+            r#"<float name="alpha" value="0.1"/><float name="alphaU" value="0.2"/>"#,
+            // Missing alphaV
+            r#"<float name="alphaU" value="0.2"/>"#,
+        ];
+        for (i, params) in invalid.iter().enumerate() {
+            let xml = format!(
+                r#"<scene version="0.6.0"><bsdf type="roughconductor" id="a">{}</bsdf></scene>"#,
+                params
+            );
+            parse_err(&format!("microfacet_{}", i), &xml);
+        }
+
+        // Anisotropy is not supported by roughplastic
+        let xml = r#"<scene version="0.6.0"><bsdf type="roughplastic" id="a">
+            <float name="alphaU" value="0.1"/><float name="alphaV" value="0.2"/>
+        </bsdf></scene>"#;
+        parse_err("microfacet_plastic", xml);
+    }
+
+    #[test]
+    fn blendbsdf() {
+        let xml = r#"<scene version="0.6.0">
+            <texture type="checkerboard" id="mud_mask"/>
+            <bsdf type="diffuse" id="mud"/>
+            <bsdf type="roughconductor" id="metal"/>
+            <bsdf type="blendbsdf" id="inline">
+                <float name="weight" value="0.25"/>
+                <bsdf type="diffuse"/>
+                <bsdf type="conductor"/>
+            </bsdf>
+            <bsdf type="blendbsdf" id="textured">
+                <texture name="weight" type="bitmap">
+                    <string name="filename" value="mask.png"/>
+                </texture>
+                <ref id="mud"/>
+                <ref id="metal"/>
+            </bsdf>
+            <bsdf type="blendbsdf" id="mixed">
+                <ref id="mud"/>
+                <bsdf type="conductor"/>
+            </bsdf>
+            <bsdf type="blendbsdf" id="mixed_inline_first">
+                <bsdf type="conductor"/>
+                <ref id="mud"/>
+            </bsdf>
+            <bsdf type="blendbsdf" id="textured_ref">
+                <ref name="weight" id="mud_mask"/>
+                <ref id="mud"/>
+                <ref id="metal"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("blendbsdf", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["inline"] {
+            crate::BSDF::Blend {
+                weight: crate::BSDFColor::Constant(w),
+                bsdfs,
+            } => {
+                assert_eq!(*w, 0.25);
+                assert!(matches!(*bsdfs[0], crate::BSDF::Diffuse { .. }));
+                assert!(matches!(*bsdfs[1], crate::BSDF::Conductor { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["textured"] {
+            crate::BSDF::Blend {
+                weight: crate::BSDFColor::Texture(crate::Texture::Bitmap { .. }),
+                bsdfs,
+            } => {
+                assert!(matches!(*bsdfs[0], crate::BSDF::Diffuse { .. }));
+                assert!(matches!(*bsdfs[1], crate::BSDF::Conductor { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        // Nested BSDFs and references keep the document order
+        match &scene.bsdfs["mixed"] {
+            crate::BSDF::Blend { bsdfs, .. } => {
+                assert!(matches!(*bsdfs[0], crate::BSDF::Diffuse { .. }));
+                assert!(matches!(*bsdfs[1], crate::BSDF::Conductor { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["mixed_inline_first"] {
+            crate::BSDF::Blend { bsdfs, .. } => {
+                assert!(matches!(*bsdfs[0], crate::BSDF::Conductor { .. }));
+                assert!(matches!(*bsdfs[1], crate::BSDF::Diffuse { .. }));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        assert!(matches!(
+            &scene.bsdfs["textured_ref"],
+            crate::BSDF::Blend {
+                weight: crate::BSDFColor::Texture(crate::Texture::Checkerboard { .. }),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn simple_bsdfs() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="difftrans" id="paper">
+                <rgb name="transmittance" value="0.2, 0.3, 0.4"/>
+            </bsdf>
+            <bsdf type="difftrans" id="default"/>
+            <bsdf type="null" id="boundary"/>
+            <bsdf type="polarizer" id="filter">
+                <float name="theta" value="45"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("simple_bsdfs", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["paper"] {
+            crate::BSDF::DiffuseTransmitter {
+                transmittance: crate::BSDFColor::Constant(t),
+            } => assert_eq!(t.value, "0.2, 0.3, 0.4"),
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["default"] {
+            crate::BSDF::DiffuseTransmitter {
+                transmittance: crate::BSDFColor::Constant(t),
+            } => assert_eq!(t.value, "0.5"),
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        assert!(matches!(scene.bsdfs["boundary"], crate::BSDF::Null));
+        match &scene.bsdfs["filter"] {
+            crate::BSDF::Polarizer {
+                theta: crate::BSDFColor::Constant(theta),
+                polarizing,
+                ..
+            } => {
+                assert_eq!(*theta, 45.0);
+                assert!(*polarizing);
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+    }
+
+    #[test]
+    fn subsurface() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="hk" id="layer">
+                <string name="material" value="Ketchup"/>
+                <float name="thickness" value="0.5"/>
+                <phase type="hg">
+                    <float name="g" value="0.7"/>
+                </phase>
+            </bsdf>
+            <shape type="sphere">
+                <subsurface type="dipole">
+                    <rgb name="sigmaT" value="1, 2, 3"/>
+                    <rgb name="albedo" value="0.9"/>
+                    <float name="scale" value="100"/>
+                    <string name="intIOR" value="water"/>
+                </subsurface>
+            </shape>
+        </scene>"#;
+        let scene = parse_str("subsurface", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["layer"] {
+            crate::BSDF::HK {
+                coefficients,
+                thickness,
+                phase: crate::PhaseFunction::HG { g },
+            } => {
+                let (sigma_s, _) = coefficients.sigma_s_a().unwrap();
+                assert_eq!(sigma_s.r, 0.18);
+                assert_eq!((*thickness, *g), (0.5, 0.7));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.shapes_unamed[0] {
+            crate::Shape::Sphere { option, .. } => match &option.subsurface {
+                Some(crate::Subsurface::Dipole {
+                    coefficients:
+                        crate::scattering::ScatteringParameters::Extinction { sigma_t, .. },
+                    scale,
+                    int_ior,
+                    irr_samples,
+                    ..
+                }) => {
+                    assert_eq!(sigma_t.value, "1, 2, 3");
+                    assert_eq!((*scale, *int_ior, *irr_samples), (100.0, 1.333, 16));
+                }
+                v => panic!("Wrong subsurface {:?}", v),
+            },
+            v => panic!("Wrong shape {:?}", v),
+        }
+    }
+
+    #[test]
+    fn principled() {
+        let xml = r#"<scene version="3.0.0">
+            <bsdf type="principled" id="paint">
+                <rgb name="base_color" value="0.8, 0.1, 0.1"/>
+                <float name="metallic" value="0.7"/>
+                <float name="specular" value="0.5"/>
+                <texture type="checkerboard" name="roughness"/>
+            </bsdf>
+            <bsdf type="principledthin" id="leaf">
+                <float name="eta" value="1.33"/>
+                <float name="diff_trans" value="0.3"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("principled", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["paint"] {
+            crate::BSDF::Principled {
+                base_color: crate::BSDFColor::Constant(base_color),
+                roughness: crate::BSDFColor::Texture(_),
+                metallic: crate::BSDFColor::Constant(metallic),
+                eta: crate::BSDFColor::Constant(eta),
+                clearcoat: crate::BSDFColor::Constant(clearcoat),
+                ..
+            } => {
+                assert_eq!(base_color.value, "0.8, 0.1, 0.1");
+                assert_eq!((*metallic, *clearcoat), (0.7, 0.0));
+                assert!((eta - 1.5).abs() < 1e-3);
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["leaf"] {
+            crate::BSDF::PrincipledThin {
+                eta: crate::BSDFColor::Constant(eta),
+                diff_trans: crate::BSDFColor::Constant(diff_trans),
+                roughness: crate::BSDFColor::Constant(roughness),
+                ..
+            } => assert_eq!((*eta, *diff_trans, *roughness), (1.33, 0.3, 0.5)),
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+    }
+
+    #[test]
+    fn measured() {
+        let xml = r#"<scene version="2.0.0">
+            <bsdf type="measured" id="paint">
+                <string name="filename" value="aniso_morpho_melenaus_rgb.bsdf"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("measured", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["paint"] {
+            crate::BSDF::Measured { filename } => {
+                assert_eq!(filename, "aniso_morpho_melenaus_rgb.bsdf")
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+    }
+
+    #[test]
+    fn irawan() {
+        let wv = r#"weave {
+            name = "Polyester", tileWidth = 2, tileHeight = 2,
+            alpha = 0.015, beta = 4, ss = 0.5, hWidth = 0.5,
+            warpArea = 8, weftArea = 16,
+            pattern { 1, 2, 2, 1 },
+            yarn { type = warp, umax = $warp_umax, kd = $warp_kd },
+            yarn { type = weft, umax = 38 }
+        }"#;
+        std::fs::write(std::env::temp_dir().join("mitsuba_rs_irawan.wv"), wv).unwrap();
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="irawan" id="cloth">
+                <string name="filename" value="mitsuba_rs_irawan.wv"/>
+                <float name="repeatU" value="120"/>
+                <float name="repeatV" value="80"/>
+                <rgb name="warp_kd" value="0.1, 0.2, 0.3"/>
+                <float name="warp_umax" value="38"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("irawan", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["cloth"] {
+            crate::BSDF::Irawan {
+                filename,
+                pattern,
+                repeat_u,
+                repeat_v,
+                parameters,
+            } => {
+                assert_eq!(filename, "mitsuba_rs_irawan.wv");
+                // The pattern is read next to the scene file
+                assert_eq!(pattern.name, "Polyester");
+                assert_eq!(pattern.pattern, vec![1, 2, 2, 1]);
+                assert_eq!(pattern.yarns.len(), 2);
+                assert_eq!(pattern.yarns[0].umax, 38.0);
+                assert_eq!(
+                    pattern.yarns[0].kd,
+                    crate::Spectrum::from_rgb("0.1, 0.2, 0.3".to_string())
+                );
+                assert_eq!((*repeat_u, *repeat_v), (120.0, 80.0));
+                assert_eq!(parameters.len(), 2);
+                assert_eq!(
+                    parameters["warp_umax"],
+                    crate::weave::WeaveParameter::Float(38.0)
+                );
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+    }
+
+    #[test]
+    fn texturable_parameters() {
+        let xml = r#"<scene version="0.6.0">
+            <texture type="checkerboard" id="check"/>
+            <bsdf type="roughdiffuse" id="default"/>
+            <bsdf type="roughdiffuse" id="values">
+                <float name="reflectance" value="0.3"/>
+                <spectrum name="alpha" value="0.25"/>
+            </bsdf>
+            <bsdf type="roughdiffuse" id="textures">
+                <ref name="reflectance" id="check"/>
+                <texture name="alpha" type="checkerboard"/>
+            </bsdf>
+            <bsdf type="roughconductor" id="rough">
+                <texture name="alpha" type="checkerboard"/>
+            </bsdf>
+            <emitter type="point">
+                <point name="position" x="0" y="1" z="0"/>
+                <float name="intensity" value="2"/>
+            </emitter>
+        </scene>"#;
+        let scene = parse_str("texturable", xml, &crate::ParseOptions::default());
+        match &scene.bsdfs["default"] {
+            crate::BSDF::Roughtdiffuse {
+                reflectance, alpha, ..
+            } => {
+                assert_eq!(
+                    *reflectance,
+                    crate::BSDFColor::Constant(crate::Spectrum::from_f32(0.5))
+                );
+                assert_eq!(*alpha, crate::BSDFColor::Constant(0.2));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["values"] {
+            crate::BSDF::Roughtdiffuse {
+                reflectance, alpha, ..
+            } => {
+                assert_eq!(
+                    *reflectance,
+                    crate::BSDFColor::Constant(crate::Spectrum::from_f32(0.3))
+                );
+                assert_eq!(*alpha, crate::BSDFColor::Constant(0.25));
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["textures"] {
+            crate::BSDF::Roughtdiffuse {
+                reflectance: crate::BSDFColor::Texture(_),
+                alpha: crate::BSDFColor::Texture(_),
+                ..
+            } => {}
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["rough"] {
+            crate::BSDF::Conductor {
+                distribution: Some(d),
+                ..
+            } => match d.alpha {
+                crate::Alpha::Isotropic(crate::BSDFColor::Texture(_)) => {}
+                ref v => panic!("Wrong alpha {:?}", v),
+            },
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.emitters[0] {
+            crate::Emitter::Point { intensity, .. } => {
+                assert_eq!(*intensity, crate::Spectrum::from_f32(2.0))
+            }
+            v => panic!("Wrong emitter {:?}", v),
+        }
+
+        let invalid = [
+            // Unknown texture reference
+            r#"<bsdf type="diffuse" id="a"><ref name="reflectance" id="missing"/></bsdf>"#,
+            // Float parameter given as a colored spectrum
+            r#"<bsdf type="roughdiffuse" id="a"><rgb name="alpha" value="0.1, 0.2, 0.3"/></bsdf>"#,
+        ];
+        for (i, params) in invalid.iter().enumerate() {
+            let xml = format!(r#"<scene version="0.6.0">{}</scene>"#, params);
+            parse_err(&format!("texturable_{}", i), &xml);
+        }
+    }
+
+    #[test]
+    fn bitmap_parameters() {
+        let xml = r#"<scene version="0.6.0">
+            <texture type="bitmap" id="default">
+                <string name="filename" value="image.png"/>
+            </texture>
+            <texture type="bitmap" id="custom">
+                <string name="filename" value="image.exr"/>
+                <string name="wrapMode" value="clamp"/>
+                <string name="wrapModeV" value="mirror"/>
+                <string name="filterType" value="nearest"/>
+                <float name="maxAnisotropy" value="4"/>
+                <float name="gamma" value="2.2"/>
+                <string name="channel" value="A"/>
+                <boolean name="cache" value="false"/>
+            </texture>
+        </scene>"#;
+        let scene = parse_str("bitmap", xml, &crate::ParseOptions::default());
+        match &scene.textures["default"] {
+            crate::Texture::Bitmap {
+                wrap_mode_u,
+                wrap_mode_v,
+                filter_type,
+                max_anisotropy,
+                gamma,
+                channel,
+                cache,
+                ..
+            } => {
+                assert_eq!(*wrap_mode_u, crate::WrapMode::Repeat);
+                assert_eq!(*wrap_mode_v, crate::WrapMode::Repeat);
+                assert_eq!(*filter_type, crate::FilterType::EWA);
+                assert_eq!(*max_anisotropy, 20.0);
+                assert_eq!((*gamma, channel, *cache), (None, &None, None));
+            }
+            v => panic!("Wrong texture {:?}", v),
+        }
+        match &scene.textures["custom"] {
+            crate::Texture::Bitmap {
+                wrap_mode_u,
+                wrap_mode_v,
+                filter_type,
+                max_anisotropy,
+                gamma,
+                channel,
+                cache,
+                ..
+            } => {
+                assert_eq!(*wrap_mode_u, crate::WrapMode::Clamp);
+                assert_eq!(*wrap_mode_v, crate::WrapMode::Mirror);
+                assert_eq!(*filter_type, crate::FilterType::Nearest);
+                assert_eq!(*max_anisotropy, 4.0);
+                assert_eq!(*gamma, Some(2.2));
+                assert_eq!(channel.as_deref(), Some("a"));
+                assert_eq!(*cache, Some(false));
+            }
+            v => panic!("Wrong texture {:?}", v),
+        }
+
+        let invalid = [
+            r#"<string name="wrapMode" value="wrap"/>"#,
+            r#"<string name="filterType" value="bilinear"/>"#,
+            r#"<float name="maxAnisotropy" value="0"/>"#,
+        ];
+        for (i, params) in invalid.iter().enumerate() {
+            let xml = format!(
+                r#"<scene version="0.6.0"><texture type="bitmap" id="a">
+                    <string name="filename" value="image.png"/>{}</texture></scene>"#,
+                params
+            );
+            parse_err(&format!("bitmap_{}", i), &xml);
+        }
+    }
+
+    #[test]
+    fn nested_textures() {
+        let xml = r#"<scene version="0.6.0">
+            <texture type="bitmap" id="image">
+                <string name="filename" value="image.png"/>
+            </texture>
+            <texture type="scale" id="scaled">
+                <float name="scale" value="0.5"/>
+                <texture type="checkerboard">
+                    <texture name="color0" type="bitmap" id="inner">
+                        <string name="filename" value="color.png"/>
+                    </texture>
+                    <ref name="color1" id="image"/>
+                </texture>
+            </texture>
+            <texture type="scale" id="scaled_ref">
+                <rgb name="scale" value="0.1, 0.2, 0.3"/>
+                <ref id="image"/>
+            </texture>
+            <bsdf type="bumpmap" id="bump">
+                <ref id="scaled"/>
+                <bsdf type="diffuse"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_str("nested_textures", xml, &crate::ParseOptions::default());
+        assert!(scene.textures.contains_key("inner"));
+        match &scene.textures["scaled"] {
+            crate::Texture::Scale {
+                texture,
+                value: crate::TextureScale::Float(v),
+            } => {
+                assert_eq!(*v, 0.5);
+                match &**texture {
+                    crate::Texture::Checkerboard { color0, color1, .. } => {
+                        assert!(matches!(
+                            **color0,
+                            crate::BSDFColor::Texture(crate::Texture::Bitmap { .. })
+                        ));
+                        assert_eq!(
+                            **color1,
+                            crate::BSDFColor::Texture(scene.textures["image"].clone())
+                        );
+                    }
+                    v => panic!("Wrong texture {:?}", v),
+                }
+            }
+            v => panic!("Wrong texture {:?}", v),
+        }
+        match &scene.textures["scaled_ref"] {
+            crate::Texture::Scale {
+                texture,
+                value: crate::TextureScale::Spectrum(_),
+            } => assert_eq!(**texture, scene.textures["image"]),
+            v => panic!("Wrong texture {:?}", v),
+        }
+        // The scale of the height map is extracted
+        match &scene.bsdfs["bump"] {
+            crate::BSDF::BumpMap { texture, scale, .. } => {
+                assert!(matches!(texture, crate::Texture::Checkerboard { .. }));
+                assert_eq!(*scale, 0.5);
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+
+        let invalid = [
+            // Missing nested texture
+            r#"<texture type="scale" id="a"><float name="scale" value="2"/></texture>"#,
+            // Unknown reference
+            r#"<texture type="scale" id="a"><ref id="missing"/></texture>"#,
+        ];
+        for (i, params) in invalid.iter().enumerate() {
+            let xml = format!(r#"<scene version="0.6.0">{}</scene>"#, params);
+            parse_err(&format!("nested_{}", i), &xml);
+        }
+    }
+
+    #[test]
+    fn mesh_textures() {
+        let xml = r#"<scene version="0.6.0">
+            <texture type="vertexcolors" id="colors"/>
+            <texture type="wireframe" id="wire">
+                <rgb name="edgeColor" value="1, 0, 0"/>
+                <float name="lineWidth" value="0.02"/>
+            </texture>
+            <texture type="curvature" id="curvature">
+                <string name="curvature" value="gaussian"/>
+                <float name="scale" value="4"/>
+            </texture>
+        </scene>"#;
+        let scene = parse_str("mesh_textures", xml, &crate::ParseOptions::default());
+        assert_eq!(scene.textures["colors"], crate::Texture::VertexColors);
+        assert_eq!(
+            scene.textures["wire"],
+            crate::Texture::WireFrame {
+                interior_color: crate::Spectrum::from_f32(0.5),
+                edge_color: crate::Spectrum::from_rgb("1, 0, 0".to_string()),
+                line_width: 0.02,
+                step_width: 0.5,
+            }
+        );
+        assert_eq!(
+            scene.textures["curvature"],
+            crate::Texture::Curvature {
+                curvature: crate::CurvatureType::Gaussian,
+                scale: 4.0,
+            }
+        );
+
+        let invalid = [
+            r#"<texture type="wireframe" id="a"><float name="stepWidth" value="2"/></texture>"#,
+            r#"<texture type="curvature" id="a"><string name="curvature" value="max"/></texture>"#,
+        ];
+        for (i, params) in invalid.iter().enumerate() {
+            let xml = format!(r#"<scene version="0.6.0">{}</scene>"#, params);
+            parse_err(&format!("mesh_textures_{}", i), &xml);
+        }
+    }
+
+    #[test]
+    fn texture_eval() {
+        let xml = r#"<scene version="0.6.0">
+            <texture type="checkerboard" id="check">
+                <float name="uscale" value="2"/>
+                <float name="voffset" value="0.25"/>
+            </texture>
+            <texture type="gridtexture" id="grid">
+                <float name="lineWidth" value="0.1"/>
+            </texture>
+            <texture type="scale" id="scale">
+                <rgb name="scale" value="1, 0.5, 0.25"/>
+                <ref id="check"/>
+            </texture>
+        </scene>"#;
+        let scene = parse_str("texture_eval", xml, &crate::ParseOptions::default());
+        let eval = |name: &str, u: f32, v: f32| {
+            scene.textures[name]
+                .eval_f32(crate::Point2::new(u, v))
+                .unwrap()
+        };
+        // Checkerboard: color0 when both cells have the same parity
+        // (cell indices are rounded toward zero)
+        assert_eq!(eval("check", 0.1, 0.1), 0.4);
+        assert_eq!(eval("check", 0.3, 0.1), 0.2);
+        assert_eq!(eval("check", 0.3, 0.3), 0.4);
+        assert_eq!(eval("check", -0.1, 0.1), 0.4);
+        // Grid: lines around integer coordinates
+        assert_eq!(eval("grid", 0.05, 0.5), 0.2);
+        assert_eq!(eval("grid", 0.5, 0.95), 0.2);
+        assert_eq!(eval("grid", 0.5, 0.5), 0.4);
+        let v = scene.textures["scale"]
+            .eval(crate::Point2::new(0.3, 0.1))
+            .unwrap();
+        assert!((v.r - 0.2).abs() < 1e-6 && (v.g - 0.1).abs() < 1e-6 && (v.b - 0.05).abs() < 1e-6);
+
+        let bitmap = crate::Texture::Bitmap {
+            filename: "image.png".to_string(),
+            wrap_mode_u: crate::WrapMode::Repeat,
+            wrap_mode_v: crate::WrapMode::Repeat,
+            filter_type: crate::FilterType::EWA,
+            max_anisotropy: 20.0,
+            gamma: None,
+            channel: None,
+            cache: None,
+            to_uv: crate::UVTransform::default(),
+        };
+        assert!(bitmap.eval(crate::Point2::new(0.5, 0.5)).is_err());
+        assert!(crate::Texture::VertexColors
+            .eval(crate::Point2::new(0.5, 0.5))
+            .is_err());
+    }
+
+    #[test]
+    fn uv_transform() {
+        let xml = r#"<scene version="2.0.0">
+            <texture type="checkerboard" id="legacy">
+                <float name="uoffset" value="1"/>
+                <float name="uscale" value="2"/>
+                <float name="vscale" value="2"/>
+            </texture>
+            <texture type="checkerboard" id="mitsuba2">
+                <transform name="to_uv">
+                    <translate x="0.5"/>
+                    <scale x="2" y="2"/>
+                </transform>
+            </texture>
+            <texture type="gridtexture" id="default"/>
+            <texture type="bitmap" id="bitmap">
+                <string name="filename" value="texture.png"/>
+                <string name="wrap_mode" value="mirror"/>
+                <string name="filter_type" value="bilinear"/>
+                <transform name="to_uv">
+                    <rotate z="1" angle="90"/>
+                </transform>
+            </texture>
+        </scene>"#;
+        let scene = parse_str("uv_transform", xml, &crate::ParseOptions::default());
+        let uv = |name: &str, u: f32, v: f32| {
+            let p = scene.textures[name].to_texture_space(crate::Point2::new(u, v));
+            ((p.x * 1e5).round() / 1e5, (p.y * 1e5).round() / 1e5)
+        };
+        // Operations are applied in order (translate then scale)
+        assert_eq!(uv("legacy", 0.25, 0.25), (1.5, 0.5));
+        assert_eq!(uv("mitsuba2", 0.25, 0.25), (1.5, 0.5));
+        assert_eq!(uv("default", 0.25, 0.75), (0.25, 0.75));
+        assert_eq!(uv("bitmap", 1.0, 0.0), (0.0, 1.0));
+        match &scene.textures["bitmap"] {
+            crate::Texture::Bitmap {
+                wrap_mode_u,
+                wrap_mode_v,
+                filter_type,
+                ..
+            } => {
+                assert_eq!(*wrap_mode_u, crate::WrapMode::Mirror);
+                assert_eq!(*wrap_mode_v, crate::WrapMode::Mirror);
+                assert_eq!(*filter_type, crate::FilterType::Bilinear);
+            }
+            t => panic!("Unexpected texture {:?}", t),
+        }
+
+        // Both parametrizations, and filters of the other Mitsuba version
+        let invalid = [
+            r#"<float name="uscale" value="2"/><transform name="to_uv"><scale x="2"/></transform>"#,
+            r#"<string name="filter_type" value="ewa"/>"#,
+            r#"<string name="filterType" value="bilinear"/>"#,
+        ];
+        for (i, t) in invalid.iter().enumerate() {
+            let xml = format!(
+                r#"<scene version="2.0.0"><texture type="bitmap" id="t"><string name="filename" value="texture.png"/>{}</texture></scene>"#,
+                t
+            );
+            parse_err(&format!("uv_transform_{}", i), &xml);
+        }
+    }
+
+    #[test]
+    fn emitter_textures() {
+        let xml = r#"<scene version="0.6.0">
+            <texture type="checkerboard" id="check"/>
+            <shape type="sphere">
+                <emitter type="area">
+                    <texture type="checkerboard" name="radiance">
+                        <spectrum name="color0" value="10"/>
+                    </texture>
+                </emitter>
+            </shape>
+            <shape type="sphere">
+                <emitter type="area">
+                    <ref name="radiance" id="check"/>
+                </emitter>
+            </shape>
+            <shape type="sphere">
+                <emitter type="area">
+                    <spectrum name="radiance" value="5"/>
+                </emitter>
+            </shape>
+            <emitter type="spot">
+                <texture type="bitmap" name="texture">
+                    <string name="filename" value="projected.png"/>
+                </texture>
+            </emitter>
+            <emitter type="spot">
+                <ref name="texture" id="check"/>
+            </emitter>
+            <emitter type="spot"/>
+        </scene>"#;
+        let scene = parse_str("emitter_textures", xml, &crate::ParseOptions::default());
+        let radiance = |s: &crate::Shape| match s {
+            crate::Shape::Sphere { option, .. } => {
+                option.emitter.as_ref().unwrap().radiance.clone()
+            }
+            _ => panic!("Wrong shape"),
+        };
+        let radiances = scene.shapes_unamed.iter().map(radiance).collect::<Vec<_>>();
+        match &radiances[0] {
+            crate::BSDFColor::Texture(crate::Texture::Checkerboard { color0, .. }) => {
+                assert_eq!(
+                    **color0,
+                    crate::BSDFColor::Constant(crate::Spectrum::from_f32(10.0))
+                );
+            }
+            r => panic!("Wrong radiance {:?}", r),
+        }
+        assert_eq!(
+            radiances[1],
+            crate::BSDFColor::Texture(scene.textures["check"].clone())
+        );
+        assert_eq!(
+            radiances[2],
+            crate::BSDFColor::Constant(crate::Spectrum::from_f32(5.0))
+        );
+
+        let textures = scene
+            .emitters
+            .iter()
+            .map(|e| match e {
+                crate::Emitter::Spot { texture, .. } => texture.clone(),
+                _ => panic!("Wrong emitter"),
+            })
+            .collect::<Vec<_>>();
+        match &textures[0] {
+            Some(crate::Texture::Bitmap { filename, .. }) => assert_eq!(filename, "projected.png"),
+            t => panic!("Wrong texture {:?}", t),
+        }
+        assert_eq!(textures[1].as_ref(), Some(&scene.textures["check"]));
+        assert!(textures[2].is_none());
+
+        // A constant is not a projected texture
+        let xml = r#"<scene version="0.6.0"><emitter type="spot"><rgb name="texture" value="1, 0, 0"/></emitter></scene>"#;
+        parse_err("emitter_textures_invalid", xml);
+    }
+
+    #[test]
+    fn sun_position() {
+        use cgmath::InnerSpace;
+        // Zenith and azimuth (degrees) from the NOAA solar calculator equations
+        let positions = [
+            (
+                (2010, 7, 10, 15.0, 35.6894, 139.6917, 9.0),
+                (43.919, 265.753),
+            ),
+            ((2010, 12, 21, 9.5, 48.8566, 2.3522, 1.0), (84.757, 135.404)),
+            ((2010, 3, 20, 12.0, 0.0, 0.0, 0.0), (1.872, 92.768)),
+            (
+                (2000, 6, 21, 18.0, -33.8688, 151.2093, 10.0),
+                (103.402, 289.422),
+            ),
+            (
+                (2015, 1, 15, 7.0, 40.7128, -74.006, -5.0),
+                (93.901, 114.702),
+            ),
+        ];
+        for ((year, month, day, hour, latitude, longitude, timezone), (zenith, azimuth)) in
+            positions.iter()
+        {
+            let sun = crate::SunDirection::DateAndPos {
+                year: *year,
+                month: *month,
+                day: *day,
+                hour: *hour,
+                minute: 0.0,
+                second: 0.0,
+                latitude: *latitude,
+                longitude: *longitude,
+                timezone: *timezone,
+            };
+            let (theta, phi) = (
+                (*zenith as f32).to_radians(),
+                (*azimuth as f32).to_radians(),
+            );
+            let expected = crate::Vector3::new(
+                phi.sin() * theta.sin(),
+                theta.cos(),
+                -phi.cos() * theta.sin(),
+            );
+            // Within 0.05 degree
+            let d = sun.to_vector().dot(expected);
+            assert!(
+                d > 0.05f32.to_radians().cos(),
+                "{:?}: {}",
+                sun,
+                d.acos().to_degrees()
+            );
+        }
+
+        // Afternoon in Tokyo: sun toward the west (-x)
+        let sun = crate::SunDirection::DateAndPos {
+            year: 2010,
+            month: 7,
+            day: 10,
+            hour: 15.0,
+            minute: 0.0,
+            second: 0.0,
+            latitude: 35.6894,
+            longitude: 139.6917,
+            timezone: 9.0,
+        };
+        assert!(sun.to_vector().x < 0.0);
+        let sun = crate::SunDirection::Vector(crate::Vector3::new(0.0, 2.0, 0.0));
+        assert_eq!(sun.to_vector(), crate::Vector3::new(0.0, 1.0, 0.0));
+
+        // The emitter transform is applied in world space
+        let xml = r#"<scene version="0.6.0">
+            <emitter type="sun">
+                <vector name="sunDirection" x="0" y="1" z="0"/>
+                <transform name="toWorld">
+                    <rotate x="1" angle="-90"/>
+                </transform>
+            </emitter>
+        </scene>"#;
+        let scene = parse_str("sun_position", xml, &crate::ParseOptions::default());
+        let d = scene.emitters[0].sun_direction().unwrap();
+        assert!((d - crate::Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn sun_sky_parameters() {
+        let xml = r#"<scene version="0.6.0">
+            <emitter type="sun">
+                <float name="turbidity" value="5"/>
+                <integer name="resolution" value="256"/>
+                <float name="scale" value="2"/>
+                <vector name="sunDirection" x="0" y="1" z="1"/>
+            </emitter>
+            <emitter type="sky">
+                <float name="stretch" value="1.5"/>
+                <rgb name="albedo" value="0.1, 0.2, 0.3"/>
+                <transform name="toWorld">
+                    <translate y="2"/>
+                </transform>
+            </emitter>
+            <emitter type="sunsky">
+                <float name="skyScale" value="3"/>
+                <float name="sunRadiusScale" value="0"/>
+                <float name="latitude" value="48.85"/>
+            </emitter>
+        </scene>"#;
+        let scene = parse_str("sun_sky_parameters", xml, &crate::ParseOptions::default());
+        match &scene.emitters[0] {
+            crate::Emitter::SunSky {
+                turbidity,
+                resolution,
+                sun_direction: crate::SunDirection::Vector(_),
+                sun: Some(sun),
+                sky: None,
+                ..
+            } => {
+                assert_eq!((*turbidity, *resolution), (5.0, 256));
+                assert_eq!((sun.scale, sun.radius_scale), (2.0, 1.0));
+            }
+            e => panic!("Wrong emitter {:?}", e),
+        }
+        match &scene.emitters[1] {
+            crate::Emitter::SunSky {
+                to_world,
+                turbidity,
+                resolution,
+                sun_direction: crate::SunDirection::DateAndPos { month, .. },
+                sun: None,
+                sky: Some(sky),
+                ..
+            } => {
+                assert_eq!((*turbidity, *resolution, *month), (3.0, 512, 7));
+                assert_eq!((sky.scale, sky.stretch), (1.0, 1.5));
+                assert_eq!(sky.albedo.value, "0.1, 0.2, 0.3");
+                let m = to_world.clone().as_matrix();
+                assert_eq!(m.w, crate::Vector4::new(0.0, 2.0, 0.0, 1.0));
+            }
+            e => panic!("Wrong emitter {:?}", e),
+        }
+        match &scene.emitters[2] {
+            crate::Emitter::SunSky {
+                sun_direction: crate::SunDirection::DateAndPos { latitude, .. },
+                sun: Some(sun),
+                sky: Some(sky),
+                ..
+            } => {
+                assert_eq!(*latitude, 48.85);
+                assert_eq!((sun.scale, sun.radius_scale), (1.0, 0.0));
+                assert_eq!((sky.scale, sky.stretch), (3.0, 1.0));
+                assert_eq!(sky.albedo, crate::Spectrum::from_f32(0.15));
+            }
+            e => panic!("Wrong emitter {:?}", e),
+        }
+
+        // Out of range values, parameters of other types and both sun positions
+        let invalid = [
+            r#"<emitter type="sun"><float name="turbidity" value="11"/></emitter>"#,
+            r#"<emitter type="sky"><float name="stretch" value="2.5"/></emitter>"#,
+            r#"<emitter type="sky"><rgb name="albedo" value="0.5, 1.5, 0.5"/></emitter>"#,
+            r#"<emitter type="sunsky"><float name="sunScale" value="-1"/></emitter>"#,
+            r#"<emitter type="sunsky"><integer name="resolution" value="0"/></emitter>"#,
+            r#"<emitter type="sky"><float name="skyScale" value="2"/></emitter>"#,
+            r#"<emitter type="sun"><float name="stretch" value="2"/></emitter>"#,
+            r#"<emitter type="sun"><vector name="sunDirection" x="0" y="1" z="0"/><integer name="year" value="2020"/></emitter>"#,
+        ];
+        for (i, e) in invalid.iter().enumerate() {
+            let xml = format!(r#"<scene version="0.6.0">{}</scene>"#, e);
+            parse_err(&format!("sun_sky_{}", i), &xml);
+        }
+    }
+
+    #[test]
+    fn transform_to_world() {
+        use cgmath::{InnerSpace, Transform as _};
+        let xml = r#"<scene version="0.6.0">
+            <shape type="sphere">
+                <transform name="toWorld">
+                    <rotate z="1" angle="90"/>
+                    <translate x="1" y="2" z="3"/>
+                </transform>
+            </shape>
+        </scene>"#;
+        let scene = parse_str("transform_to_world", xml, &crate::ParseOptions::default());
+        let m = match &scene.shapes_unamed[0] {
+            crate::Shape::Sphere { option, .. } => option.to_world.clone().unwrap().as_matrix(),
+            _ => panic!("Wrong shape"),
+        };
+        // Operations are applied in order: rotation then translation
+        assert_eq!(m.w, crate::Vector4::new(1.0, 2.0, 3.0, 1.0));
+        let p = m.transform_point(crate::Point3::new(1.0, 0.0, 0.0));
+        assert!((p - crate::Point3::new(1.0, 3.0, 3.0)).magnitude() < 1e-5);
+    }
+
+    // Check that a red (linear sRGB) was converted, or not, to Rec.2020
+    fn assert_red(s: &crate::Spectrum, converted: bool) {
+        if converted {
+            let rgb = s.clone().as_rgb().unwrap();
+            assert!((rgb.r - 0.6274).abs() < 1e-3 && (rgb.g - 0.0691).abs() < 1e-3);
+        } else {
+            assert_eq!(s.value, "1, 0, 0");
+        }
+    }
+
+    fn constant(c: &crate::BSDFColorSpectrum) -> &crate::Spectrum {
+        match c {
+            crate::BSDFColor::Constant(s) => s,
+            _ => panic!("Wrong color {:?}", c),
+        }
+    }
+
+    fn parse_rec2020(name: &str, xml: &str) -> crate::Scene {
+        let options = crate::ParseOptions {
+            color_space: Some(crate::color::ColorSpace::Rec2020),
+        };
+        parse_str(name, xml, &options)
+    }
+
+    #[test]
+    fn color_space_albedos() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="dielectric" id="glass">
+                <rgb name="specularTransmittance" value="1, 0, 0"/>
+            </bsdf>
+            <bsdf type="mask" id="mask">
+                <rgb name="opacity" value="1, 0, 0"/>
+                <bsdf type="diffuse"/>
+            </bsdf>
+            <shape type="sphere">
+                <subsurface type="dipole">
+                    <rgb name="sigmaT" value="1, 0, 0"/>
+                    <rgb name="albedo" value="1, 0, 0"/>
+                </subsurface>
+            </shape>
+        </scene>"#;
+        let scene = parse_rec2020("color_space_albedos", xml);
+        match &scene.bsdfs["glass"] {
+            crate::BSDF::Dielectric {
+                specular_transmittance,
+                ..
+            } => assert_red(constant(specular_transmittance), true),
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["mask"] {
+            crate::BSDF::Mask { opacity, .. } => assert_red(constant(opacity), true),
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.shapes_unamed[0] {
+            crate::Shape::Sphere { option, .. } => match &option.subsurface {
+                Some(crate::Subsurface::Dipole {
+                    coefficients:
+                        crate::scattering::ScatteringParameters::Extinction { sigma_t, albedo },
+                    ..
+                }) => {
+                    assert_red(albedo, true);
+                    assert_red(sigma_t, false);
+                }
+                v => panic!("Wrong subsurface {:?}", v),
+            },
+            v => panic!("Wrong shape {:?}", v),
+        }
+    }
+
+    #[test]
+    fn color_space_emission() {
+        let xml = r#"<scene version="0.6.0">
+            <emitter type="point">
+                <point name="position" x="0" y="0" z="0"/>
+                <rgb name="intensity" value="1, 0, 0"/>
+            </emitter>
+            <shape type="sphere">
+                <emitter type="area">
+                    <rgb name="radiance" value="1, 0, 0"/>
+                </emitter>
+            </shape>
+        </scene>"#;
+        let scene = parse_rec2020("color_space_emission", xml);
+        match &scene.emitters[0] {
+            crate::Emitter::Point { intensity, .. } => assert_red(intensity, true),
+            v => panic!("Wrong emitter {:?}", v),
+        }
+        match &scene.shapes_unamed[0] {
+            crate::Shape::Sphere { option, .. } => {
+                assert_red(constant(&option.emitter.as_ref().unwrap().radiance), true)
+            }
+            v => panic!("Wrong shape {:?}", v),
+        }
+    }
+
+    #[test]
+    fn color_space_ior() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="conductor" id="metal">
+                <rgb name="eta" value="1, 0, 0"/>
+                <rgb name="k" value="1, 0, 0"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_rec2020("color_space_ior", xml);
+        match &scene.bsdfs["metal"] {
+            crate::BSDF::Conductor { eta, k, .. } => {
+                assert_red(eta, false);
+                assert_red(k, false);
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+    }
+
+    #[test]
+    fn color_space_coefficients() {
+        let xml = r#"<scene version="0.6.0">
+            <medium type="homogeneous" id="smoke">
+                <rgb name="sigmaA" value="1, 0, 0"/>
+                <rgb name="sigmaS" value="1, 0, 0"/>
+            </medium>
+            <bsdf type="coating" id="varnish">
+                <rgb name="sigmaA" value="1, 0, 0"/>
+                <bsdf type="diffuse"/>
+            </bsdf>
+            <bsdf type="hk" id="layer">
+                <rgb name="sigmaS" value="1, 0, 0"/>
+                <rgb name="sigmaA" value="1, 0, 0"/>
+            </bsdf>
+        </scene>"#;
+        let scene = parse_rec2020("color_space_coefficients", xml);
+        match &scene.medium["smoke"] {
+            crate::Medium::Homogenous {
+                sigma_a, sigma_s, ..
+            } => {
+                assert_red(sigma_a, false);
+                assert_red(sigma_s, false);
+            }
+        }
+        match &scene.bsdfs["varnish"] {
+            crate::BSDF::Coating { sigma_a, .. } => assert_red(constant(sigma_a), false),
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        match &scene.bsdfs["layer"] {
+            crate::BSDF::HK {
+                coefficients:
+                    crate::scattering::ScatteringParameters::Coefficients { sigma_s, sigma_a },
+                ..
+            } => {
+                assert_red(sigma_s, false);
+                assert_red(sigma_a, false);
+            }
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+    }
+
+    #[test]
+    fn color_space_float_parameters() {
+        let xml = r#"<scene version="0.6.0">
+            <bsdf type="roughdiffuse" id="rough">
+                <texture name="alpha" type="checkerboard">
+                    <rgb name="color0" value="1, 0, 0"/>
+                </texture>
+            </bsdf>
+            <texture type="scale" id="scaled">
+                <rgb name="scale" value="1, 0, 0"/>
+                <texture type="checkerboard">
+                    <rgb name="color0" value="1, 0, 0"/>
+                </texture>
+            </texture>
+        </scene>"#;
+        let scene = parse_rec2020("color_space_float_parameters", xml);
+        match &scene.bsdfs["rough"] {
+            crate::BSDF::Roughtdiffuse {
+                alpha: crate::BSDFColor::Texture(crate::Texture::Checkerboard { color0, .. }),
+                ..
+            } => assert_red(constant(color0), false),
+            v => panic!("Wrong BSDF {:?}", v),
+        }
+        // The scale is a multiplier, the scaled texture is a color
+        match &scene.textures["scaled"] {
+            crate::Texture::Scale {
+                texture,
+                value: crate::TextureScale::Spectrum(s),
+            } => {
+                assert_red(s, false);
+                match &**texture {
+                    crate::Texture::Checkerboard { color0, .. } => {
+                        assert_red(constant(color0), true)
+                    }
+                    v => panic!("Wrong texture {:?}", v),
+                }
+            }
+            v => panic!("Wrong texture {:?}", v),
+        }
+    }
+
     #[test]
     fn spectrum_to_rgb_failed() {
         let s = crate::Spectrum {
@@ -0,0 +1,257 @@
+//! Tensor files (RGL `.bsdf` format) used by the `measured` BSDF.
+//!
+//! The file starts with the `tensor_file\0` magic, a version number and
+//! the number of fields. Each field has a name, a data type, an offset
+//! inside the file and a shape. For measured BSDFs, the fields are
+//! `theta_i`, `phi_i`, `ndf`, `sigma`, `vndf`, `luminance`, `rgb`, ...
+use crate::{Error, Result};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TensorData {
+    UInt8(Vec<u8>),
+    Int8(Vec<i8>),
+    UInt16(Vec<u16>),
+    Int16(Vec<i16>),
+    UInt32(Vec<u32>),
+    Int32(Vec<i32>),
+    UInt64(Vec<u64>),
+    Int64(Vec<i64>),
+    Float16(Vec<f32>), // Converted to f32 when read
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+}
+
+impl TensorData {
+    pub fn len(&self) -> usize {
+        match self {
+            TensorData::UInt8(v) => v.len(),
+            TensorData::Int8(v) => v.len(),
+            TensorData::UInt16(v) => v.len(),
+            TensorData::Int16(v) => v.len(),
+            TensorData::UInt32(v) => v.len(),
+            TensorData::Int32(v) => v.len(),
+            TensorData::UInt64(v) => v.len(),
+            TensorData::Int64(v) => v.len(),
+            TensorData::Float16(v) | TensorData::Float32(v) => v.len(),
+            TensorData::Float64(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Values converted to f32 (whatever the stored type)
+    pub fn as_f32(&self) -> Vec<f32> {
+        match self {
+            TensorData::UInt8(v) => v.iter().map(|&v| v as f32).collect(),
+            TensorData::Int8(v) => v.iter().map(|&v| v as f32).collect(),
+            TensorData::UInt16(v) => v.iter().map(|&v| v as f32).collect(),
+            TensorData::Int16(v) => v.iter().map(|&v| v as f32).collect(),
+            TensorData::UInt32(v) => v.iter().map(|&v| v as f32).collect(),
+            TensorData::Int32(v) => v.iter().map(|&v| v as f32).collect(),
+            TensorData::UInt64(v) => v.iter().map(|&v| v as f32).collect(),
+            TensorData::Int64(v) => v.iter().map(|&v| v as f32).collect(),
+            TensorData::Float16(v) | TensorData::Float32(v) => v.clone(),
+            TensorData::Float64(v) => v.iter().map(|&v| v as f32).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorField {
+    pub shape: Vec<usize>,
+    pub data: TensorData,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorFile {
+    pub fields: HashMap<String, TensorField>,
+}
+
+impl TensorFile {
+    pub fn field(&self, name: &str) -> Result<&TensorField> {
+        self.fields
+            .get(name)
+            .ok_or_else(|| tensor_error(format!("field {:?} not found in the tensor file", name)))
+    }
+}
+
+fn tensor_error(reason: String) -> Error {
+    Error::Other(Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        reason,
+    )))
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Other(Box::new(e))
+}
+
+// IEEE 754 half precision to single precision
+fn f16_to_f32(h: u16) -> f32 {
+    let sign = ((h as u32) & 0x8000) << 16;
+    let exp = ((h >> 10) & 0x1f) as u32;
+    let mant = (h & 0x3ff) as u32;
+    let bits = match exp {
+        0 if mant == 0 => sign,
+        0 => {
+            // Subnormal: normalize the mantissa
+            let shift = mant.leading_zeros() - 21;
+            let mant = (mant << shift) & 0x3ff;
+            sign | ((113 - shift) << 23) | (mant << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mant << 13),
+        _ => sign | ((exp + 112) << 23) | (mant << 13),
+    };
+    f32::from_bits(bits)
+}
+
+fn read_data<R: Read>(f: &mut R, dtype: u8, size: usize) -> Result<TensorData> {
+    macro_rules! read_vec {
+        ($variant:ident, $read:expr) => {
+            TensorData::$variant(
+                (0..size)
+                    .map(|_| $read)
+                    .collect::<std::io::Result<Vec<_>>>()
+                    .map_err(io_error)?,
+            )
+        };
+    }
+    Ok(match dtype {
+        1 => read_vec!(UInt8, f.read_u8()),
+        2 => read_vec!(Int8, f.read_i8()),
+        3 => read_vec!(UInt16, f.read_u16::<LittleEndian>()),
+        4 => read_vec!(Int16, f.read_i16::<LittleEndian>()),
+        5 => read_vec!(UInt32, f.read_u32::<LittleEndian>()),
+        6 => read_vec!(Int32, f.read_i32::<LittleEndian>()),
+        7 => read_vec!(UInt64, f.read_u64::<LittleEndian>()),
+        8 => read_vec!(Int64, f.read_i64::<LittleEndian>()),
+        9 => read_vec!(Float16, f.read_u16::<LittleEndian>().map(f16_to_f32)),
+        10 => read_vec!(Float32, f.read_f32::<LittleEndian>()),
+        11 => read_vec!(Float64, f.read_f64::<LittleEndian>()),
+        _ => return Err(tensor_error(format!("invalid field type {}", dtype))),
+    })
+}
+
+/// Decode a tensor file
+pub fn parse_tensor_file<R: Read + Seek>(f: &mut R) -> Result<TensorFile> {
+    let mut header = [0; 12];
+    f.read_exact(&mut header).map_err(io_error)?;
+    if &header != b"tensor_file\0" {
+        return Err(tensor_error("invalid tensor file header".to_string()));
+    }
+    let major = f.read_u8().map_err(io_error)?;
+    let minor = f.read_u8().map_err(io_error)?;
+    if (major, minor) != (1, 0) {
+        return Err(tensor_error(format!(
+            "unsupported tensor file version {}.{}",
+            major, minor
+        )));
+    }
+    let nb_fields = f.read_u32::<LittleEndian>().map_err(io_error)?;
+
+    // Read the field descriptions
+    let mut descriptions = vec![];
+    for _ in 0..nb_fields {
+        let name_length = f.read_u16::<LittleEndian>().map_err(io_error)?;
+        let mut name = vec![0; name_length as usize];
+        f.read_exact(&mut name).map_err(io_error)?;
+        let name = String::from_utf8(name)
+            .map_err(|_| tensor_error("field name is not valid UTF-8".to_string()))?;
+        let ndim = f.read_u16::<LittleEndian>().map_err(io_error)?;
+        let dtype = f.read_u8().map_err(io_error)?;
+        let offset = f.read_u64::<LittleEndian>().map_err(io_error)?;
+        let shape = (0..ndim)
+            .map(|_| f.read_u64::<LittleEndian>().map(|v| v as usize))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(io_error)?;
+        descriptions.push((name, dtype, offset, shape));
+    }
+
+    // Read the data of each field
+    let mut fields = HashMap::new();
+    for (name, dtype, offset, shape) in descriptions {
+        f.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        let data = read_data(f, dtype, shape.iter().product())?;
+        fields.insert(name, TensorField { shape, data });
+    }
+    Ok(TensorFile { fields })
+}
+
+/// Read the tensor file of a `measured` BSDF (`filename` relative to `wk`)
+pub fn read_tensor_file(filename: &str, wk: &std::path::Path) -> Result<TensorFile> {
+    let filename = wk.join(std::path::Path::new(filename));
+    let f = File::open(filename).map_err(io_error)?;
+    parse_tensor_file(&mut BufReader::new(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use std::io::{Cursor, Write};
+
+    // Write a tensor file with a f32 field and a f16 field
+    fn tensor_file() -> Vec<u8> {
+        let fields: [(&str, u8, Vec<u64>); 2] = [("theta_i", 10, vec![3]), ("rgb", 9, vec![2, 2])];
+        let header_size = 12
+            + 2
+            + 4
+            + fields
+                .iter()
+                .map(|(n, _, s)| 2 + n.len() + 2 + 1 + 8 + 8 * s.len())
+                .sum::<usize>();
+
+        let mut f = vec![];
+        f.write_all(b"tensor_file\0").unwrap();
+        f.write_all(&[1, 0]).unwrap();
+        f.write_u32::<LittleEndian>(2).unwrap();
+        let mut offset = header_size as u64;
+        for (name, dtype, shape) in &fields {
+            f.write_u16::<LittleEndian>(name.len() as u16).unwrap();
+            f.write_all(name.as_bytes()).unwrap();
+            f.write_u16::<LittleEndian>(shape.len() as u16).unwrap();
+            f.write_u8(*dtype).unwrap();
+            f.write_u64::<LittleEndian>(offset).unwrap();
+            for s in shape {
+                f.write_u64::<LittleEndian>(*s).unwrap();
+            }
+            offset += shape.iter().product::<u64>() * if *dtype == 10 { 4 } else { 2 };
+        }
+        for v in &[0.0, 0.5, 1.0] {
+            f.write_f32::<LittleEndian>(*v).unwrap();
+        }
+        // 1.0, -2.0, 0.5 and 65504 (max half)
+        for v in &[0x3c00, 0xc000, 0x3800, 0x7bff] {
+            f.write_u16::<LittleEndian>(*v).unwrap();
+        }
+        f
+    }
+
+    #[test]
+    fn read_fields() {
+        let tensor = parse_tensor_file(&mut Cursor::new(tensor_file())).unwrap();
+        assert_eq!(tensor.fields.len(), 2);
+        let theta_i = tensor.field("theta_i").unwrap();
+        assert_eq!(theta_i.shape, vec![3]);
+        assert_eq!(theta_i.data, TensorData::Float32(vec![0.0, 0.5, 1.0]));
+        let rgb = tensor.field("rgb").unwrap();
+        assert_eq!(rgb.shape, vec![2, 2]);
+        assert_eq!(rgb.data.as_f32(), vec![1.0, -2.0, 0.5, 65504.0]);
+        assert!(tensor.field("vndf").is_err());
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut data = tensor_file();
+        data[0] = b'T';
+        assert!(parse_tensor_file(&mut Cursor::new(data)).is_err());
+    }
+}
@@ -35,8 +35,10 @@ struct PlyVertex {
     pos: Vector3<f32>,
     normal: Vector3<f32>,
     uv: Vector2<f32>,
+    color: Vector3<f32>,
     has_normal: bool,
     has_uv: bool,
+    has_color: bool,
 }
 impl ply::PropertyAccess for PlyVertex {
     fn new() -> Self {
@@ -44,8 +46,10 @@ impl ply::PropertyAccess for PlyVertex {
             pos: Vector3::new(0.0, 0.0, 0.0),
             normal: Vector3::new(0.0, 0.0, 0.0),
             uv: Vector2::new(0.0, 0.0),
+            color: Vector3::new(0.0, 0.0, 0.0),
             has_normal: false,
             has_uv: false,
+            has_color: false,
         }
     }
 
@@ -82,16 +86,40 @@ impl ply::PropertyAccess for PlyVertex {
                 self.has_uv = true;
                 self.uv.y = v
             }
+            // Colors are either normalized floats or bytes
+            ("red" | "r", p) => {
+                self.has_color = true;
+                self.color.x = color_value(p)
+            }
+            ("green" | "g", p) => {
+                self.has_color = true;
+                self.color.y = color_value(p)
+            }
+            ("blue" | "b", p) => {
+                self.has_color = true;
+                self.color.z = color_value(p)
+            }
+            ("alpha" | "a", _) => {}
             (k, _) => panic!("Face: Unexpected key/value combination: key: {}", k),
         }
     }
 }
 
+fn color_value(property: ply::Property) -> f32 {
+    match property {
+        ply::Property::Float(v) => v,
+        ply::Property::Double(v) => v as f32,
+        ply::Property::UChar(v) => f32::from(v) / 255.0,
+        p => panic!("Vertex: Unexpected color value: {:?}", p),
+    }
+}
+
 pub struct PlyLoaded {
     pub indices: Vec<Vector3<usize>>,
     pub points: Vec<Point3<f32>>,
     pub normals: Option<Vec<Vector3<f32>>>,
     pub uv: Option<Vec<Vector2<f32>>>,
+    pub color: Option<Vec<Vector3<f32>>>,
 }
 
 pub fn read_ply(filename: &std::path::Path) -> PlyLoaded {
@@ -167,6 +195,11 @@ pub fn read_ply(filename: &std::path::Path) -> PlyLoaded {
     } else {
         None
     };
+    let color = if vertex_list[0].has_color {
+        Some(vertex_list.iter().map(|v| v.color).collect())
+    } else {
+        None
+    };
     let vertex_list = vertex_list
         .into_iter()
         .map(|v| Point3::from_vec(v.pos))
@@ -177,5 +210,46 @@ pub fn read_ply(filename: &std::path::Path) -> PlyLoaded {
         points: vertex_list,
         normals,
         uv,
+        color,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_colors() {
+        let ply = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 0 51 0
+0 1 0 0 0 255
+3 0 1 2
+";
+        let filename = std::env::temp_dir().join("mitsuba_rs_colors.ply");
+        std::fs::write(&filename, ply).unwrap();
+        let mesh = read_ply(&filename);
+        assert_eq!(mesh.points.len(), 3);
+        assert_eq!(mesh.indices, vec![Vector3::new(0, 1, 2)]);
+        assert!(mesh.normals.is_none() && mesh.uv.is_none());
+        assert_eq!(
+            mesh.color.unwrap(),
+            vec![
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(0.0, 0.2, 0.0),
+                Vector3::new(0.0, 0.0, 1.0)
+            ]
+        );
     }
 }
@@ -0,0 +1,267 @@
+// Spectral integration and coefficient optimization for the RGB to spectrum
+// upsampling. This file is shared between `spectral.rs` and the build
+// script, which uses it to precompute the coefficient table.
+
+/// Smallest wavelength (nm) used for the integration
+pub const LAMBDA_MIN: f32 = 360.0;
+/// Largest wavelength (nm) used for the integration
+pub const LAMBDA_MAX: f32 = 830.0;
+/// Number of samples used to integrate the spectra
+const CIE_SAMPLES: usize = 95;
+
+// Linear sRGB <-> XYZ (D65 white point)
+#[rustfmt::skip]
+pub(crate) const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [ 3.240479, -1.537150, -0.498535],
+    [-0.969256,  1.875991,  0.041556],
+    [ 0.055648, -0.204043,  1.057311],
+];
+
+// CIE standard illuminant D65 (380nm to 780nm, 10nm steps)
+#[rustfmt::skip]
+const D65: [f64; 41] = [
+    49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008,
+    117.812, 114.861, 115.923, 108.811, 109.354, 107.802, 104.790, 107.689,
+    104.405, 104.046, 100.000, 96.3342, 95.788, 88.6856, 90.0062, 89.5991,
+    87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778, 78.2842, 69.7213,
+    71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054,
+    63.3828,
+];
+
+fn piecewise_gaussian(x: f64, mu: f64, sigma_l: f64, sigma_r: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma_l } else { sigma_r };
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 2° color matching functions (x̄, ȳ, z̄) at the wavelength `lambda` (nm).
+///
+/// Uses the multi-lobe analytic fit of Wyman et al. (2013).
+pub fn cie_1931(lambda: f32) -> [f32; 3] {
+    let l = lambda as f64;
+    let x = 1.056 * piecewise_gaussian(l, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(l, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(l, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(l, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(l, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(l, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(l, 459.0, 26.0, 13.8);
+    [x as f32, y as f32, z as f32]
+}
+
+/// Relative spectral power of the CIE D65 illuminant (100 at 560nm).
+///
+/// Values outside the tabulated range (380-780nm) are clamped.
+pub fn d65(lambda: f32) -> f32 {
+    let x = ((lambda as f64 - 380.0) / 10.0)
+        .max(0.0)
+        .min((D65.len() - 1) as f64);
+    let i = (x.floor() as usize).min(D65.len() - 2);
+    let t = x - i as f64;
+    ((1.0 - t) * D65[i] + t * D65[i + 1]) as f32
+}
+
+/// Precomputed integration tables shared by the optimizer
+struct Tables {
+    lambda: Vec<f64>,
+    // Weights to go from a spectrum to linear sRGB (illuminant D65 included)
+    rgb: Vec<[f64; 3]>,
+    // White point (XYZ) of the illuminant, used by the CIELAB conversion
+    xyz_whitepoint: [f64; 3],
+    // Normalization of the D65 illuminant (unit luminance)
+    d65_norm: f64,
+}
+
+lazy_static! {
+    static ref TABLES: Tables = {
+        let h = (LAMBDA_MAX - LAMBDA_MIN) as f64 / (CIE_SAMPLES - 1) as f64;
+        let lambda = (0..CIE_SAMPLES)
+            .map(|i| LAMBDA_MIN as f64 + i as f64 * h)
+            .collect::<Vec<_>>();
+
+        // Luminance of the illuminant (for normalization)
+        let (mut norm, mut y_integral) = (0.0, 0.0);
+        for l in &lambda {
+            let cmf = cie_1931(*l as f32);
+            norm += cmf[1] as f64 * d65(*l as f32) as f64;
+            y_integral += cmf[1] as f64;
+        }
+
+        let mut xyz_whitepoint = [0.0; 3];
+        let rgb = lambda
+            .iter()
+            .map(|l| {
+                let cmf = cie_1931(*l as f32);
+                let illum = d65(*l as f32) as f64 / norm;
+                let mut rgb = [0.0; 3];
+                for k in 0..3 {
+                    xyz_whitepoint[k] += cmf[k] as f64 * illum;
+                    for j in 0..3 {
+                        rgb[k] += XYZ_TO_SRGB[k][j] * cmf[j] as f64 * illum;
+                    }
+                }
+                rgb
+            })
+            .collect();
+
+        Tables {
+            lambda,
+            rgb,
+            xyz_whitepoint,
+            d65_norm: y_integral / norm,
+        }
+    };
+}
+
+fn sigmoid(x: f64) -> f64 {
+    0.5 + x / (2.0 * (1.0 + x * x).sqrt())
+}
+
+fn cie_lab(rgb: [f64; 3]) -> [f64; 3] {
+    // Linear sRGB -> XYZ (inverse of XYZ_TO_SRGB)
+    #[rustfmt::skip]
+    const SRGB_TO_XYZ: [[f64; 3]; 3] = [
+        [0.412453, 0.357580, 0.180423],
+        [0.212671, 0.715160, 0.072169],
+        [0.019334, 0.119193, 0.950227],
+    ];
+    let mut xyz = [0.0; 3];
+    for (k, row) in SRGB_TO_XYZ.iter().enumerate() {
+        xyz[k] = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+    }
+
+    let f = |t: f64| {
+        let delta = 6.0 / 29.0;
+        if t > delta * delta * delta {
+            t.cbrt()
+        } else {
+            t / (delta * delta * 3.0) + 4.0 / 29.0
+        }
+    };
+    let w = TABLES.xyz_whitepoint;
+    let (fx, fy, fz) = (f(xyz[0] / w[0]), f(xyz[1] / w[1]), f(xyz[2] / w[2]));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// Residual (in CIELAB) between the target and the spectrum described by the
+// coefficients. The coefficients are expressed over a normalized wavelength
+// range [0, 1] to keep the optimization well conditioned.
+fn eval_residual(coeffs: &[f64; 3], target: [f64; 3]) -> [f64; 3] {
+    let t = &*TABLES;
+    let mut rgb = [0.0; 3];
+    for (l, w) in t.lambda.iter().zip(t.rgb.iter()) {
+        let x = (l - LAMBDA_MIN as f64) / (LAMBDA_MAX - LAMBDA_MIN) as f64;
+        let s = sigmoid((coeffs[0] * x + coeffs[1]) * x + coeffs[2]);
+        for k in 0..3 {
+            rgb[k] += w[k] * s;
+        }
+    }
+    let (a, b) = (cie_lab(rgb), cie_lab(target));
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+// Solve the 3x3 linear system `a * x = b` (Gaussian elimination with
+// partial pivoting). Return None if the system is degenerated.
+fn solve3(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
+    for i in 0..3 {
+        let pivot = (i..3)
+            .max_by(|&p, &q| a[p][i].abs().partial_cmp(&a[q][i].abs()).unwrap())
+            .unwrap();
+        if a[pivot][i].abs() < 1e-15 {
+            return None;
+        }
+        a.swap(i, pivot);
+        b.swap(i, pivot);
+        let row = a[i];
+        for j in (i + 1)..3 {
+            let f = a[j][i] / row[i];
+            for (v, r) in a[j].iter_mut().zip(row.iter()).skip(i) {
+                *v -= f * r;
+            }
+            b[j] -= f * b[i];
+        }
+    }
+    let mut x = [0.0; 3];
+    for i in (0..3).rev() {
+        let s = (i + 1..3).fold(b[i], |s, k| s - a[i][k] * x[k]);
+        x[i] = s / a[i][i];
+    }
+    Some(x)
+}
+
+// Gauss-Newton optimization of the (normalized) coefficients
+fn gauss_newton(target: [f64; 3], coeffs: &mut [f64; 3]) {
+    for _ in 0..15 {
+        let residual = eval_residual(coeffs, target);
+        let mut jacobian = [[0.0; 3]; 3];
+        for i in 0..3 {
+            let mut tmp = *coeffs;
+            tmp[i] -= 1e-5;
+            let r0 = eval_residual(&tmp, target);
+            tmp[i] += 2e-5;
+            let r1 = eval_residual(&tmp, target);
+            for j in 0..3 {
+                jacobian[j][i] = (r1[j] - r0[j]) / 2e-5;
+            }
+        }
+
+        let x = match solve3(jacobian, residual) {
+            Some(x) => x,
+            None => break,
+        };
+        for i in 0..3 {
+            coeffs[i] -= x[i];
+        }
+
+        // Keep the coefficients in a reasonable range
+        let max = coeffs.iter().fold(0.0f64, |m, c| m.max(c.abs()));
+        if max > 200.0 {
+            for c in coeffs.iter_mut() {
+                *c *= 200.0 / max;
+            }
+        }
+
+        let r2 = residual.iter().map(|v| v * v).sum::<f64>();
+        if r2 < 1e-6 {
+            break;
+        }
+    }
+}
+
+// Optimize the coefficients starting from the current values. If the
+// optimization does not converge, use a continuation from a gray with the
+// same maximum value, progressively moving toward the target color.
+fn optimize(target: [f64; 3], coeffs: &mut [f64; 3]) {
+    gauss_newton(target, coeffs);
+    let r2 = eval_residual(coeffs, target)
+        .iter()
+        .map(|v| v * v)
+        .sum::<f64>();
+    if r2 < 1e-4 {
+        return;
+    }
+
+    let max = target.iter().cloned().fold(0.0, f64::max);
+    *coeffs = [0.0; 3];
+    let steps = 8;
+    for s in 1..=steps {
+        let t = s as f64 / steps as f64;
+        let current = [
+            max + (target[0] - max) * t,
+            max + (target[1] - max) * t,
+            max + (target[2] - max) * t,
+        ];
+        gauss_newton(current, coeffs);
+    }
+}
+
+// Convert coefficients from the normalized wavelength range to nanometers
+fn denormalize(coeffs: [f64; 3]) -> [f32; 3] {
+    let c0 = LAMBDA_MIN as f64;
+    let c1 = 1.0 / (LAMBDA_MAX - LAMBDA_MIN) as f64;
+    let (a, b, c) = (coeffs[0], coeffs[1], coeffs[2]);
+    [
+        (a * c1 * c1) as f32,
+        (b * c1 - 2.0 * a * c0 * c1 * c1) as f32,
+        (c - b * c0 * c1 + a * (c0 * c1) * (c0 * c1)) as f32,
+    ]
+}
@@ -0,0 +1,175 @@
+//! Scattering coefficients of participating materials.
+//!
+//! Mitsuba's subsurface models (`hk`, `dipole`) describe the medium either
+//! with absorption and scattering coefficients, with extinction and albedo,
+//! or with a named material from the measurements of Jensen et al.
+//! ("A Practical Model for Subsurface Light Transport", 2001).
+use crate::{Error, Result, Spectrum, Value, RGB};
+use std::collections::HashMap;
+
+// Reduced scattering and absorption coefficients (mm^-1)
+#[rustfmt::skip]
+const JENSEN_DATA: &[(&str, [f32; 3], [f32; 3])] = &[
+    ("apple", [2.29, 2.39, 1.97], [0.0030, 0.0034, 0.046]),
+    ("chicken1", [0.15, 0.21, 0.38], [0.015, 0.077, 0.19]),
+    ("chicken2", [0.19, 0.25, 0.32], [0.018, 0.088, 0.20]),
+    ("cream", [7.38, 5.47, 3.15], [0.0002, 0.0028, 0.0163]),
+    ("ketchup", [0.18, 0.07, 0.03], [0.061, 0.97, 1.45]),
+    ("marble", [2.19, 2.62, 3.00], [0.0021, 0.0041, 0.0071]),
+    ("potato", [0.68, 0.70, 0.55], [0.0024, 0.0090, 0.12]),
+    ("skimmilk", [0.70, 1.22, 1.90], [0.0014, 0.0025, 0.0142]),
+    ("skin1", [0.74, 0.88, 1.01], [0.032, 0.17, 0.48]),
+    ("skin2", [1.09, 1.59, 1.79], [0.013, 0.070, 0.145]),
+    ("spectralon", [11.6, 20.4, 14.9], [0.00, 0.00, 0.00]),
+    ("wholemilk", [2.55, 3.21, 3.77], [0.0011, 0.0024, 0.014]),
+];
+
+fn rgb_spectrum(v: &[f32; 3]) -> Spectrum {
+    Spectrum::from_rgb(format!("{}, {}, {}", v[0], v[1], v[2]))
+}
+
+/// Names of the measured materials (Jensen et al. 2001)
+pub fn scattering_material_names() -> Vec<&'static str> {
+    JENSEN_DATA.iter().map(|(n, _, _)| *n).collect()
+}
+
+/// Lookup the (reduced) scattering and absorption coefficients in mm^-1 of
+/// a measured material (case insensitive)
+pub fn lookup_scattering_material(name: &str) -> Result<ScatteringParameters> {
+    let lower = name.to_lowercase();
+    match JENSEN_DATA.iter().find(|(n, _, _)| *n == lower) {
+        Some((_, sigma_s, sigma_a)) => Ok(ScatteringParameters::Coefficients {
+            sigma_s: rgb_spectrum(sigma_s),
+            sigma_a: rgb_spectrum(sigma_a),
+        }),
+        None => Err(Error::UnknownMaterial(
+            name.to_string(),
+            scattering_material_names().join(", "),
+        )),
+    }
+}
+
+/// Description of the medium coefficients (as given inside the scene)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScatteringParameters {
+    /// `sigmaS` and `sigmaA` (also used for named materials)
+    Coefficients {
+        sigma_s: Spectrum,
+        sigma_a: Spectrum,
+    },
+    /// `sigmaT` and `albedo`
+    Extinction { sigma_t: Spectrum, albedo: Spectrum },
+}
+impl ScatteringParameters {
+    /// Read the coefficients following Mitsuba's rules: a named `material`,
+    /// `sigmaS`/`sigmaA` or `sigmaT`/`albedo` (mutually exclusive).
+    /// Without any of them, the `default` material is used.
+    pub(crate) fn parse(map: &mut HashMap<String, Value>, default: &str) -> Result<Self> {
+        let material = map.remove("material");
+        let sigma_s = map.remove("sigmaS");
+        let sigma_a = map.remove("sigmaA");
+        let sigma_t = map.remove("sigmaT");
+        let albedo = map.remove("albedo");
+
+        let has_coeffs = sigma_s.is_some() || sigma_a.is_some();
+        let has_extinction = sigma_t.is_some() || albedo.is_some();
+        if (material.is_some() && (has_coeffs || has_extinction)) || (has_coeffs && has_extinction)
+        {
+            return Err(Error::InvalidParameter(
+                "material".to_string(),
+                "please specify either a material, sigmaS/sigmaA or sigmaT/albedo".to_string(),
+            ));
+        }
+
+        match (sigma_s, sigma_a, sigma_t, albedo) {
+            (Some(sigma_s), Some(sigma_a), None, None) => Ok(ScatteringParameters::Coefficients {
+                sigma_s: sigma_s.as_spectrum_or_gray()?,
+                sigma_a: sigma_a.as_spectrum_or_gray()?,
+            }),
+            (None, None, Some(sigma_t), Some(albedo)) => Ok(ScatteringParameters::Extinction {
+                sigma_t: sigma_t.as_spectrum_or_gray()?,
+                albedo: albedo.as_spectrum_or_gray()?,
+            }),
+            (None, None, None, None) => match material {
+                Some(v) => lookup_scattering_material(&v.as_string()?),
+                None => lookup_scattering_material(default),
+            },
+            _ => Err(Error::InvalidParameter(
+                "sigmaS".to_string(),
+                "sigmaS/sigmaA and sigmaT/albedo need to be specified together".to_string(),
+            )),
+        }
+    }
+
+    /// Scattering and absorption coefficients (RGB)
+    pub fn sigma_s_a(&self) -> Result<(RGB, RGB)> {
+        match self {
+            ScatteringParameters::Coefficients { sigma_s, sigma_a } => {
+                Ok((sigma_s.clone().as_rgb()?, sigma_a.clone().as_rgb()?))
+            }
+            ScatteringParameters::Extinction { sigma_t, albedo } => {
+                let t = sigma_t.clone().as_rgb()?;
+                let a = albedo.clone().as_rgb()?;
+                let s = RGB {
+                    r: t.r * a.r,
+                    g: t.g * a.g,
+                    b: t.b * a.b,
+                };
+                Ok((
+                    s,
+                    RGB {
+                        r: t.r - s.r,
+                        g: t.g - s.g,
+                        b: t.b - s.b,
+                    },
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_material() {
+        let mut map = HashMap::new();
+        map.insert("material".to_string(), Value::String("Ketchup".to_string()));
+        let (s, a) = ScatteringParameters::parse(&mut map, "skin1")
+            .unwrap()
+            .sigma_s_a()
+            .unwrap();
+        assert_eq!((s.r, a.b), (0.18, 1.45));
+        assert!(lookup_scattering_material("mayonnaise").is_err());
+    }
+
+    #[test]
+    fn extinction_albedo() {
+        let mut map = HashMap::new();
+        map.insert(
+            "sigmaT".to_string(),
+            Value::Spectrum(Spectrum::from_f32(2.0)),
+        );
+        map.insert(
+            "albedo".to_string(),
+            Value::Spectrum(Spectrum::from_f32(0.75)),
+        );
+        let (s, a) = ScatteringParameters::parse(&mut map, "skin1")
+            .unwrap()
+            .sigma_s_a()
+            .unwrap();
+        assert_eq!((s.g, a.g), (1.5, 0.5));
+    }
+
+    #[test]
+    fn conflicting_parameters() {
+        let mut map = HashMap::new();
+        map.insert("material".to_string(), Value::String("skin2".to_string()));
+        map.insert(
+            "sigmaT".to_string(),
+            Value::Spectrum(Spectrum::from_f32(2.0)),
+        );
+        assert!(ScatteringParameters::parse(&mut map, "skin1").is_err());
+    }
+}
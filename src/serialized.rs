@@ -215,6 +215,7 @@ mod tests {
                 emitter: None,
                 interior: None,
                 exterior: None,
+                subsurface: None,
             },
         };
         let wk = std::path::Path::new(".");
@@ -236,6 +237,7 @@ mod tests {
                     emitter: None,
                     interior: None,
                     exterior: None,
+                    subsurface: None,
                 },
             };
             let wk = std::path::Path::new(".");
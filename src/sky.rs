@@ -0,0 +1,692 @@
+//! Radiance of the `sun`, `sky` and `sunsky` emitters.
+//!
+//! As in Mitsuba 0.6 (RGB mode), the sky follows the Hosek and Wilkie (2012)
+//! model and the sun is a disk whose spectral radiance is attenuated by the
+//! atmosphere as in Preetham et al. (1999). The emitters can be baked into
+//! lat-long environment maps (same layout and `toWorld` convention as the
+//! `envmap` emitter).
+//!
+//! The coefficients of the sky model are not distributed with this crate:
+//! they are read from the `ArHosekSkyModelData_RGB.h` file of the reference
+//! implementation (see `SkyDataset`).
+use crate::images::Image;
+use crate::spectral::{cie_1931, LAMBDA_MAX, LAMBDA_MIN, XYZ_TO_SRGB};
+use crate::{Emitter, Error, Result, RGB};
+
+use cgmath::Vector3;
+use std::f64::consts::PI;
+use std::path::Path;
+
+/// Apparent radius of the sun seen from the earth (degrees)
+const SUN_APP_RADIUS: f64 = 0.5358 / 2.0;
+/// Sum of the CIE Y curve (normalization used by Mitsuba)
+const CIE_Y_INTEGRAL: f64 = 106.856_980;
+
+// Spherical coordinates (zenith angle, azimuth) of a direction (y up)
+fn from_sphere(d: Vector3<f32>) -> (f64, f64) {
+    let (x, y, z) = (d.x as f64, d.y as f64, d.z as f64);
+    let norm = (x * x + y * y + z * z).sqrt();
+    let theta = (y / norm).clamp(-1.0, 1.0).acos();
+    let mut phi = x.atan2(-z);
+    if phi < 0.0 {
+        phi += 2.0 * PI;
+    }
+    (theta, phi)
+}
+
+fn to_sphere(theta: f64, phi: f64) -> Vector3<f64> {
+    Vector3::new(
+        phi.sin() * theta.sin(),
+        theta.cos(),
+        -phi.cos() * theta.sin(),
+    )
+}
+
+/// Coefficients of the RGB Hosek-Wilkie sky model
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkyDataset {
+    // Per channel: 2 albedos x 10 turbidities x 6 elevations x 9 parameters
+    configs: [Vec<f64>; 3],
+    // Per channel: 2 albedos x 10 turbidities x 6 elevations
+    radiances: [Vec<f64>; 3],
+}
+
+fn dataset_error(reason: String) -> Error {
+    Error::Other(Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        reason,
+    )))
+}
+
+impl SkyDataset {
+    /// Parse the content of `ArHosekSkyModelData_RGB.h`
+    /// (`datasetRGB1..3` and `datasetRGBRad1..3` arrays)
+    pub fn parse(source: &str) -> Result<Self> {
+        // Values of the array named `name`
+        let array = |name: &str, size: usize| -> Result<Vec<f64>> {
+            let start = source
+                .find(&format!("{}[]", name))
+                .ok_or_else(|| dataset_error(format!("array {} not found", name)))?;
+            let body = &source[start..];
+            let (open, close) = match (body.find('{'), body.find('}')) {
+                (Some(open), Some(close)) if open < close => (open, close),
+                _ => return Err(dataset_error(format!("array {} is not closed", name))),
+            };
+            // Remove the comments
+            let mut values = String::new();
+            for line in body[open + 1..close].lines() {
+                values += line.split("//").next().unwrap();
+                values += "\n";
+            }
+            let values = values
+                .split(',')
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| {
+                    v.parse::<f64>()
+                        .map_err(|_| dataset_error(format!("invalid value {:?} in {}", v, name)))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if values.len() != size {
+                return Err(dataset_error(format!(
+                    "array {} has {} values ({} expected)",
+                    name,
+                    values.len(),
+                    size
+                )));
+            }
+            Ok(values)
+        };
+        Ok(SkyDataset {
+            configs: [
+                array("datasetRGB1", 1080)?,
+                array("datasetRGB2", 1080)?,
+                array("datasetRGB3", 1080)?,
+            ],
+            radiances: [
+                array("datasetRGBRad1", 120)?,
+                array("datasetRGBRad2", 120)?,
+                array("datasetRGBRad3", 120)?,
+            ],
+        })
+    }
+
+    /// Read `ArHosekSkyModelData_RGB.h`
+    pub fn read(filename: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(filename).map_err(|e| Error::Other(Box::new(e)))?;
+        SkyDataset::parse(&source)
+    }
+}
+
+// Interpolation of the dataset over the turbidity, albedo and solar
+// elevation (quintic Bezier curve), `stride` values per control point
+fn cook(dataset: &[f64], stride: usize, turbidity: f64, albedo: f64, elevation: f64) -> Vec<f64> {
+    let int_turbidity = turbidity as usize;
+    let turbidity_rem = turbidity - int_turbidity as f64;
+    let t = (elevation / (PI / 2.0)).powf(1.0 / 3.0);
+    let bezier = [
+        (1.0 - t).powi(5),
+        5.0 * (1.0 - t).powi(4) * t,
+        10.0 * (1.0 - t).powi(3) * t.powi(2),
+        10.0 * (1.0 - t).powi(2) * t.powi(3),
+        5.0 * (1.0 - t) * t.powi(4),
+        t.powi(5),
+    ];
+
+    let mut config = vec![0.0; stride];
+    let mut add = |albedo_index: usize, turbidity_index: usize, weight: f64| {
+        let offset = stride * 6 * (10 * albedo_index + turbidity_index);
+        for (i, c) in config.iter_mut().enumerate() {
+            *c += weight
+                * bezier
+                    .iter()
+                    .enumerate()
+                    .map(|(k, b)| b * dataset[offset + i + stride * k])
+                    .sum::<f64>();
+        }
+    };
+    add(0, int_turbidity - 1, (1.0 - albedo) * (1.0 - turbidity_rem));
+    add(1, int_turbidity - 1, albedo * (1.0 - turbidity_rem));
+    if int_turbidity < 10 {
+        add(0, int_turbidity, (1.0 - albedo) * turbidity_rem);
+        add(1, int_turbidity, albedo * turbidity_rem);
+    }
+    config
+}
+
+/// Hosek-Wilkie sky (`sky` emitter)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkyModel {
+    configs: [Vec<f64>; 3],
+    radiances: [f64; 3],
+    sun: (f64, f64),
+    stretch: f64,
+    scale: f64,
+}
+
+impl SkyModel {
+    /// `turbidity` in [1, 10] and `stretch` in [1, 2]. A sun below the
+    /// horizon is moved to the horizon.
+    pub fn new(
+        dataset: &SkyDataset,
+        turbidity: f32,
+        albedo: RGB,
+        sun: Vector3<f32>,
+        stretch: f32,
+        scale: f32,
+    ) -> Result<Self> {
+        if !(1.0..=10.0).contains(&turbidity) {
+            return Err(Error::InvalidParameter(
+                "turbidity".to_string(),
+                format!("{} is outside [1, 10]", turbidity),
+            ));
+        }
+        if !(1.0..=2.0).contains(&stretch) {
+            return Err(Error::InvalidParameter(
+                "stretch".to_string(),
+                format!("{} is outside [1, 2]", stretch),
+            ));
+        }
+
+        let sun = from_sphere(sun);
+        let elevation = (PI / 2.0 - sun.0).max(0.0);
+        let albedo = [albedo.r, albedo.g, albedo.b];
+        let turbidity = turbidity as f64;
+        let channel = |c: usize| {
+            let a = albedo[c] as f64;
+            (
+                cook(&dataset.configs[c], 9, turbidity, a, elevation),
+                cook(&dataset.radiances[c], 1, turbidity, a, elevation)[0],
+            )
+        };
+        let (c0, c1, c2) = (channel(0), channel(1), channel(2));
+        Ok(SkyModel {
+            configs: [c0.0, c1.0, c2.0],
+            radiances: [c0.1, c1.1, c2.1],
+            sun,
+            stretch: stretch as f64,
+            scale: scale as f64,
+        })
+    }
+
+    fn eval_spherical(&self, theta: f64, phi: f64) -> RGB {
+        let theta = theta / self.stretch;
+        if theta.cos() <= 0.0 {
+            return RGB {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            };
+        }
+        let (sun_theta, sun_phi) = self.sun;
+        let cos_gamma =
+            theta.cos() * sun_theta.cos() + theta.sin() * sun_theta.sin() * (phi - sun_phi).cos();
+        let gamma = cos_gamma.clamp(-1.0, 1.0).acos();
+
+        let radiance = |c: usize| {
+            let p = &self.configs[c];
+            let exp_m = (p[4] * gamma).exp();
+            let ray_m = cos_gamma * cos_gamma;
+            let mie_m = (1.0 + cos_gamma * cos_gamma)
+                / (1.0 + p[8] * p[8] - 2.0 * p[8] * cos_gamma).powf(1.5);
+            let zenith = theta.cos().sqrt();
+            let v = (1.0 + p[0] * (p[1] / (theta.cos() + 0.01)).exp())
+                * (p[2] + p[3] * exp_m + p[5] * ray_m + p[6] * mie_m + p[7] * zenith)
+                * self.radiances[c];
+            (v / CIE_Y_INTEGRAL).max(0.0) * self.scale
+        };
+        RGB {
+            r: radiance(0) as f32,
+            g: radiance(1) as f32,
+            b: radiance(2) as f32,
+        }
+    }
+
+    /// Radiance coming from the direction `d` (emitter space)
+    pub fn eval(&self, d: Vector3<f32>) -> RGB {
+        let (theta, phi) = from_sphere(d);
+        self.eval_spherical(theta, phi)
+    }
+}
+
+// Preetham et al. (1999) spectral data, as used by Mitsuba
+
+// Ozone absorption coefficient (cm^-1)
+#[rustfmt::skip]
+const K_O: ([f64; 64], [f64; 64]) = ([
+    300.0, 305.0, 310.0, 315.0, 320.0, 325.0, 330.0, 335.0, 340.0, 345.0,
+    350.0, 355.0, 445.0, 450.0, 455.0, 460.0, 465.0, 470.0, 475.0, 480.0,
+    485.0, 490.0, 495.0, 500.0, 505.0, 510.0, 515.0, 520.0, 525.0, 530.0,
+    535.0, 540.0, 545.0, 550.0, 555.0, 560.0, 565.0, 570.0, 575.0, 580.0,
+    585.0, 590.0, 595.0, 600.0, 605.0, 610.0, 620.0, 630.0, 640.0, 650.0,
+    660.0, 670.0, 680.0, 690.0, 700.0, 710.0, 720.0, 730.0, 740.0, 750.0,
+    760.0, 770.0, 780.0, 790.0,
+], [
+    10.0, 4.8, 2.7, 1.35, 0.8, 0.38, 0.16, 0.075, 0.04, 0.019,
+    0.007, 0.0, 0.003, 0.003, 0.004, 0.006, 0.008, 0.009, 0.012, 0.014,
+    0.017, 0.021, 0.025, 0.03, 0.035, 0.04, 0.045, 0.048, 0.057, 0.063,
+    0.07, 0.075, 0.08, 0.085, 0.095, 0.103, 0.11, 0.12, 0.122, 0.12,
+    0.118, 0.115, 0.12, 0.125, 0.13, 0.12, 0.105, 0.09, 0.079, 0.067,
+    0.057, 0.048, 0.036, 0.028, 0.023, 0.018, 0.014, 0.011, 0.01, 0.009,
+    0.007, 0.004, 0.0, 0.0,
+]);
+
+// Mixed gases absorption coefficient (cm^-1)
+const K_G: ([f64; 4], [f64; 4]) = ([759.0, 760.0, 770.0, 771.0], [0.0, 3.0, 0.21, 0.0]);
+
+// Water vapor absorption coefficient (cm^-1)
+#[rustfmt::skip]
+const K_WA: ([f64; 13], [f64; 13]) = ([
+    689.0, 690.0, 700.0, 710.0, 720.0, 730.0, 740.0, 750.0, 760.0, 770.0,
+    780.0, 790.0, 800.0,
+], [
+    0.0, 0.016, 0.024, 0.0125, 1.0, 0.87, 0.061, 0.001, 0.000_01, 0.000_01,
+    0.0006, 0.0175, 0.036,
+]);
+
+// Solar spectral radiance outside the atmosphere (W / (m^2 nm sr))
+#[rustfmt::skip]
+const SOL: ([f64; 38], [f64; 38]) = ([
+    380.0, 390.0, 400.0, 410.0, 420.0, 430.0, 440.0, 450.0, 460.0, 470.0,
+    480.0, 490.0, 500.0, 510.0, 520.0, 530.0, 540.0, 550.0, 560.0, 570.0,
+    580.0, 590.0, 600.0, 610.0, 620.0, 630.0, 640.0, 650.0, 660.0, 670.0,
+    680.0, 690.0, 700.0, 710.0, 720.0, 730.0, 740.0, 750.0,
+], [
+    16559.0, 16233.7, 21127.5, 25888.2, 25829.1, 24232.3, 26760.5, 29658.3,
+    30545.4, 30057.5, 30663.7, 28830.4, 28712.1, 27825.0, 27100.6, 27233.6,
+    26361.3, 25503.8, 25060.2, 25311.6, 25355.9, 25134.2, 24631.5, 24173.2,
+    23685.3, 23212.1, 22827.7, 22339.8, 21970.2, 21526.7, 21097.9, 20728.3,
+    20240.4, 19870.8, 19427.2, 19072.4, 18628.9, 18259.2,
+]);
+
+// Piecewise linear spectrum (zero outside of the wavelength range)
+fn interpolate(wavelengths: &[f64], values: &[f64], lambda: f64) -> f64 {
+    if lambda < wavelengths[0] || lambda > wavelengths[wavelengths.len() - 1] {
+        return 0.0;
+    }
+    let i = wavelengths
+        .windows(2)
+        .position(|w| lambda <= w[1])
+        .unwrap_or(wavelengths.len() - 2);
+    let t = (lambda - wavelengths[i]) / (wavelengths[i + 1] - wavelengths[i]);
+    (1.0 - t) * values[i] + t * values[i + 1]
+}
+
+// Spectral radiance of the sun (zenith angle `theta`) at the ground
+fn sun_spectral_radiance(theta: f64, turbidity: f64, lambda: f64) -> f64 {
+    let beta = 0.046_083_658_220_5 * turbidity - 0.045_860_259_285_22;
+    // Relative optical mass
+    let m = 1.0 / (theta.cos() + 0.15 * (93.885 - theta.to_degrees()).powf(-1.253));
+    let lambda_um = lambda / 1000.0;
+
+    // Rayleigh scattering
+    let tau_r = (-m * 0.008_735 * lambda_um.powf(-4.08)).exp();
+    // Aerosol attenuation (alpha = 1.3)
+    let tau_a = (-m * beta * lambda_um.powf(-1.3)).exp();
+    // Ozone absorption (0.35 cm of ozone)
+    let k_o = interpolate(&K_O.0, &K_O.1, lambda);
+    let tau_o = (-m * k_o * 0.35).exp();
+    // Mixed gases absorption
+    let k_g = interpolate(&K_G.0, &K_G.1, lambda);
+    let tau_g = (-1.41 * k_g * m / (1.0 + 118.93 * k_g * m).powf(0.45)).exp();
+    // Water vapor absorption (2 cm of precipitable water)
+    let k_wa = interpolate(&K_WA.0, &K_WA.1, lambda);
+    let tau_wa = (-0.2385 * k_wa * 2.0 * m / (1.0 + 20.07 * k_wa * 2.0 * m).powf(0.45)).exp();
+
+    interpolate(&SOL.0, &SOL.1, lambda) * tau_r * tau_a * tau_o * tau_g * tau_wa
+}
+
+/// Radiance (linear sRGB) of the solar disk for the sun zenith angle
+/// `theta` (radians). Zero when the sun is below the horizon.
+pub fn sun_radiance(theta: f32, turbidity: f32) -> RGB {
+    let theta = theta as f64;
+    if theta >= PI / 2.0 {
+        return RGB {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+        };
+    }
+    // Integration against the CIE curves (1nm steps), Y normalized
+    let mut xyz = [0.0; 3];
+    let mut y_integral = 0.0;
+    let mut lambda = LAMBDA_MIN as f64;
+    while lambda <= LAMBDA_MAX as f64 {
+        let cmf = cie_1931(lambda as f32);
+        let s = sun_spectral_radiance(theta, turbidity as f64, lambda);
+        for k in 0..3 {
+            xyz[k] += cmf[k] as f64 * s;
+        }
+        y_integral += cmf[1] as f64;
+        lambda += 1.0;
+    }
+    let rgb = XYZ_TO_SRGB
+        .iter()
+        .map(|row| (0..3).map(|k| row[k] * xyz[k]).sum::<f64>() / y_integral)
+        .map(|v| v.max(0.0) as f32)
+        .collect::<Vec<_>>();
+    RGB {
+        r: rgb[0],
+        g: rgb[1],
+        b: rgb[2],
+    }
+}
+
+/// Solar disk (`sun` emitter)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SunModel {
+    direction: Vector3<f64>,
+    // Radiance of the disk with its apparent radius
+    radiance: RGB,
+    // Radius and radiance of the scaled disk
+    cos_radius: f64,
+    disk_radiance: RGB,
+}
+
+impl SunModel {
+    /// The disk is `radius_scale` times larger than the real sun and keeps
+    /// the same power (as in Mitsuba)
+    pub fn new(turbidity: f32, sun: Vector3<f32>, scale: f32, radius_scale: f32) -> Self {
+        let (theta, phi) = from_sphere(sun);
+        let radiance = sun_radiance(theta as f32, turbidity);
+        let radiance = RGB {
+            r: radiance.r * scale,
+            g: radiance.g * scale,
+            b: radiance.b * scale,
+        };
+        let radius = SUN_APP_RADIUS.to_radians();
+        let cos_radius = (radius * radius_scale as f64).cos();
+        let ratio = ((1.0 - radius.cos()) / (1.0 - cos_radius)) as f32;
+        SunModel {
+            direction: to_sphere(theta, phi),
+            disk_radiance: RGB {
+                r: radiance.r * ratio,
+                g: radiance.g * ratio,
+                b: radiance.b * ratio,
+            },
+            radiance,
+            cos_radius,
+        }
+    }
+
+    /// Radiance of the sun (apparent radius, `scale` applied)
+    pub fn radiance(&self) -> RGB {
+        self.radiance
+    }
+
+    /// Irradiance of the sun (for a `sunRadiusScale` of 0, Mitsuba uses
+    /// a directional emitter with this irradiance)
+    pub fn irradiance(&self) -> RGB {
+        let solid_angle = (2.0 * PI * (1.0 - SUN_APP_RADIUS.to_radians().cos())) as f32;
+        RGB {
+            r: self.radiance.r * solid_angle,
+            g: self.radiance.g * solid_angle,
+            b: self.radiance.b * solid_angle,
+        }
+    }
+
+    /// Direction toward the sun (emitter space)
+    pub fn direction(&self) -> Vector3<f32> {
+        self.direction.cast().unwrap()
+    }
+
+    fn is_inside(&self, d: Vector3<f64>) -> bool {
+        self.direction.x * d.x + self.direction.y * d.y + self.direction.z * d.z >= self.cos_radius
+    }
+
+    /// Radiance coming from the direction `d` (emitter space)
+    pub fn eval(&self, d: Vector3<f32>) -> RGB {
+        let (theta, phi) = from_sphere(d);
+        if self.is_inside(to_sphere(theta, phi)) {
+            self.disk_radiance
+        } else {
+            RGB {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            }
+        }
+    }
+}
+
+/// Bake a `sun`, `sky` or `sunsky` emitter into a lat-long environment map
+/// (`resolution` x `resolution / 2`, RGB). As in Mitsuba, the map is in the
+/// emitter space: it is an `envmap` to place with the emitter `to_world`.
+/// `dataset` is needed for the sky. A sun with a `sunRadiusScale` of 0 is not baked
+/// (see `SunModel::irradiance`).
+pub fn bake(emitter: &Emitter, dataset: Option<&SkyDataset>) -> Result<Image> {
+    let (turbidity, resolution, sun_direction, sun, sky) = match emitter {
+        Emitter::SunSky {
+            turbidity,
+            resolution,
+            sun_direction,
+            sun,
+            sky,
+            ..
+        } => (*turbidity, *resolution as usize, sun_direction, sun, sky),
+        _ => {
+            return Err(Error::Unsupported(format!(
+                "sky baking of the emitter {:?}",
+                emitter
+            )))
+        }
+    };
+    let direction = sun_direction.to_vector();
+    let sky = match sky {
+        Some(sky) => {
+            let dataset = dataset.ok_or_else(|| {
+                Error::Unsupported("sky baking without the Hosek-Wilkie dataset".to_string())
+            })?;
+            let albedo = sky.albedo.clone().as_rgb()?;
+            Some(SkyModel::new(
+                dataset,
+                turbidity,
+                albedo,
+                direction,
+                sky.stretch,
+                sky.scale,
+            )?)
+        }
+        None => None,
+    };
+    let sun = match sun {
+        Some(sun) if sun.radius_scale > 0.0 => Some(SunModel::new(
+            turbidity,
+            direction,
+            sun.scale,
+            sun.radius_scale,
+        )),
+        _ => None,
+    };
+
+    let (width, height) = (resolution, resolution / 2);
+    let (dphi, dtheta) = (2.0 * PI / width as f64, PI / height as f64);
+    let mut data = vec![0.0; width * height * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let mut v = [0.0; 3];
+            // Sky at the pixel center
+            if let Some(sky) = &sky {
+                let rgb = sky.eval_spherical((y as f64 + 0.5) * dtheta, (x as f64 + 0.5) * dphi);
+                v = [rgb.r, rgb.g, rgb.b];
+            }
+            // Portion of the pixel covered by the sun
+            if let Some(sun) = &sun {
+                let center = to_sphere((y as f64 + 0.5) * dtheta, (x as f64 + 0.5) * dphi);
+                let cos_center = sun.direction.x * center.x
+                    + sun.direction.y * center.y
+                    + sun.direction.z * center.z;
+                let margin = sun.cos_radius.acos() + dphi.max(dtheta);
+                if cos_center >= margin.min(PI).cos() {
+                    const N: usize = 16;
+                    let (mut covered, mut total) = (0.0, 0.0);
+                    for j in 0..N {
+                        let theta = (y as f64 + (j as f64 + 0.5) / N as f64) * dtheta;
+                        for i in 0..N {
+                            let phi = (x as f64 + (i as f64 + 0.5) / N as f64) * dphi;
+                            if sun.is_inside(to_sphere(theta, phi)) {
+                                covered += theta.sin();
+                            }
+                            total += theta.sin();
+                        }
+                    }
+                    let coverage = (covered / total) as f32;
+                    v[0] += sun.disk_radiance.r * coverage;
+                    v[1] += sun.disk_radiance.g * coverage;
+                    v[2] += sun.disk_radiance.b * coverage;
+                }
+            }
+            data[(y * width + x) * 3..(y * width + x + 1) * 3].copy_from_slice(&v);
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        channels: 3,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SunDirection, SunEmitterParam};
+
+    // Dataset where the parameters only depend on the albedo
+    // (sky radiance: (1 - a) * 1 + a * 2, times the channel index + 1)
+    fn dataset() -> SkyDataset {
+        let mut source = String::from("// Synthetic dataset\n");
+        for c in 1..=3 {
+            let config = (0..1080)
+                .map(|i| {
+                    if i % 9 == 2 {
+                        if i < 540 {
+                            "1.0"
+                        } else {
+                            "2.0"
+                        }
+                    } else {
+                        "0.0"
+                    }
+                })
+                .collect::<Vec<_>>();
+            source += &format!(
+                "double datasetRGB{}[] =\n{{\n// albedo 0\n{}\n}};\n",
+                c,
+                config.join(",\n")
+            );
+            let radiance = vec![format!("{:e}", c as f64 * CIE_Y_INTEGRAL); 120];
+            source += &format!(
+                "double datasetRGBRad{}[] = {{ {} }};\n",
+                c,
+                radiance.join(", ")
+            );
+        }
+        SkyDataset::parse(&source).unwrap()
+    }
+
+    #[test]
+    fn sky_model() {
+        let dataset = dataset();
+        let albedo = RGB {
+            r: 0.0,
+            g: 0.5,
+            b: 1.0,
+        };
+        let sun = Vector3::new(0.0, 1.0, -1.0);
+        let sky = SkyModel::new(&dataset, 3.5, albedo, sun, 1.0, 2.0).unwrap();
+        let v = sky.eval(Vector3::new(1.0, 1.0, 0.0));
+        assert!((v.r - 2.0).abs() < 1e-4);
+        assert!((v.g - 6.0).abs() < 1e-4);
+        assert!((v.b - 12.0).abs() < 1e-4);
+        // Below the horizon (unless stretched)
+        let below = Vector3::new(1.0, -0.1, 0.0);
+        assert_eq!(sky.eval(below).r, 0.0);
+        let sky = SkyModel::new(&dataset, 3.5, albedo, sun, 2.0, 2.0).unwrap();
+        assert!(sky.eval(below).r > 0.0);
+
+        assert!(SkyModel::new(&dataset, 11.0, albedo, sun, 1.0, 1.0).is_err());
+        assert!(SkyModel::new(&dataset, 3.0, albedo, sun, 2.5, 1.0).is_err());
+        assert!(SkyDataset::parse("double datasetRGB1[] = { 1.0, 2.0 };").is_err());
+    }
+
+    #[test]
+    fn sun_model() {
+        // Redder and darker toward the horizon or with more aerosols
+        let zenith = sun_radiance(0.0, 3.0);
+        let horizon = sun_radiance(85f32.to_radians(), 3.0);
+        let turbid = sun_radiance(0.0, 8.0);
+        assert!(zenith.g > horizon.g && zenith.g > turbid.g);
+        assert!(horizon.r / horizon.b > zenith.r / zenith.b);
+        assert_eq!(sun_radiance(100f32.to_radians(), 3.0).g, 0.0);
+
+        // Larger disk, same power
+        let d = Vector3::new(0.0, 1.0, 1.0);
+        let sun = SunModel::new(3.0, d, 1.0, 1.0);
+        let large = SunModel::new(3.0, d, 1.0, 4.0);
+        assert!((sun.irradiance().g - large.irradiance().g).abs() < 1e-3 * sun.irradiance().g);
+        assert!((large.eval(d).g * 16.0 - sun.eval(d).g).abs() < 1e-2 * sun.eval(d).g);
+        assert_eq!(sun.eval(Vector3::new(0.0, 1.0, 0.0)).g, 0.0);
+    }
+
+    #[test]
+    fn bake_sun() {
+        let direction = Vector3::new(0.3, 0.5, -0.2);
+        let emitter = Emitter::SunSky {
+            to_world: crate::Transform(cgmath::Matrix4::from_scale(1.0)),
+            turbidity: 3.0,
+            resolution: 256,
+            sampling_weight: 1.0,
+            sun_direction: SunDirection::Vector(direction),
+            sun: Some(SunEmitterParam {
+                scale: 1.0,
+                radius_scale: 8.0,
+            }),
+            sky: None,
+        };
+        let image = bake(&emitter, None).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (256, 128, 3));
+
+        // Power of the sun and position of the brightest pixel
+        let (mut power, mut brightest) = (0.0, (0, 0, 0.0));
+        for y in 0..image.height {
+            let theta = (y as f64 + 0.5) * PI / image.height as f64;
+            let solid_angle = (2.0 * PI / image.width as f64) * (PI / image.height as f64);
+            for x in 0..image.width {
+                let v = image.pixel(x, y)[1];
+                power += v as f64 * solid_angle * theta.sin();
+                if v > brightest.2 {
+                    brightest = (x, y, v);
+                }
+            }
+        }
+        let sun = SunModel::new(3.0, direction, 1.0, 8.0);
+        let expected = sun.irradiance().g as f64;
+        assert!((power - expected).abs() < 0.02 * expected);
+        let (x, y, _) = brightest;
+        let d = to_sphere(
+            (y as f64 + 0.5) * PI / image.height as f64,
+            (x as f64 + 0.5) * 2.0 * PI / image.width as f64,
+        );
+        assert!(sun.is_inside(d));
+
+        // The sky needs the dataset
+        let emitter = Emitter::SunSky {
+            to_world: crate::Transform(cgmath::Matrix4::from_scale(1.0)),
+            turbidity: 3.0,
+            resolution: 64,
+            sampling_weight: 1.0,
+            sun_direction: SunDirection::Vector(direction),
+            sun: None,
+            sky: Some(crate::SkyEmitterParam {
+                scale: 1.0,
+                stretch: 1.0,
+                albedo: crate::Spectrum::from_f32(0.15),
+            }),
+        };
+        assert!(bake(&emitter, None).is_err());
+        let image = bake(&emitter, Some(&dataset())).unwrap();
+        assert!(image.pixel(0, 0)[0] > 0.0);
+        assert_eq!(image.pixel(0, 31)[0], 0.0);
+    }
+}
@@ -0,0 +1,456 @@
+//! RGB to spectrum upsampling.
+//!
+//! Mitsuba scenes mostly describe colors as RGB triplets. Spectral renderers
+//! need smooth spectra instead. This module implements the approach of
+//! Jakob and Hanika ("A Low-Dimensional Function Space for Efficient Spectral
+//! Upsampling", 2019): each RGB reflectance is represented by a sigmoid of a
+//! quadratic polynomial in wavelength. The polynomial coefficients are found
+//! by a Gauss-Newton optimization in CIELAB space. A table of coefficients
+//! over the RGB cube is precomputed by the build script for fast lookups.
+use crate::{Error, Result, Spectrum, RGB};
+
+include!("rgb2spec_opt.rs");
+
+/// Resolution of the precomputed table (see `build.rs`)
+const RGB2SPEC_RES: usize = 32;
+
+/// Smooth spectrum `S(λ) = sigmoid(c0 λ² + c1 λ + c2)` (λ in nm).
+///
+/// Values are always inside [0, 1], which makes this representation
+/// suitable for reflectances.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SigmoidPolynomial {
+    pub c0: f32,
+    pub c1: f32,
+    pub c2: f32,
+}
+impl SigmoidPolynomial {
+    /// Constant spectrum (`v` is clamped to [0, 1])
+    pub fn constant(v: f32) -> Self {
+        let v = v.clamp(0.0, 1.0) as f64;
+        let c2 = if v <= 0.0 {
+            f64::NEG_INFINITY
+        } else if v >= 1.0 {
+            f64::INFINITY
+        } else {
+            (v - 0.5) / (v * (1.0 - v)).sqrt()
+        };
+        Self {
+            c0: 0.0,
+            c1: 0.0,
+            c2: c2 as f32,
+        }
+    }
+
+    /// Optimize the coefficients for a given linear sRGB reflectance.
+    ///
+    /// The components are clamped to [0, 1]. This is slower than a table
+    /// lookup but exact, which is fine for the few constant values of a scene.
+    pub fn fit(rgb: &RGB) -> Self {
+        let target = [
+            rgb.r.clamp(0.0, 1.0) as f64,
+            rgb.g.clamp(0.0, 1.0) as f64,
+            rgb.b.clamp(0.0, 1.0) as f64,
+        ];
+        if target[0] == target[1] && target[1] == target[2] {
+            return Self::constant(target[0] as f32);
+        }
+
+        let mut coeffs = [0.0; 3];
+        optimize(target, &mut coeffs);
+        let [c0, c1, c2] = denormalize(coeffs);
+        Self { c0, c1, c2 }
+    }
+
+    /// Evaluate the spectrum at the wavelength `lambda` (nm)
+    pub fn eval(&self, lambda: f32) -> f32 {
+        let x = (self.c0 * lambda + self.c1) * lambda + self.c2;
+        if x.is_infinite() {
+            return if x > 0.0 { 1.0 } else { 0.0 };
+        }
+        0.5 + 0.5 * x / (1.0 + x * x).sqrt()
+    }
+}
+
+/// Precomputed table of sigmoid coefficients over the RGB cube.
+///
+/// The table follows the layout of Jakob and Hanika: the largest RGB
+/// component selects one of three blocks, and each block is parameterized by
+/// the largest component (non-uniformly) and the two other components
+/// divided by it. Coefficients are trilinearly interpolated at lookup.
+pub struct RGBToSpectrumTable {
+    resolution: usize,
+    scale: Vec<f32>,
+    data: Vec<[f32; 3]>,
+}
+impl RGBToSpectrumTable {
+    /// Table for linear sRGB, precomputed by the build script
+    pub fn srgb() -> &'static Self {
+        &RGB2SPEC_SRGB
+    }
+
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    /// Lookup the sigmoid coefficients for a linear sRGB reflectance
+    /// (components are clamped to [0, 1])
+    pub fn fetch(&self, rgb: &RGB) -> SigmoidPolynomial {
+        let rgb = [
+            rgb.r.clamp(0.0, 1.0),
+            rgb.g.clamp(0.0, 1.0),
+            rgb.b.clamp(0.0, 1.0),
+        ];
+        if rgb[0] == rgb[1] && rgb[1] == rgb[2] {
+            return SigmoidPolynomial::constant(rgb[0]);
+        }
+
+        let res = self.resolution;
+        let mut i = 0;
+        for j in 1..3 {
+            if rgb[j] >= rgb[i] {
+                i = j;
+            }
+        }
+        let z = rgb[i];
+        let s = (res - 1) as f32 / z;
+        let x = rgb[(i + 1) % 3] * s;
+        let y = rgb[(i + 2) % 3] * s;
+
+        let xi = (x as usize).min(res - 2);
+        let yi = (y as usize).min(res - 2);
+        let zi = match self.scale.iter().position(|&v| v > z) {
+            Some(p) => p.max(1) - 1,
+            None => res - 2,
+        }
+        .min(res - 2);
+        let dx = x - xi as f32;
+        let dy = y - yi as f32;
+        let dz = (z - self.scale[zi]) / (self.scale[zi + 1] - self.scale[zi]);
+
+        let idx =
+            |dz: usize, dy: usize, dx: usize| ((i * res + zi + dz) * res + yi + dy) * res + xi + dx;
+        let lerp = |t: f32, a: [f32; 3], b: [f32; 3]| {
+            [
+                (1.0 - t) * a[0] + t * b[0],
+                (1.0 - t) * a[1] + t * b[1],
+                (1.0 - t) * a[2] + t * b[2],
+            ]
+        };
+        let d = &self.data;
+        let c = lerp(
+            dz,
+            lerp(
+                dy,
+                lerp(dx, d[idx(0, 0, 0)], d[idx(0, 0, 1)]),
+                lerp(dx, d[idx(0, 1, 0)], d[idx(0, 1, 1)]),
+            ),
+            lerp(
+                dy,
+                lerp(dx, d[idx(1, 0, 0)], d[idx(1, 0, 1)]),
+                lerp(dx, d[idx(1, 1, 0)], d[idx(1, 1, 1)]),
+            ),
+        );
+        SigmoidPolynomial {
+            c0: c[0],
+            c1: c[1],
+            c2: c[2],
+        }
+    }
+}
+
+lazy_static! {
+    static ref RGB2SPEC_SRGB: RGBToSpectrumTable = {
+        let bytes = include_bytes!(concat!(env!("OUT_DIR"), "/rgb2spec.bin"));
+        let values = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect::<Vec<_>>();
+        let (scale, data) = values.split_at(RGB2SPEC_RES);
+        assert_eq!(data.len(), 9 * RGB2SPEC_RES * RGB2SPEC_RES * RGB2SPEC_RES);
+        RGBToSpectrumTable {
+            resolution: RGB2SPEC_RES,
+            scale: scale.to_vec(),
+            data: data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+        }
+    };
+}
+
+/// How the RGB values need to be interpreted when upsampled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpectrumKind {
+    /// Values inside [0, 1] (e.g., albedo, reflectance)
+    Reflectance,
+    /// Positive values without upper bound (e.g., scattering coefficients)
+    Unbounded,
+    /// Emission (the spectrum is multiplied by the D65 illuminant)
+    Illuminant,
+}
+
+/// Spectrum that can be evaluated at arbitrary wavelengths
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpectralDistribution {
+    /// Tabulated values from the scene (`"400:0.1, 500:0.2, ..."`),
+    /// linearly interpolated and zero outside the range.
+    Sampled {
+        wavelengths: Vec<f32>,
+        values: Vec<f32>,
+    },
+    /// Upsampled RGB value: `scale * poly(λ)` (times the D65 illuminant
+    /// if `illuminant` is true)
+    RGB {
+        scale: f32,
+        poly: SigmoidPolynomial,
+        illuminant: bool,
+    },
+}
+impl SpectralDistribution {
+    /// Build the distribution of a RGB value, optimizing the coefficients
+    pub fn from_rgb(rgb: &RGB, kind: SpectrumKind) -> Self {
+        Self::from_rgb_with(rgb, kind, SigmoidPolynomial::fit)
+    }
+
+    /// Build the distribution of a RGB value, fetching the coefficients
+    /// from a precomputed table
+    pub fn from_rgb_table(rgb: &RGB, kind: SpectrumKind, table: &RGBToSpectrumTable) -> Self {
+        Self::from_rgb_with(rgb, kind, |v| table.fetch(v))
+    }
+
+    fn from_rgb_with<F: Fn(&RGB) -> SigmoidPolynomial>(
+        rgb: &RGB,
+        kind: SpectrumKind,
+        f: F,
+    ) -> Self {
+        match kind {
+            SpectrumKind::Reflectance => SpectralDistribution::RGB {
+                scale: 1.0,
+                poly: f(rgb),
+                illuminant: false,
+            },
+            SpectrumKind::Unbounded | SpectrumKind::Illuminant => {
+                // Rescale to keep the values inside the representable range
+                let m = rgb.r.max(rgb.g).max(rgb.b);
+                let scale = 2.0 * m;
+                let poly = if scale > 0.0 {
+                    f(&RGB {
+                        r: rgb.r / scale,
+                        g: rgb.g / scale,
+                        b: rgb.b / scale,
+                    })
+                } else {
+                    SigmoidPolynomial::constant(0.0)
+                };
+                SpectralDistribution::RGB {
+                    scale,
+                    poly,
+                    illuminant: kind == SpectrumKind::Illuminant,
+                }
+            }
+        }
+    }
+
+    /// Evaluate the spectrum at the wavelength `lambda` (nm)
+    pub fn eval(&self, lambda: f32) -> f32 {
+        match self {
+            SpectralDistribution::Sampled {
+                wavelengths,
+                values,
+            } => {
+                if wavelengths.is_empty()
+                    || lambda < wavelengths[0]
+                    || lambda > wavelengths[wavelengths.len() - 1]
+                {
+                    return 0.0;
+                }
+                let i = wavelengths
+                    .iter()
+                    .position(|&w| w >= lambda)
+                    .unwrap_or(wavelengths.len() - 1);
+                if i == 0 || wavelengths[i] == lambda {
+                    return values[i];
+                }
+                let t = (lambda - wavelengths[i - 1]) / (wavelengths[i] - wavelengths[i - 1]);
+                (1.0 - t) * values[i - 1] + t * values[i]
+            }
+            SpectralDistribution::RGB {
+                scale,
+                poly,
+                illuminant,
+            } => {
+                let v = scale * poly.eval(lambda);
+                if *illuminant {
+                    v * d65(lambda) * TABLES.d65_norm as f32
+                } else {
+                    v
+                }
+            }
+        }
+    }
+}
+
+impl Spectrum {
+    /// Convert the spectrum into a distribution that can be evaluated at
+    /// any wavelength. RGB values are upsampled (see `SpectrumKind`), and
+    /// true spectral values (`"λ:v, λ:v, ..."`) are used as they are.
+    pub fn to_spectral(&self, kind: SpectrumKind) -> Result<SpectralDistribution> {
+        if self.value.contains(':') {
+            let mut wavelengths = vec![];
+            let mut values = vec![];
+            for entry in self.value.split(',') {
+                let mut it = entry.split(':');
+                let (l, v) = match (it.next(), it.next(), it.next()) {
+                    (Some(l), Some(v), None) => (l.trim().parse::<f32>(), v.trim().parse::<f32>()),
+                    _ => return Err(Error::RGB("Wrong spectral entry", self.value.clone())),
+                };
+                match (l, v) {
+                    (Ok(l), Ok(v)) => {
+                        if wavelengths.last().is_some_and(|&last| last >= l) {
+                            return Err(Error::RGB(
+                                "Wavelengths need to be increasing",
+                                self.value.clone(),
+                            ));
+                        }
+                        wavelengths.push(l);
+                        values.push(v);
+                    }
+                    _ => return Err(Error::RGB("Wrong spectral entry", self.value.clone())),
+                }
+            }
+            Ok(SpectralDistribution::Sampled {
+                wavelengths,
+                values,
+            })
+        } else {
+            let rgb = self.clone().as_rgb()?;
+            Ok(SpectralDistribution::from_rgb(&rgb, kind))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Integrate a spectrum under D65 and convert it back to linear sRGB
+    fn to_rgb<F: Fn(f32) -> f32>(f: F) -> [f64; 3] {
+        let t = &*TABLES;
+        let mut rgb = [0.0; 3];
+        for (l, w) in t.lambda.iter().zip(t.rgb.iter()) {
+            let s = f(*l as f32) as f64;
+            for k in 0..3 {
+                rgb[k] += w[k] * s;
+            }
+        }
+        rgb
+    }
+
+    #[test]
+    fn roundtrip_reflectance() {
+        for c in &[[0.8, 0.2, 0.1], [0.1, 0.5, 0.3], [0.05, 0.05, 0.9]] {
+            let poly = SigmoidPolynomial::fit(&RGB {
+                r: c[0],
+                g: c[1],
+                b: c[2],
+            });
+            let rgb = to_rgb(|l| poly.eval(l));
+            for k in 0..3 {
+                assert!((rgb[k] - c[k] as f64).abs() < 0.01, "{:?} {:?}", rgb, c);
+            }
+        }
+    }
+
+    #[test]
+    fn constant_spectrum() {
+        let s = Spectrum::from_f32(0.3)
+            .to_spectral(SpectrumKind::Reflectance)
+            .unwrap();
+        for l in &[400.0, 550.0, 700.0] {
+            assert!((s.eval(*l) - 0.3).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn sampled_spectrum() {
+        let s = Spectrum {
+            value: "400:0.2, 500:0.4, 600:0.8".to_string(),
+        };
+        let s = s.to_spectral(SpectrumKind::Reflectance).unwrap();
+        assert!((s.eval(450.0) - 0.3).abs() < 1e-5);
+        assert!((s.eval(600.0) - 0.8).abs() < 1e-5);
+        assert_eq!(s.eval(300.0), 0.0);
+    }
+
+    // Convert an emission spectrum back to linear sRGB (unit luminance for
+    // the D65 illuminant)
+    fn emission_to_rgb<F: Fn(f32) -> f32>(f: F) -> [f64; 3] {
+        let mut rgb = [0.0; 3];
+        let mut y = 0.0;
+        for l in &TABLES.lambda {
+            let cmf = cie_1931(*l as f32);
+            let s = f(*l as f32) as f64;
+            y += cmf[1] as f64;
+            for k in 0..3 {
+                for j in 0..3 {
+                    rgb[k] += XYZ_TO_SRGB[k][j] * cmf[j] as f64 * s;
+                }
+            }
+        }
+        rgb.map(|v| v / y)
+    }
+
+    const SATURATED: [[f32; 3]; 6] = [
+        [0.9, 0.05, 0.05],
+        [0.05, 0.8, 0.05],
+        [0.05, 0.05, 0.9],
+        [0.9, 0.9, 0.05],
+        [0.05, 0.7, 0.7],
+        [0.8, 0.05, 0.8],
+    ];
+
+    #[test]
+    fn table_lookup() {
+        let table = RGBToSpectrumTable::srgb();
+        assert_eq!(table.resolution(), RGB2SPEC_RES);
+        for c in SATURATED
+            .iter()
+            .chain(&[[0.6, 0.3, 0.2], [0.02, 0.01, 0.03]])
+        {
+            let poly = table.fetch(&RGB {
+                r: c[0],
+                g: c[1],
+                b: c[2],
+            });
+            let rgb = to_rgb(|l| poly.eval(l));
+            for k in 0..3 {
+                assert!((rgb[k] - c[k] as f64).abs() < 0.02, "{:?} {:?}", rgb, c);
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_saturated() {
+        for c in &SATURATED {
+            let rgb = RGB {
+                r: c[0],
+                g: c[1],
+                b: c[2],
+            };
+            let reflectance = SpectralDistribution::from_rgb(&rgb, SpectrumKind::Reflectance);
+            let emission = SpectralDistribution::from_rgb_table(
+                &RGB {
+                    r: 10.0 * c[0],
+                    g: 10.0 * c[1],
+                    b: 10.0 * c[2],
+                },
+                SpectrumKind::Illuminant,
+                RGBToSpectrumTable::srgb(),
+            );
+            let r = to_rgb(|l| reflectance.eval(l));
+            let e = emission_to_rgb(|l| emission.eval(l));
+            for k in 0..3 {
+                assert!((r[k] - c[k] as f64).abs() < 0.01, "{:?} {:?}", r, c);
+                assert!((e[k] - 10.0 * c[k] as f64).abs() < 0.2, "{:?} {:?}", e, c);
+            }
+        }
+    }
+}
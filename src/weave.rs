@@ -0,0 +1,472 @@
+//! Weave pattern files (`.wv`) used by the `irawan` cloth BSDF.
+//!
+//! A weave file describes a tile of woven yarns (Irawan and Marschner 2012):
+//! ```text
+//! weave {
+//!     name = "Polyester", tileWidth = 2, tileHeight = 2,
+//!     alpha = 0.015, beta = 4, ss = 0.5, hWidth = 0.5,
+//!     warpArea = 8, weftArea = 16,
+//!     pattern { 1, 2, 2, 1 },
+//!     yarn { type = warp, umax = 38, kd = $warp_kd, ks = $warp_ks },
+//!     yarn { type = weft, umax = 38, kd = $weft_kd, ks = $weft_ks }
+//! }
+//! ```
+//! Values starting with `$` refer to parameters of the BSDF. Comments
+//! (`#`, `//` and `/* */`) are ignored.
+use crate::{Error, Result, Spectrum};
+use std::collections::HashMap;
+
+/// Parameter of the `irawan` BSDF (usable as `$name` inside the weave file)
+#[derive(Debug, Clone, PartialEq)]
+pub enum WeaveParameter {
+    Float(f32),
+    Spectrum(Spectrum),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YarnType {
+    Warp,
+    Weft,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Yarn {
+    pub yarn_type: YarnType, // warp
+    pub psi: f32,            // 0 (fiber twist angle, degrees)
+    pub umax: f32,           // 0 (maximum inclination angle, degrees)
+    pub kappa: f32,          // 0 (spine curvature)
+    pub width: f32,          // 0
+    pub length: f32,         // 0
+    pub center_u: f32,       // 0
+    pub center_v: f32,       // 0
+    pub kd: Spectrum,        // s(0.0)
+    pub ks: Spectrum,        // s(0.0)
+}
+
+impl Default for Yarn {
+    fn default() -> Self {
+        Yarn {
+            yarn_type: YarnType::Warp,
+            psi: 0.0,
+            umax: 0.0,
+            kappa: 0.0,
+            width: 0.0,
+            length: 0.0,
+            center_u: 0.0,
+            center_v: 0.0,
+            kd: Spectrum::from_f32(0.0),
+            ks: Spectrum::from_f32(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WeavePattern {
+    pub name: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    // Uniform and forward scattering
+    pub alpha: f32,
+    pub beta: f32,
+    // Filament smoothing
+    pub ss: f32,
+    // Highlight width
+    pub h_width: f32,
+    // Combined area taken up by the warp and weft
+    pub warp_area: f32,
+    pub weft_area: f32,
+    // Noise
+    pub d_warp_umax_over_d_warp: f32,
+    pub d_warp_umax_over_d_weft: f32,
+    pub d_weft_umax_over_d_warp: f32,
+    pub d_weft_umax_over_d_weft: f32,
+    pub fineness: f32,
+    pub period: f32,
+    /// Yarn index (starting at 1) for each cell of the tile (row major)
+    pub pattern: Vec<u32>,
+    pub yarns: Vec<Yarn>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f32),
+    Str(String),
+    Symbol(char),
+}
+
+fn tokenize(content: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let chars = content.chars().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' || (c == '/' && chars.get(i + 1) == Some(&'/')) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(weave_error("unterminated string"));
+            }
+            tokens.push(Token::Str(chars[start..i].iter().collect()));
+            i += 1;
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+            let start = i;
+            i += 1;
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || chars[i] == '.'
+                    || chars[i] == 'e'
+                    || chars[i] == 'E'
+                    || ((chars[i] == '-' || chars[i] == '+')
+                        && (chars[i - 1] == 'e' || chars[i - 1] == 'E')))
+            {
+                i += 1;
+            }
+            let s = chars[start..i].iter().collect::<String>();
+            let v = s
+                .parse::<f32>()
+                .map_err(|_| weave_error(&format!("invalid number {:?}", s)))?;
+            tokens.push(Token::Number(v));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "{}=,$".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            return Err(weave_error(&format!("unexpected character {:?}", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn weave_error(reason: &str) -> Error {
+    Error::InvalidParameter("weave".to_string(), reason.to_string())
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    parameters: &'a HashMap<String, WeaveParameter>,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<Token> {
+        let t = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| weave_error("unexpected end of file"))?;
+        self.pos += 1;
+        Ok(t)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        match self.next()? {
+            Token::Symbol(v) if v == c => Ok(()),
+            t => Err(weave_error(&format!("expected {:?} (found {:?})", c, t))),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Ident(v) => Ok(v),
+            t => Err(weave_error(&format!("expected identifier (found {:?})", t))),
+        }
+    }
+
+    // Iterate over the comma separated entries of a block until '}'
+    fn block_next(&mut self, first: bool) -> Result<bool> {
+        if !first && self.peek() == Some(&Token::Symbol(',')) {
+            self.pos += 1;
+        }
+        if self.peek() == Some(&Token::Symbol('}')) {
+            self.pos += 1;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn variable(&mut self) -> Result<&'a WeaveParameter> {
+        let name = self.ident()?;
+        self.parameters
+            .get(&name)
+            .ok_or_else(|| weave_error(&format!("missing BSDF parameter ${}", name)))
+    }
+
+    fn float(&mut self) -> Result<f32> {
+        match self.next()? {
+            Token::Number(v) => Ok(v),
+            Token::Symbol('$') => match self.variable()? {
+                WeaveParameter::Float(v) => Ok(*v),
+                v => Err(weave_error(&format!("expected a float (found {:?})", v))),
+            },
+            t => Err(weave_error(&format!("expected a float (found {:?})", t))),
+        }
+    }
+
+    fn spectrum(&mut self) -> Result<Spectrum> {
+        match self.next()? {
+            Token::Number(v) => Ok(Spectrum::from_f32(v)),
+            Token::Symbol('$') => match self.variable()? {
+                WeaveParameter::Float(v) => Ok(Spectrum::from_f32(*v)),
+                WeaveParameter::Spectrum(v) => Ok(v.clone()),
+            },
+            t => Err(weave_error(&format!("expected a spectrum (found {:?})", t))),
+        }
+    }
+
+    fn yarn(&mut self) -> Result<Yarn> {
+        let mut yarn = Yarn::default();
+        self.expect('{')?;
+        let mut first = true;
+        while self.block_next(first)? {
+            first = false;
+            let key = self.ident()?;
+            self.expect('=')?;
+            match key.as_str() {
+                "type" => {
+                    yarn.yarn_type = match self.ident()?.as_str() {
+                        "warp" => YarnType::Warp,
+                        "weft" => YarnType::Weft,
+                        v => return Err(weave_error(&format!("unknown yarn type {:?}", v))),
+                    }
+                }
+                "psi" => yarn.psi = self.float()?,
+                "umax" => yarn.umax = self.float()?,
+                "kappa" => yarn.kappa = self.float()?,
+                "width" => yarn.width = self.float()?,
+                "length" => yarn.length = self.float()?,
+                "centerU" => yarn.center_u = self.float()?,
+                "centerV" => yarn.center_v = self.float()?,
+                "kd" => yarn.kd = self.spectrum()?,
+                "ks" => yarn.ks = self.spectrum()?,
+                _ => return Err(weave_error(&format!("unknown yarn parameter {:?}", key))),
+            }
+        }
+        Ok(yarn)
+    }
+
+    fn pattern(&mut self) -> Result<Vec<u32>> {
+        let mut pattern = vec![];
+        self.expect('{')?;
+        let mut first = true;
+        while self.block_next(first)? {
+            first = false;
+            match self.next()? {
+                Token::Number(v) if v >= 0.0 && v.fract() == 0.0 => pattern.push(v as u32),
+                t => return Err(weave_error(&format!("invalid pattern entry {:?}", t))),
+            }
+        }
+        Ok(pattern)
+    }
+
+    fn weave(&mut self) -> Result<WeavePattern> {
+        let mut weave = WeavePattern::default();
+        match self.ident()?.as_str() {
+            "weave" => {}
+            v => return Err(weave_error(&format!("expected weave (found {:?})", v))),
+        }
+        self.expect('{')?;
+        let mut first = true;
+        while self.block_next(first)? {
+            first = false;
+            let key = self.ident()?;
+            match key.as_str() {
+                "pattern" => weave.pattern = self.pattern()?,
+                "yarn" => weave.yarns.push(self.yarn()?),
+                _ => {
+                    self.expect('=')?;
+                    match key.as_str() {
+                        "name" => {
+                            weave.name = match self.next()? {
+                                Token::Str(v) | Token::Ident(v) => v,
+                                t => return Err(weave_error(&format!("invalid name {:?}", t))),
+                            }
+                        }
+                        "tileWidth" => weave.tile_width = self.float()? as u32,
+                        "tileHeight" => weave.tile_height = self.float()? as u32,
+                        "alpha" => weave.alpha = self.float()?,
+                        "beta" => weave.beta = self.float()?,
+                        "ss" => weave.ss = self.float()?,
+                        "hWidth" => weave.h_width = self.float()?,
+                        "warpArea" => weave.warp_area = self.float()?,
+                        "weftArea" => weave.weft_area = self.float()?,
+                        "dWarpUmaxOverDWarp" => weave.d_warp_umax_over_d_warp = self.float()?,
+                        "dWarpUmaxOverDWeft" => weave.d_warp_umax_over_d_weft = self.float()?,
+                        "dWeftUmaxOverDWarp" => weave.d_weft_umax_over_d_warp = self.float()?,
+                        "dWeftUmaxOverDWeft" => weave.d_weft_umax_over_d_weft = self.float()?,
+                        "fineness" => weave.fineness = self.float()?,
+                        "period" => weave.period = self.float()?,
+                        _ => return Err(weave_error(&format!("unknown parameter {:?}", key))),
+                    }
+                }
+            }
+        }
+        if self.pos != self.tokens.len() {
+            return Err(weave_error("unexpected content after the weave block"));
+        }
+        Ok(weave)
+    }
+}
+
+/// Parse the content of a weave file. `parameters` are the parameters
+/// of the BSDF used to resolve the `$name` values.
+pub fn parse_weave(
+    content: &str,
+    parameters: &HashMap<String, WeaveParameter>,
+) -> Result<WeavePattern> {
+    let mut parser = Parser {
+        tokens: tokenize(content)?,
+        pos: 0,
+        parameters,
+    };
+    let weave = parser.weave()?;
+
+    // Check the consistency of the pattern
+    if weave.pattern.len() != (weave.tile_width * weave.tile_height) as usize {
+        return Err(weave_error(&format!(
+            "pattern has {} entries (expected {}x{})",
+            weave.pattern.len(),
+            weave.tile_width,
+            weave.tile_height
+        )));
+    }
+    if let Some(v) = weave
+        .pattern
+        .iter()
+        .find(|&&v| v == 0 || v as usize > weave.yarns.len())
+    {
+        return Err(weave_error(&format!(
+            "pattern refers to yarn {} ({} yarns defined)",
+            v,
+            weave.yarns.len()
+        )));
+    }
+    Ok(weave)
+}
+
+/// Read the weave file of an `irawan` BSDF (`filename` relative to `wk`)
+pub fn read_weave(
+    filename: &str,
+    parameters: &HashMap<String, WeaveParameter>,
+    wk: &std::path::Path,
+) -> Result<WeavePattern> {
+    let filename = wk.join(std::path::Path::new(filename));
+    let content = std::fs::read_to_string(filename).map_err(|e| Error::Other(Box::new(e)))?;
+    parse_weave(&content, parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLYESTER: &str = r#"
+# Polyester lining cloth
+weave {
+    name = "Polyester lining cloth",
+
+    /* Tile size of the weave pattern */
+    tileWidth = 2,
+    tileHeight = 2,
+
+    alpha = 0.015,
+    beta = 4,
+    ss = 0.5,
+    hWidth = 0.5,
+    warpArea = 8,
+    weftArea = 16,
+
+    dWarpUmaxOverDWarp = 0,
+    dWarpUmaxOverDWeft = 0,
+    dWeftUmaxOverDWarp = 0,
+    dWeftUmaxOverDWeft = 0,
+    fineness = 0,
+    period = 0,
+
+    pattern {
+        1, 2,
+        2, 1
+    },
+
+    yarn {
+        type = warp,
+        psi = 0, umax = 38, kappa = 0,
+        width = 1, length = 1,
+        centerU = 0.5, centerV = 0.5,
+        kd = $warp_kd, ks = $warp_ks
+    },
+
+    yarn {
+        type = weft,
+        psi = 0, umax = $weft_umax, kappa = -0.5e-1,
+        width = 1, length = 1,
+        centerU = 0.5, centerV = 0.5,
+        kd = 0.1, ks = $warp_ks
+    }
+}
+"#;
+
+    fn parameters() -> HashMap<String, WeaveParameter> {
+        let mut parameters = HashMap::new();
+        parameters.insert(
+            "warp_kd".to_string(),
+            WeaveParameter::Spectrum(Spectrum::from_rgb("0.1, 0.2, 0.3".to_string())),
+        );
+        parameters.insert(
+            "warp_ks".to_string(),
+            WeaveParameter::Spectrum(Spectrum::from_f32(0.4)),
+        );
+        parameters.insert("weft_umax".to_string(), WeaveParameter::Float(24.0));
+        parameters
+    }
+
+    #[test]
+    fn polyester() {
+        let weave = parse_weave(POLYESTER, &parameters()).unwrap();
+        assert_eq!(weave.name, "Polyester lining cloth");
+        assert_eq!((weave.tile_width, weave.tile_height), (2, 2));
+        assert_eq!((weave.alpha, weave.warp_area), (0.015, 8.0));
+        assert_eq!(weave.pattern, vec![1, 2, 2, 1]);
+        assert_eq!(weave.yarns.len(), 2);
+        assert_eq!(weave.yarns[0].kd.value, "0.1, 0.2, 0.3");
+        assert_eq!(weave.yarns[1].yarn_type, YarnType::Weft);
+        assert_eq!((weave.yarns[1].umax, weave.yarns[1].kappa), (24.0, -0.05));
+        assert_eq!(weave.yarns[1].kd.value, "0.1");
+    }
+
+    #[test]
+    fn invalid_weave() {
+        // Missing BSDF parameter
+        assert!(parse_weave(POLYESTER, &HashMap::new()).is_err());
+        // Pattern refers to an unknown yarn
+        let content =
+            "weave { tileWidth = 1, tileHeight = 1, pattern { 2 }, yarn { type = warp } }";
+        assert!(parse_weave(content, &HashMap::new()).is_err());
+        let content =
+            "weave { tileWidth = 1, tileHeight = 1, pattern { 1 }, yarn { type = warp } }";
+        assert!(parse_weave(content, &HashMap::new()).is_ok());
+    }
+}